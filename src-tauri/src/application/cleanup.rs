@@ -0,0 +1,34 @@
+//! Removes what connecting leaves behind on the system: the hosts entries
+//! and, if asked, the trusted certificate.
+
+use serde::{Deserialize, Serialize};
+
+use crate::infrastructure::{hosts, tls};
+
+/// What [`cleanup_system`] actually removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub hosts_removed: bool,
+    pub certificate_removed: bool,
+}
+
+/// Removes the hosts entries, and the certificate too with
+/// `remove_certificate`. Only meant for when the proxy is stopped.
+///
+/// Safe to call when nothing is installed; the report is then all `false`.
+pub fn cleanup_system(remove_certificate: bool) -> Result<CleanupReport, String> {
+    let hosts_removed = hosts::remove_hosts_entries()
+        .map_err(|e| format!("Failed to remove hosts entries: {}", e))?;
+
+    let certificate_removed = if remove_certificate {
+        tls::uninstall_certificate()
+            .map_err(|e| format!("Failed to remove the certificate: {}", e))?
+    } else {
+        false
+    };
+
+    Ok(CleanupReport {
+        hosts_removed,
+        certificate_removed,
+    })
+}
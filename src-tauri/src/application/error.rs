@@ -0,0 +1,130 @@
+//! Errors from connecting, reported to the frontend with a stable code.
+
+use std::io;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+
+use crate::application::OsuPathError;
+use crate::infrastructure::port;
+
+/// Why starting the proxy or launching osu! failed.
+///
+/// Sent to the frontend as `{ "code": ..., "message": ... }`. The code is
+/// stable for the UI to branch on; the message is for showing to the user.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProxyError {
+    /// Another process is listening on the proxy's port.
+    #[error(
+        "Port {port} is already in use{}. Please close it and try again.",
+        .owner.as_ref().map(|p| format!(" by {}", p)).unwrap_or_default()
+    )]
+    PortInUse { port: u16, owner: Option<String> },
+    /// The OS refused to let the proxy bind its port.
+    #[error("Permission denied binding to port {port}. Try running as Administrator.")]
+    PermissionDenied { port: u16 },
+    /// The proxy's certificate couldn't be loaded or generated.
+    #[error("Failed to load the proxy certificate: {0}")]
+    CertError(String),
+    /// The proxy didn't report being ready in time.
+    #[error("Failed to start proxy: port binding timeout")]
+    StartTimeout,
+    #[error(transparent)]
+    OsuNotFound(#[from] OsuPathError),
+    /// osu! was found but couldn't be started.
+    #[error("{0}")]
+    LaunchFailed(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ProxyError {
+    /// Classifies a failure to bind `port`.
+    pub fn from_bind_error(port: u16, e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::AddrInUse => Self::PortInUse {
+                port,
+                owner: port::port_owner(port),
+            },
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied { port },
+            _ => Self::Other(format!("Failed to bind to port {}: {}", port, e)),
+        }
+    }
+
+    /// Stable identifier for the kind of failure.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::PortInUse { .. } => "port_in_use",
+            Self::PermissionDenied { .. } => "permission_denied",
+            Self::CertError(_) => "cert_error",
+            Self::StartTimeout => "start_timeout",
+            Self::OsuNotFound(_) => "osu_not_found",
+            Self::LaunchFailed(_) => "launch_failed",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
+impl Serialize for ProxyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("ProxyError", 2)?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_errors_are_classified() {
+        let in_use = ProxyError::from_bind_error(443, &io::ErrorKind::AddrInUse.into());
+        assert!(matches!(in_use, ProxyError::PortInUse { port: 443, .. }));
+
+        let denied = ProxyError::from_bind_error(443, &io::ErrorKind::PermissionDenied.into());
+        assert_eq!(denied, ProxyError::PermissionDenied { port: 443 });
+
+        let other = ProxyError::from_bind_error(443, &io::ErrorKind::AddrNotAvailable.into());
+        assert_eq!(other.code(), "other");
+    }
+
+    #[test]
+    fn test_port_in_use_names_owner() {
+        let err = ProxyError::PortInUse {
+            port: 443,
+            owner: Some("nginx.exe".to_string()),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Port 443 is already in use by nginx.exe. Please close it and try again."
+        );
+
+        let err = ProxyError::PortInUse {
+            port: 443,
+            owner: None,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Port 443 is already in use. Please close it and try again."
+        );
+    }
+
+    #[test]
+    fn test_missing_osu_maps_to_osu_not_found() {
+        let err = ProxyError::from(OsuPathError::NotFound);
+        assert_eq!(err.code(), "osu_not_found");
+        assert_eq!(err.to_string(), OsuPathError::NotFound.to_string());
+    }
+
+    #[test]
+    fn test_serializes_code_and_message() {
+        let json = serde_json::to_value(ProxyError::PermissionDenied { port: 80 }).unwrap();
+        assert_eq!(json["code"], "permission_denied");
+        assert_eq!(
+            json["message"],
+            "Permission denied binding to port 80. Try running as Administrator."
+        );
+    }
+}
@@ -1,7 +1,17 @@
+pub mod cleanup;
+pub mod diagnostics;
+pub mod error;
+pub mod monitor;
 pub mod osu;
+pub mod preflight;
 pub mod proxy;
 pub mod shortcut;
 
+pub use cleanup::*;
+pub use diagnostics::*;
+pub use error::*;
+pub use monitor::*;
 pub use osu::*;
+pub use preflight::*;
 pub use proxy::*;
 pub use shortcut::*;
@@ -0,0 +1,180 @@
+//! Keeps `AppState::osu_running` in sync with whether osu! is open.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use tokio::task::JoinHandle;
+
+use crate::application::StatusListener;
+use crate::domain::AppState;
+
+/// Consecutive polls osu! must be missing for before it counts as exited, so
+/// a quick restart doesn't trigger the exit handler.
+const EXIT_CONFIRMATION_POLLS: u32 = 2;
+
+/// Called once osu! has been confirmed to have exited.
+pub type OsuExitHandler = Arc<dyn Fn() + Send + Sync>;
+
+/// Polls for the osu! process and records the result in the app state.
+pub struct OsuMonitor {
+    state: Arc<RwLock<AppState>>,
+    listener: Option<StatusListener>,
+    exit_handler: Option<OsuExitHandler>,
+    /// Whether osu! was running the last time it was confirmed either way.
+    was_running: bool,
+    absent_polls: u32,
+}
+
+impl OsuMonitor {
+    pub fn new(state: Arc<RwLock<AppState>>, listener: Option<StatusListener>) -> Self {
+        Self {
+            state,
+            listener,
+            exit_handler: None,
+            was_running: false,
+            absent_polls: 0,
+        }
+    }
+
+    /// Registers a handler run when osu! goes from running to not running.
+    pub fn with_exit_handler(mut self, handler: Option<OsuExitHandler>) -> Self {
+        self.exit_handler = handler;
+        self
+    }
+
+    /// Records whether osu! is running, notifying the listener if that changed
+    /// and the exit handler once an exit is confirmed.
+    fn observe(&mut self, running: bool) {
+        if running {
+            self.was_running = true;
+            self.absent_polls = 0;
+        } else if self.was_running {
+            self.absent_polls += 1;
+            if self.absent_polls >= EXIT_CONFIRMATION_POLLS {
+                self.was_running = false;
+                if let Some(handler) = &self.exit_handler {
+                    handler();
+                }
+            }
+        }
+
+        let snapshot = {
+            let mut state = self.state.write();
+            if state.osu_running == running {
+                return;
+            }
+            state.osu_running = running;
+            state.clone()
+        };
+
+        tracing::info!("osu! {}", if running { "started" } else { "exited" });
+
+        if let Some(listener) = &self.listener {
+            listener(&snapshot);
+        }
+    }
+
+    /// Runs `check` every `interval` until the returned task is aborted.
+    ///
+    /// `check` is injectable so the monitor can be driven without a real
+    /// osu! process; in the app it's [`is_osu_running`](crate::application::is_osu_running).
+    pub fn spawn<F, Fut>(mut self, interval: Duration, check: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = bool> + Send,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                self.observe(check().await);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use parking_lot::Mutex;
+
+    async fn wait_for(state: &Arc<RwLock<AppState>>, running: bool) {
+        for _ in 0..100 {
+            if state.read().osu_running == running {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("osu_running never became {}", running);
+    }
+
+    fn counting_monitor() -> (OsuMonitor, Arc<AtomicUsize>) {
+        let exits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&exits);
+        let monitor = OsuMonitor::new(Arc::new(RwLock::new(AppState::default())), None)
+            .with_exit_handler(Some(Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })));
+        (monitor, exits)
+    }
+
+    #[test]
+    fn test_exit_handler_runs_once_after_confirmed_exit() {
+        let (mut monitor, exits) = counting_monitor();
+
+        for running in [false, true, true, false, false, false, false] {
+            monitor.observe(running);
+        }
+
+        assert_eq!(exits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_quick_restart_is_not_an_exit() {
+        let (mut monitor, exits) = counting_monitor();
+
+        for running in [true, false, true, false, true] {
+            monitor.observe(running);
+        }
+        assert_eq!(exits.load(Ordering::SeqCst), 0);
+
+        // Never seen running, so a missing process isn't an exit either
+        let (mut monitor, exits) = counting_monitor();
+        monitor.observe(false);
+        monitor.observe(false);
+        assert_eq!(exits.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_monitor_tracks_process_and_emits_on_change() {
+        let state = Arc::new(RwLock::new(AppState::default()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let listener: StatusListener =
+            Arc::new(move |state: &AppState| sink.lock().push(state.osu_running));
+
+        let running = Arc::new(AtomicBool::new(false));
+        let probe = Arc::clone(&running);
+        let task = OsuMonitor::new(Arc::clone(&state), Some(listener)).spawn(
+            Duration::from_millis(5),
+            move || {
+                let probe = Arc::clone(&probe);
+                async move { probe.load(Ordering::SeqCst) }
+            },
+        );
+
+        running.store(true, Ordering::SeqCst);
+        wait_for(&state, true).await;
+        running.store(false, Ordering::SeqCst);
+        wait_for(&state, false).await;
+        task.abort();
+
+        // Repeated polls with the same answer don't emit again
+        assert_eq!(*events.lock(), vec![true, false]);
+    }
+}
@@ -1,9 +1,16 @@
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 #[cfg(target_os = "windows")]
 use tokio::process::Command as TokioCommand;
 
 use crate::domain::AppConfig;
+#[cfg(target_os = "windows")]
+use crate::infrastructure::process::background_command;
 
 #[cfg(target_os = "windows")]
 mod deelevate {
@@ -27,7 +34,7 @@ mod deelevate {
         OsStr::new(s).encode_wide().chain(once(0)).collect()
     }
 
-    struct HandleGuard(HANDLE);
+    pub struct HandleGuard(HANDLE);
 
     impl Drop for HandleGuard {
         fn drop(&mut self) {
@@ -39,12 +46,34 @@ mod deelevate {
         }
     }
 
+    /// A process started by [`launch_deelevated`].
+    pub struct DeelevatedProcess {
+        process: HandleGuard,
+    }
+
+    impl DeelevatedProcess {
+        /// Returns the exit code if the process has exited.
+        pub fn try_wait(&mut self) -> Option<u32> {
+            use windows::Win32::Foundation::WAIT_OBJECT_0;
+            use windows::Win32::System::Threading::{GetExitCodeProcess, WaitForSingleObject};
+
+            unsafe {
+                if WaitForSingleObject(self.process.0, 0) != WAIT_OBJECT_0 {
+                    return None;
+                }
+                let mut code = 0u32;
+                GetExitCodeProcess(self.process.0, &mut code).ok()?;
+                Some(code)
+            }
+        }
+    }
+
     /// Launches with medium integrity by borrowing explorer.exe's token.
     pub fn launch_deelevated(
         exe_path: &Path,
         args: &[&str],
         working_dir: &Path,
-    ) -> Result<(), String> {
+    ) -> Result<DeelevatedProcess, String> {
         unsafe {
             let shell_window = GetShellWindow();
             if shell_window.0.is_null() {
@@ -81,12 +110,11 @@ mod deelevate {
             .map_err(|e| format!("Failed to duplicate token: {}", e))?;
             let primary_token = HandleGuard(primary_token);
 
-            let exe_str = exe_path.to_string_lossy();
-            let cmd_line = if args.is_empty() {
-                format!("\"{}\"", exe_str)
-            } else {
-                format!("\"{}\" {}", exe_str, args.join(" "))
-            };
+            let mut cmd_line = format!("\"{}\"", exe_path.to_string_lossy());
+            for arg in args {
+                cmd_line.push(' ');
+                cmd_line.push_str(&super::quote_windows_arg(arg));
+            }
             let mut cmd_wide = to_wide(&cmd_line);
             let working_dir_wide = to_wide(&working_dir.to_string_lossy());
 
@@ -107,16 +135,67 @@ mod deelevate {
             )
             .map_err(|e| format!("Failed to create de-elevated process: {}", e))?;
 
-            let _ = CloseHandle(process_info.hProcess);
             let _ = CloseHandle(process_info.hThread);
 
-            Ok(())
+            Ok(DeelevatedProcess {
+                process: HandleGuard(process_info.hProcess),
+            })
         }
     }
 }
 
 #[cfg(target_os = "windows")]
-use deelevate::launch_deelevated;
+use deelevate::{launch_deelevated, DeelevatedProcess};
+
+/// How long osu! must stay up after launching before it counts as started.
+/// Exiting sooner usually means it crashed on startup.
+pub const EARLY_EXIT_WINDOW: Duration = Duration::from_secs(1);
+
+/// How often to check for an early exit while waiting out the window.
+const EARLY_EXIT_POLL: Duration = Duration::from_millis(50);
+
+/// A running osu! process started by [`launch_osu`].
+pub enum OsuProcess {
+    Child(Child),
+    #[cfg(target_os = "windows")]
+    Deelevated(DeelevatedProcess),
+}
+
+impl OsuProcess {
+    /// Returns a description of how the process exited, or `None` if it's
+    /// still running.
+    pub fn try_exit(&mut self) -> Option<String> {
+        match self {
+            Self::Child(child) => match child.try_wait() {
+                Ok(Some(status)) => Some(status.to_string()),
+                Ok(None) => None,
+                Err(e) => Some(format!("unknown status ({})", e)),
+            },
+            #[cfg(target_os = "windows")]
+            Self::Deelevated(process) => process
+                .try_wait()
+                .map(|code| format!("exit code: {}", code)),
+        }
+    }
+}
+
+/// Fails if `process` exits within `window`, so a crash on startup can be
+/// told apart from a launch that never happened.
+async fn check_early_exit(process: &mut OsuProcess, window: Duration) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + window;
+    loop {
+        if let Some(exit) = process.try_exit() {
+            return Err(format!(
+                "osu! exited right after launching ({}). It may have crashed on startup.",
+                exit
+            ));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Ok(());
+        }
+        tokio::time::sleep(EARLY_EXIT_POLL).await;
+    }
+}
 
 const OSU_COMMON_PATHS: &[&str] = &[
     r"%LOCALAPPDATA%\osu!",
@@ -128,22 +207,209 @@ const OSU_COMMON_PATHS: &[&str] = &[
     r"D:\Games\osu!",
 ];
 
+/// Typical osu!lazer install locations, checked after the stable ones.
+#[cfg(target_os = "windows")]
+const OSU_LAZER_PATHS: &[&str] = &[r"%LOCALAPPDATA%\osulazer\current"];
+#[cfg(target_os = "macos")]
+const OSU_LAZER_PATHS: &[&str] = &["/Applications/osu!.app", "%HOME%/Applications/osu!.app"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const OSU_LAZER_PATHS: &[&str] = &["%HOME%/Applications", "%HOME%/.local/bin"];
+
+/// Files that make up a lazer install, the executable first. On Windows
+/// lazer also ships `osu!.exe`, so `osu.Game.dll` tells it apart from stable.
+#[cfg(target_os = "windows")]
+const LAZER_FILES: &[&str] = &["osu!.exe", "osu.Game.dll"];
+#[cfg(target_os = "macos")]
+const LAZER_FILES: &[&str] = &["Contents/MacOS/osu!"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LAZER_FILES: &[&str] = &["osu.AppImage"];
+
+const STABLE_EXECUTABLE: &str = "osu!.exe";
+
+/// Which osu! client an install directory holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OsuVariant {
+    #[default]
+    Stable,
+    Lazer,
+}
+
+impl OsuVariant {
+    /// Path of the executable, relative to the install directory.
+    pub fn executable(self) -> &'static str {
+        match self {
+            Self::Stable => STABLE_EXECUTABLE,
+            Self::Lazer => LAZER_FILES[0],
+        }
+    }
+
+    /// Arguments pointing the client at `host` instead of the official servers.
+    ///
+    /// Stable takes a single-dash flag; lazer uses GNU-style long options.
+    pub fn devserver_args(self, host: &str) -> Vec<String> {
+        let flag = match self {
+            Self::Stable => "-devserver",
+            Self::Lazer => "--devserver",
+        };
+        vec![flag.to_string(), host.to_string()]
+    }
+}
+
 pub fn detect_osu_path() -> Option<PathBuf> {
-    for path_template in OSU_COMMON_PATHS {
-        let expanded = expand_env_vars(path_template);
-        let path = PathBuf::from(&expanded);
+    detect_osu_installation().map(|(path, _)| path)
+}
 
-        if is_valid_osu_installation(&path) {
-            return Some(path);
+/// Finds an osu! install and reports which client it is. Stable installs are
+/// preferred over lazer.
+pub fn detect_osu_installation() -> Option<(PathBuf, OsuVariant)> {
+    #[cfg(windows)]
+    if let Some(path) = detect_osu_path_from_registry() {
+        if let Some(variant) = osu_variant(&path) {
+            return Some((path, variant));
         }
     }
 
-    None
+    let stable = OSU_COMMON_PATHS.iter().map(|t| (t, OsuVariant::Stable));
+    let lazer = OSU_LAZER_PATHS.iter().map(|t| (t, OsuVariant::Lazer));
+
+    stable.chain(lazer).find_map(|(path_template, wanted)| {
+        let path = PathBuf::from(expand_env_vars(path_template));
+        (osu_variant(&path) == Some(wanted)).then_some((path, wanted))
+    })
+}
+
+/// Registry keys whose default value is the command osu! registered to open
+/// its links and beatmap files.
+#[cfg(windows)]
+const OSU_REGISTRY_COMMANDS: &[&str] = &[
+    r"osu\shell\open\command",
+    r"osu!\shell\open\command",
+    r"osustable.File.osz\shell\open\command",
+];
+
+/// Finds the install directory through the handlers osu! registers under
+/// `HKEY_CLASSES_ROOT`, which also covers installs in non-standard folders.
+#[cfg(windows)]
+pub fn detect_osu_path_from_registry() -> Option<PathBuf> {
+    OSU_REGISTRY_COMMANDS
+        .iter()
+        .filter_map(|key| read_registry_default(key))
+        .filter_map(|command| exe_dir_from_command(&command))
+        .find(|dir| is_valid_osu_installation(dir))
+}
+
+/// Reads the default string value of `subkey` under `HKEY_CLASSES_ROOT`.
+#[cfg(windows)]
+fn read_registry_default(subkey: &str) -> Option<String> {
+    use std::ffi::OsStr;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CLASSES_ROOT, RRF_RT_REG_SZ};
+
+    let key: Vec<u16> = OsStr::new(subkey).encode_wide().chain(once(0)).collect();
+
+    unsafe {
+        let mut size = 0u32;
+        let status = RegGetValueW(
+            HKEY_CLASSES_ROOT,
+            PCWSTR(key.as_ptr()),
+            PCWSTR::null(),
+            RRF_RT_REG_SZ,
+            None,
+            None,
+            Some(&mut size),
+        );
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+
+        let mut buf = vec![0u16; (size as usize).div_ceil(2)];
+        let status = RegGetValueW(
+            HKEY_CLASSES_ROOT,
+            PCWSTR(key.as_ptr()),
+            PCWSTR::null(),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buf.as_mut_ptr() as *mut core::ffi::c_void),
+            Some(&mut size),
+        );
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        Some(String::from_utf16_lossy(&buf[..len]))
+    }
+}
+
+/// Extracts the directory of the executable from a registered shell command
+/// such as `"C:\osu!\osu!.exe" "%1"`.
+#[cfg(any(windows, test))]
+fn exe_dir_from_command(command: &str) -> Option<PathBuf> {
+    let command = command.trim();
+    let exe = match command.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => {
+            // Unquoted paths may contain spaces, so cut after the extension
+            let end = command.to_ascii_lowercase().find(".exe")? + ".exe".len();
+            &command[..end]
+        }
+    };
+
+    // Split on either separator so this also works when tested off Windows
+    let (dir, _) = exe.rsplit_once(['\\', '/'])?;
+    (!dir.is_empty()).then(|| PathBuf::from(dir))
+}
+
+/// Quotes `arg` for a Windows command line so the program splits it back into
+/// the same argument: wrapped in quotes if it has whitespace or quotes, with
+/// quotes and the backslashes before them escaped.
+#[cfg(any(windows, test))]
+fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // Backslashes are only special right before a quote
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.push_str(&"\\".repeat(escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // Doubled so they don't escape the closing quote
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Returns which osu! client is installed at `path`, if any.
+pub fn osu_variant(path: &Path) -> Option<OsuVariant> {
+    if LAZER_FILES.iter().all(|file| path.join(file).is_file()) {
+        Some(OsuVariant::Lazer)
+    } else if path.join(STABLE_EXECUTABLE).is_file() {
+        Some(OsuVariant::Stable)
+    } else {
+        None
+    }
 }
 
 pub fn is_valid_osu_installation(path: &Path) -> bool {
-    let exe_path = path.join("osu!.exe");
-    exe_path.exists() && exe_path.is_file()
+    osu_variant(path).is_some()
 }
 
 fn expand_env_vars(path: &str) -> String {
@@ -161,56 +427,124 @@ fn expand_env_vars(path: &str) -> String {
         result = result.replace("%USERPROFILE%", &user_profile);
     }
 
+    if let Ok(home) = std::env::var("HOME") {
+        result = result.replace("%HOME%", &home);
+    }
+
     result
 }
 
-pub fn launch_osu(osu_path: &Path, devserver_host: &str) -> Result<(), String> {
-    let exe_path = osu_path.join("osu!.exe");
+/// Builds the command line for osu!: the devserver flag and its host as
+/// separate arguments, then the user's extra arguments.
+fn launch_arguments(
+    variant: OsuVariant,
+    devserver_host: &str,
+    extra_args: &[String],
+) -> Vec<String> {
+    let mut args = variant.devserver_args(devserver_host);
+    args.extend(extra_args.iter().cloned());
+    args
+}
 
-    if !exe_path.exists() {
-        return Err(format!("osu!.exe not found at {:?}", exe_path));
-    }
+/// Launches osu! and waits out [`EARLY_EXIT_WINDOW`] to make sure it didn't
+/// exit straight away.
+pub async fn launch_osu(
+    osu_path: &Path,
+    devserver_host: &str,
+    extra_args: &[String],
+) -> Result<OsuProcess, String> {
+    let mut process = spawn_osu(osu_path, devserver_host, extra_args)?;
+    check_early_exit(&mut process, EARLY_EXIT_WINDOW).await?;
+    Ok(process)
+}
+
+fn spawn_osu(
+    osu_path: &Path,
+    devserver_host: &str,
+    extra_args: &[String],
+) -> Result<OsuProcess, String> {
+    let variant = osu_variant(osu_path)
+        .ok_or_else(|| format!("No osu! installation found at {}", osu_path.display()))?;
+    let exe_path = osu_path.join(variant.executable());
+    let args = launch_arguments(variant, devserver_host, extra_args);
 
     #[cfg(target_os = "windows")]
     {
-        match launch_deelevated(&exe_path, &["-devserver", devserver_host], osu_path) {
-            Ok(()) => return Ok(()),
+        let arg_refs: Vec<&str> = args.iter().map(String::as_str).collect();
+        match launch_deelevated(&exe_path, &arg_refs, osu_path) {
+            Ok(process) => return Ok(OsuProcess::Deelevated(process)),
             Err(e) => tracing::warn!("De-elevated launch failed ({}), using fallback", e),
         }
+    }
+
+    let result = Command::new(&exe_path)
+        .args(&args)
+        .current_dir(osu_path)
+        .spawn();
+
+    match result {
+        Ok(child) => Ok(OsuProcess::Child(child)),
+        Err(e) => Err(format!("Failed to launch osu!: {}", e)),
+    }
+}
 
-        let result = Command::new(&exe_path)
-            .arg("-devserver")
-            .arg(devserver_host)
-            .current_dir(osu_path)
-            .spawn();
+/// The last answer of an expensive yes/no check, reused while it's fresh.
+///
+/// Callers arriving while a check is in flight wait for it rather than
+/// starting their own.
+pub struct CachedCheck {
+    last: tokio::sync::Mutex<Option<(Instant, bool)>>,
+}
+
+impl Default for CachedCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        match result {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to launch osu!: {}", e)),
+impl CachedCheck {
+    pub const fn new() -> Self {
+        Self {
+            last: tokio::sync::Mutex::const_new(None),
         }
     }
 
-    #[cfg(not(target_os = "windows"))]
+    /// The last answer if it's younger than `max_age`, otherwise a fresh one
+    /// from `check`.
+    pub async fn get<F, Fut>(&self, max_age: Duration, check: F) -> bool
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = bool>,
     {
-        let result = Command::new(&exe_path)
-            .arg("-devserver")
-            .arg(devserver_host)
-            .current_dir(osu_path)
-            .spawn();
-
-        match result {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to launch osu!: {}", e)),
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, answer)) = *last {
+            if checked_at.elapsed() < max_age {
+                return answer;
+            }
         }
+
+        let answer = check().await;
+        *last = Some((Instant::now(), answer));
+        answer
     }
 }
 
+/// Shared by the monitor and the UI so polling from both doesn't spawn
+/// `tasklist` twice as often.
 #[cfg(target_os = "windows")]
-pub async fn is_osu_running() -> bool {
-    let output = TokioCommand::new("tasklist")
-        .args(["/FI", "IMAGENAME eq osu!.exe", "/NH"])
-        .output()
-        .await;
+static OSU_RUNNING: CachedCheck = CachedCheck::new();
+
+/// Whether osu! is running, reusing an answer younger than `max_age`.
+#[cfg(target_os = "windows")]
+pub async fn is_osu_running(max_age: Duration) -> bool {
+    OSU_RUNNING.get(max_age, query_osu_running).await
+}
+
+#[cfg(target_os = "windows")]
+async fn query_osu_running() -> bool {
+    let mut tasklist = background_command("tasklist");
+    tasklist.args(["/FI", "IMAGENAME eq osu!.exe", "/NH"]);
+    let output = TokioCommand::from(tasklist).output().await;
 
     match output {
         Ok(output) => {
@@ -222,23 +556,61 @@ pub async fn is_osu_running() -> bool {
 }
 
 #[cfg(not(target_os = "windows"))]
-pub async fn is_osu_running() -> bool {
+pub async fn is_osu_running(_max_age: Duration) -> bool {
     false
 }
 
+/// Why no usable osu! installation could be found.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OsuPathError {
+    /// The configured folder still exists, but osu! was removed from it.
+    #[error("The configured osu! folder {} no longer contains osu!.exe. Please select your osu! folder again in settings.", .0.display())]
+    ExecutableMissing(PathBuf),
+    /// The configured folder itself is gone.
+    #[error("The configured osu! folder {} no longer exists. Please select your osu! folder again in settings.", .0.display())]
+    FolderMissing(PathBuf),
+    /// Nothing was configured and no install was detected.
+    #[error("osu! installation not found. Please configure the path in settings.")]
+    NotFound,
+}
+
 pub fn get_osu_path(config: &AppConfig) -> Option<PathBuf> {
-    if let Some(ref path) = config.osu_path {
+    resolve_osu_path(config).ok()
+}
+
+/// Finds the osu! install to launch: the active profile's folder if it's
+/// still valid, otherwise a detected one. When neither works, the error says
+/// whether a configured folder went stale, so the user knows to reselect it.
+pub fn resolve_osu_path(config: &AppConfig) -> Result<PathBuf, OsuPathError> {
+    resolve_osu_path_with(config, detect_osu_path)
+}
+
+fn resolve_osu_path_with(
+    config: &AppConfig,
+    detect: impl FnOnce() -> Option<PathBuf>,
+) -> Result<PathBuf, OsuPathError> {
+    let configured = config.active_launch_profile().osu_path;
+    if let Some(ref path) = configured {
         if is_valid_osu_installation(path) {
-            return Some(path.clone());
+            return Ok(path.clone());
         }
     }
 
-    detect_osu_path()
+    if let Some(path) = detect() {
+        return Ok(path);
+    }
+
+    match configured {
+        Some(path) if path.is_dir() => Err(OsuPathError::ExecutableMissing(path)),
+        Some(path) => Err(OsuPathError::FolderMissing(path)),
+        None => Err(OsuPathError::NotFound),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::LaunchProfile;
 
     #[test]
     fn test_expand_env_vars() {
@@ -246,4 +618,221 @@ mod tests {
         let expanded = expand_env_vars(path);
         assert!(!expanded.contains("%USERPROFILE%") || expanded == path);
     }
+
+    #[test]
+    fn test_expand_home() {
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(
+                expand_env_vars("%HOME%/Applications"),
+                format!("{}/Applications", home)
+            );
+        }
+    }
+
+    #[test]
+    fn test_exe_dir_from_registry_command() {
+        assert_eq!(
+            exe_dir_from_command(r#""C:\Users\player\AppData\Local\osu!\osu!.exe" "%1""#),
+            Some(PathBuf::from(r"C:\Users\player\AppData\Local\osu!"))
+        );
+        assert_eq!(
+            exe_dir_from_command(r"D:\My Games\osu!\osu!.exe %1"),
+            Some(PathBuf::from(r"D:\My Games\osu!"))
+        );
+        assert_eq!(exe_dir_from_command(r#""osu!.exe" "%1""#), None);
+        assert_eq!(exe_dir_from_command(""), None);
+    }
+
+    #[test]
+    fn test_quote_windows_arg() {
+        assert_eq!(quote_windows_arg("-devserver"), "-devserver");
+        assert_eq!(quote_windows_arg(r"C:\Songs"), r"C:\Songs");
+        assert_eq!(
+            quote_windows_arg(r"C:\My Songs\map.osz"),
+            r#""C:\My Songs\map.osz""#
+        );
+        assert_eq!(quote_windows_arg(r"D:\My Games\"), r#""D:\My Games\\""#);
+        assert_eq!(quote_windows_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_windows_arg(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_windows_arg(""), r#""""#);
+    }
+
+    #[test]
+    fn test_stable_installation() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(osu_variant(dir.path()), None);
+        assert!(!is_valid_osu_installation(dir.path()));
+
+        std::fs::write(dir.path().join("osu!.exe"), b"").unwrap();
+        assert_eq!(osu_variant(dir.path()), Some(OsuVariant::Stable));
+        assert!(is_valid_osu_installation(dir.path()));
+    }
+
+    #[test]
+    fn test_lazer_installation() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in LAZER_FILES {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+
+        assert_eq!(osu_variant(dir.path()), Some(OsuVariant::Lazer));
+        assert!(is_valid_osu_installation(dir.path()));
+    }
+
+    #[test]
+    fn test_resolve_reports_deleted_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            osu_path: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            resolve_osu_path_with(&config, || None),
+            Err(OsuPathError::ExecutableMissing(dir.path().to_path_buf()))
+        );
+
+        let gone = dir.path().join("osu!");
+        let config = AppConfig {
+            osu_path: Some(gone.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_osu_path_with(&config, || None),
+            Err(OsuPathError::FolderMissing(gone))
+        );
+    }
+
+    #[test]
+    fn test_resolve_never_configured() {
+        let config = AppConfig::default();
+        assert_eq!(
+            resolve_osu_path_with(&config, || None),
+            Err(OsuPathError::NotFound)
+        );
+
+        let detected = PathBuf::from("/games/osu");
+        assert_eq!(
+            resolve_osu_path_with(&config, || Some(detected.clone())),
+            Ok(detected)
+        );
+    }
+
+    #[test]
+    fn test_selected_profile_is_launched() {
+        let stable = tempfile::tempdir().unwrap();
+        std::fs::write(stable.path().join("osu!.exe"), b"").unwrap();
+        let other = tempfile::tempdir().unwrap();
+        std::fs::write(other.path().join("osu!.exe"), b"").unwrap();
+
+        let mut config = AppConfig {
+            osu_path: Some(stable.path().to_path_buf()),
+            profiles: vec![LaunchProfile {
+                name: "Tournament client".to_string(),
+                osu_path: Some(other.path().to_path_buf()),
+                launch_args: vec!["-spectateclient".to_string()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(get_osu_path(&config), Some(stable.path().to_path_buf()));
+
+        config.active_profile = Some(0);
+        assert_eq!(
+            resolve_osu_path_with(&config, || None),
+            Ok(other.path().to_path_buf())
+        );
+        let profile = config.active_launch_profile();
+        assert_eq!(
+            launch_arguments(OsuVariant::Stable, "localhost", &profile.launch_args),
+            ["-devserver", "localhost", "-spectateclient"]
+        );
+
+        // A stale profile folder is reported, not silently swapped for the default
+        config.profiles[0].osu_path = Some(other.path().join("gone"));
+        assert_eq!(
+            resolve_osu_path_with(&config, || None),
+            Err(OsuPathError::FolderMissing(other.path().join("gone")))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_early_exit_is_detected() {
+        let child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let mut process = OsuProcess::Child(child);
+
+        let err = check_early_exit(&mut process, Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(err.contains("exited right after launching"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_running_process_passes_early_exit_check() {
+        let child = Command::new("sleep").arg("5").spawn().unwrap();
+        let mut process = OsuProcess::Child(child);
+
+        assert!(check_early_exit(&mut process, Duration::from_millis(200))
+            .await
+            .is_ok());
+
+        // Only refutable on Windows, where there's a de-elevated variant too
+        #[allow(irrefutable_let_patterns)]
+        if let OsuProcess::Child(mut child) = process {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_launch_arguments() {
+        assert_eq!(
+            launch_arguments(OsuVariant::Stable, "localhost", &[]),
+            ["-devserver", "localhost"]
+        );
+        assert_eq!(
+            launch_arguments(
+                OsuVariant::Stable,
+                "localhost",
+                &["-windowed".to_string(), "-tournament".to_string()]
+            ),
+            ["-devserver", "localhost", "-windowed", "-tournament"]
+        );
+    }
+
+    #[test]
+    fn test_devserver_args_per_variant() {
+        assert_eq!(
+            OsuVariant::Stable.devserver_args("localhost"),
+            ["-devserver", "localhost"]
+        );
+        assert_eq!(
+            OsuVariant::Lazer.devserver_args("localhost"),
+            ["--devserver", "localhost"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_check_reuses_fresh_answer() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let cache = CachedCheck::new();
+        let calls = AtomicUsize::new(0);
+        let check = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            true
+        };
+
+        assert!(cache.get(Duration::from_secs(60), check).await);
+        assert!(cache.get(Duration::from_secs(60), check).await);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A stale answer is checked again
+        assert!(cache.get(Duration::ZERO, check).await);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,254 @@
+//! Checks that everything `connect` depends on is in place.
+//!
+//! Connecting fails late and with little context if, say, port 443 is taken
+//! or the certificate isn't trusted. The preflight check runs each
+//! prerequisite up front so the UI can show a checklist with a fix for every
+//! item that fails.
+
+use serde::{Deserialize, Serialize};
+
+use crate::application::resolve_osu_path;
+use crate::domain::{AppConfig, ElevationMode};
+use crate::infrastructure::port::{check_port_available, PortStatus};
+use crate::infrastructure::{elevation, hosts, tls};
+
+/// Something that must hold for `connect` to succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Prerequisite {
+    CertificateInstalled,
+    HostsEntriesPresent,
+    PortAvailable,
+    OsuPathValid,
+    Elevated,
+}
+
+/// Outcome of checking one [`Prerequisite`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreflightCheck {
+    pub prerequisite: Prerequisite,
+    pub passed: bool,
+    /// How to fix the problem, set only when the check failed.
+    pub hint: Option<String>,
+}
+
+impl PreflightCheck {
+    fn new(prerequisite: Prerequisite, passed: bool, hint: impl FnOnce() -> String) -> Self {
+        Self {
+            prerequisite,
+            passed,
+            hint: (!passed).then(hint),
+        }
+    }
+}
+
+/// Result of every preflight check, in the order the UI should list them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    pub fn all_passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+}
+
+/// With `managed` off, rai!connect won't install the certificate itself, so
+/// the hint says so.
+fn certificate_check(installed: bool, managed: bool) -> PreflightCheck {
+    PreflightCheck::new(Prerequisite::CertificateInstalled, installed, || {
+        if managed {
+            "Install the rai!connect certificate so osu! trusts the local proxy.".to_string()
+        } else {
+            "Install the rai!connect certificate so osu! trusts the local proxy. Automatic setup is turned off, so this has to be done by hand.".to_string()
+        }
+    })
+}
+
+/// As for [`certificate_check`], `managed` tells whether rai!connect adds the
+/// entries itself.
+fn hosts_check(present: bool, managed: bool) -> PreflightCheck {
+    PreflightCheck::new(Prerequisite::HostsEntriesPresent, present, || {
+        if managed {
+            "Add the *.localhost entries to the hosts file. rai!connect does this on connect when run as administrator.".to_string()
+        } else {
+            "Add the *.localhost entries to the hosts file. Automatic setup is turned off, so this has to be done by hand.".to_string()
+        }
+    })
+}
+
+fn port_check(port: u16, status: PortStatus) -> PreflightCheck {
+    PreflightCheck::new(
+        Prerequisite::PortAvailable,
+        status == PortStatus::Free,
+        || {
+            match status {
+            PortStatus::InUse {
+                process: Some(process),
+            } => format!("Port {} is used by {}. Close it and try again.", port, process),
+            PortStatus::InUse { process: None } => format!(
+                "Port {} is used by another program. Close it (IIS and Skype are common culprits) and try again.",
+                port
+            ),
+            PortStatus::PermissionDenied => format!(
+                "Binding port {} needs administrator privileges. Restart rai!connect as administrator.",
+                port
+            ),
+            PortStatus::Unavailable { message } => {
+                format!("Port {} can't be bound: {}", port, message)
+            }
+            PortStatus::Free => String::new(),
+        }
+        },
+    )
+}
+
+fn osu_path_check(config: &AppConfig) -> PreflightCheck {
+    let resolved = resolve_osu_path(config);
+    PreflightCheck::new(Prerequisite::OsuPathValid, resolved.is_ok(), || {
+        resolved.err().map(|e| e.to_string()).unwrap_or_default()
+    })
+}
+
+fn elevation_check(elevated: bool) -> PreflightCheck {
+    PreflightCheck::new(Prerequisite::Elevated, elevated, || {
+        "Restart rai!connect as administrator so it can edit the hosts file and install the certificate.".to_string()
+    })
+}
+
+/// Checks every prerequisite for connecting with `config`.
+///
+/// With `proxy_running`, the port is held by rai!connect itself, so the port
+/// check passes without probing. Without elevation, the port checked is the
+/// unprivileged one the proxy would fall back to.
+pub fn preflight_check(config: &AppConfig, proxy_running: bool) -> PreflightReport {
+    let elevated = elevation::is_elevated();
+    let port = ElevationMode::from_elevated(elevated).https_port(&config.proxy);
+    let port_status = if proxy_running {
+        PortStatus::Free
+    } else {
+        check_port_available(config.proxy.bind_ip(), port)
+    };
+
+    PreflightReport {
+        checks: vec![
+            certificate_check(
+                tls::is_certificate_installed(),
+                config.proxy.manage_certificate,
+            ),
+            hosts_check(
+                hosts::are_hosts_entries_present(&hosts::HostsOptions::from(&config.proxy)),
+                config.proxy.manage_hosts,
+            ),
+            port_check(port, port_status),
+            osu_path_check(config),
+            elevation_check(elevated),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr, TcpListener};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_certificate_and_hosts_checks() {
+        assert!(certificate_check(true, true).passed);
+        assert!(certificate_check(true, true).hint.is_none());
+        assert!(!certificate_check(false, true).passed);
+        assert!(certificate_check(false, true).hint.is_some());
+
+        assert!(hosts_check(true, true).passed);
+        assert!(hosts_check(false, true).hint.is_some());
+    }
+
+    #[test]
+    fn test_unmanaged_checks_ask_for_manual_setup() {
+        let hint = certificate_check(false, false).hint.unwrap();
+        assert!(hint.contains("by hand"));
+        assert!(!certificate_check(false, true)
+            .hint
+            .unwrap()
+            .contains("by hand"));
+
+        let hint = hosts_check(false, false).hint.unwrap();
+        assert!(hint.contains("by hand"));
+        assert!(!hint.contains("does this on connect"));
+    }
+
+    #[test]
+    fn test_port_check_free_port() {
+        // Bind then drop to get a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let check = port_check(port, check_port_available(LOCALHOST, port));
+        assert!(check.passed);
+        assert_eq!(check.hint, None);
+    }
+
+    #[test]
+    fn test_port_check_port_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let check = port_check(port, check_port_available(LOCALHOST, port));
+        assert!(!check.passed);
+        assert!(check.hint.unwrap().contains("used by"));
+
+        let check = port_check(
+            443,
+            PortStatus::InUse {
+                process: Some("Skype.exe (PID 4321)".into()),
+            },
+        );
+        assert!(check.hint.unwrap().contains("Skype.exe"));
+    }
+
+    #[test]
+    fn test_port_check_permission_denied() {
+        let check = port_check(443, PortStatus::PermissionDenied);
+        assert!(!check.passed);
+        assert!(check.hint.unwrap().contains("administrator"));
+    }
+
+    #[test]
+    fn test_osu_path_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            osu_path: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert!(!osu_path_check(&config).passed);
+
+        fs::write(dir.path().join("osu!.exe"), b"").unwrap();
+        assert!(osu_path_check(&config).passed);
+    }
+
+    #[test]
+    fn test_elevation_check() {
+        assert!(elevation_check(true).passed);
+        assert!(elevation_check(false).hint.is_some());
+    }
+
+    #[test]
+    fn test_report_all_passed() {
+        let report = PreflightReport {
+            checks: vec![certificate_check(true, true), elevation_check(true)],
+        };
+        assert!(report.all_passed());
+
+        let report = PreflightReport {
+            checks: vec![certificate_check(true, true), elevation_check(false)],
+        };
+        assert!(!report.all_passed());
+    }
+}
@@ -1,15 +1,70 @@
+use std::net::IpAddr;
 use std::sync::Arc;
 
 use parking_lot::RwLock;
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
 
-use crate::domain::{AppState, ConnectionStatus, ProxyConfig};
-use crate::infrastructure::{hosts, tls};
+use crate::application::{is_osu_running, OsuExitHandler, OsuMonitor, ProxyError};
+use crate::domain::{
+    unix_millis, AppState, ConnectionStatus, ElevationMode, ProxyConfig, RouteStats, SupporterMode,
+    DEFAULT_OSU_POLL_INTERVAL_SECS,
+};
+use crate::infrastructure::http_proxy::{UpstreamClients, CONNECTION_DRAIN_TIMEOUT};
+use crate::infrastructure::packet_capture::{CapturedPacket, PacketCapture};
+use crate::infrastructure::{elevation, hosts, port, tls};
+
+/// Upper bound on waiting for the listener's port to be released when
+/// stopping.
+const PORT_RELEASE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Called with a snapshot of the state whenever the connection status changes,
+/// or osu! starts or exits.
+pub type StatusListener = Arc<dyn Fn(&AppState) + Send + Sync>;
+
+/// What [`ProxyManager::start`] has to change on the system before listening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SystemChanges {
+    install_certificate: bool,
+    add_hosts_entries: bool,
+}
+
+impl SystemChanges {
+    /// Nothing is changed that `config` leaves to the user, and without
+    /// elevation the hosts file is never edited, even if entries are missing.
+    fn needed(
+        config: &ProxyConfig,
+        mode: ElevationMode,
+        certificate_installed: bool,
+        hosts_present: bool,
+    ) -> Self {
+        Self {
+            install_certificate: config.manage_certificate && !certificate_installed,
+            add_hosts_entries: config.manage_hosts && mode.edits_hosts_file() && !hosts_present,
+        }
+    }
+}
 
 pub struct ProxyManager {
     state: Arc<RwLock<AppState>>,
     http_shutdown: Option<oneshot::Sender<()>>,
+    http_task: Option<JoinHandle<Result<(), ProxyError>>>,
+    osu_monitor: Option<JoinHandle<()>>,
     config: ProxyConfig,
+    /// Shared with the running proxy so supporter injection can be toggled live.
+    supporter: Arc<RwLock<SupporterMode>>,
+    /// Reused across restarts so the connection pool stays warm.
+    clients: Option<Arc<UpstreamClients>>,
+    /// Kept across restarts, like the clients.
+    capture: Arc<PacketCapture>,
+    status_listener: Option<StatusListener>,
+    osu_exit_handler: Option<OsuExitHandler>,
+    /// Detected on the first start unless set with `with_elevation_mode`.
+    elevation: Option<ElevationMode>,
+    /// Used instead of the stored certificate if set with `with_tls_acceptor`.
+    tls_acceptor: Option<TlsAcceptor>,
+    osu_poll_interval: std::time::Duration,
 }
 
 impl ProxyManager {
@@ -17,10 +72,51 @@ impl ProxyManager {
         Self {
             state: Arc::new(RwLock::new(AppState::default())),
             http_shutdown: None,
+            http_task: None,
+            osu_monitor: None,
+            supporter: Arc::new(RwLock::new(config.supporter_mode())),
             config,
+            clients: None,
+            capture: Arc::new(PacketCapture::default()),
+            status_listener: None,
+            osu_exit_handler: None,
+            elevation: None,
+            tls_acceptor: None,
+            osu_poll_interval: std::time::Duration::from_secs(DEFAULT_OSU_POLL_INTERVAL_SECS),
         }
     }
 
+    /// Registers a listener notified on every status transition.
+    pub fn with_status_listener(mut self, listener: StatusListener) -> Self {
+        self.status_listener = Some(listener);
+        self
+    }
+
+    /// Registers a handler run when osu! exits while the proxy is running.
+    pub fn with_osu_exit_handler(mut self, handler: OsuExitHandler) -> Self {
+        self.osu_exit_handler = Some(handler);
+        self
+    }
+
+    /// Uses `mode` instead of detecting whether rai!connect is elevated.
+    pub fn with_elevation_mode(mut self, mode: ElevationMode) -> Self {
+        self.elevation = Some(mode);
+        self
+    }
+
+    /// Serves with `acceptor` instead of loading (or generating) the stored
+    /// certificate on start.
+    pub fn with_tls_acceptor(mut self, acceptor: TlsAcceptor) -> Self {
+        self.tls_acceptor = Some(acceptor);
+        self
+    }
+
+    /// Sets how often the osu! monitor checks whether osu! is running.
+    pub fn with_osu_poll_interval(mut self, interval: std::time::Duration) -> Self {
+        self.osu_poll_interval = interval;
+        self
+    }
+
     pub fn state(&self) -> Arc<RwLock<AppState>> {
         Arc::clone(&self.state)
     }
@@ -29,19 +125,150 @@ impl ProxyManager {
         self.state.read().status
     }
 
-    pub async fn start(&mut self) -> Result<(), String> {
+    pub fn elevation_mode(&self) -> ElevationMode {
+        self.elevation
+            .unwrap_or_else(|| ElevationMode::from_elevated(elevation::is_elevated()))
+    }
+
+    /// Port the proxy listens on, or will once started.
+    pub fn https_port(&self) -> u16 {
+        self.elevation_mode().https_port(&self.config)
+    }
+
+    /// Address the proxy listens on, or will once started.
+    pub fn bind_ip(&self) -> IpAddr {
+        self.config.bind_ip()
+    }
+
+    /// Host to launch osu! with, through `-devserver`.
+    pub fn devserver_host(&self) -> String {
+        self.elevation_mode().devserver_host(&self.config)
+    }
+
+    /// Bancho packets recorded with `capture_packets` on, oldest first.
+    pub fn captured_packets(&self) -> Vec<CapturedPacket> {
+        self.capture.get_all()
+    }
+
+    /// Changes supporter injection on the running proxy, taking effect from
+    /// the next Bancho response.
+    pub fn set_supporter_mode(&mut self, mode: SupporterMode) {
+        self.config.set_supporter_mode(mode);
+        *self.supporter.write() = mode;
+    }
+
+    /// The upstream clients the proxy has been using, if it has started.
+    pub fn clients(&self) -> Option<Arc<UpstreamClients>> {
+        self.clients.clone()
+    }
+
+    /// Returns the upstream clients for the current config, building new ones
+    /// only if there are none yet or the timeouts have changed.
+    fn upstream_clients(&mut self) -> Arc<UpstreamClients> {
+        match &self.clients {
+            Some(clients) if clients.matches(&self.config) => Arc::clone(clients),
+            _ => {
+                let clients = Arc::new(UpstreamClients::new(&self.config));
+                self.clients = Some(Arc::clone(&clients));
+                clients
+            }
+        }
+    }
+
+    /// Updates the status, applying `update` under the same lock, and notifies
+    /// the listener if the status actually changed.
+    ///
+    /// Becoming `Connected` stamps `started_at`; any other status clears it,
+    /// along with `current_username`.
+    fn transition(&self, status: ConnectionStatus, update: impl FnOnce(&mut AppState)) {
+        let snapshot = {
+            let mut state = self.state.write();
+            let changed = state.status != status;
+            state.status = status;
+            if changed {
+                state.started_at = (status == ConnectionStatus::Connected).then(unix_millis);
+                state.update_uptime(unix_millis());
+                if status != ConnectionStatus::Connected {
+                    state.current_username = None;
+                }
+            }
+            update(&mut state);
+            changed.then(|| state.clone())
+        };
+
+        // Notify outside the lock so the listener can read the state itself
+        if let (Some(snapshot), Some(listener)) = (snapshot, &self.status_listener) {
+            listener(&snapshot);
+        }
+    }
+
+    pub async fn start(&mut self) -> Result<(), ProxyError> {
         if self.status() == ConnectionStatus::Connected {
             return Ok(());
         }
 
-        {
-            let mut state = self.state.write();
-            state.status = ConnectionStatus::Connecting;
-            state.last_error = None;
+        self.transition(ConnectionStatus::Connecting, |state| {
+            state.last_error = None
+        });
+
+        let mode = self.elevation_mode();
+        self.elevation = Some(mode);
+        self.prepare_system(mode);
+
+        let tls_acceptor = match &self.tls_acceptor {
+            Some(acceptor) => acceptor.clone(),
+            None => match tls::create_tls_acceptor(tls::CertOptions::from(&self.config)) {
+                Ok(acceptor) => acceptor,
+                Err(e) => return Err(self.fail(ProxyError::CertError(e.to_string()))),
+            },
+        };
+
+        self.listen(mode.https_port(&self.config), tls_acceptor)
+            .await
+    }
+
+    /// Installs the certificate and, when elevated, the hosts entries if
+    /// they're missing. Failures are only logged: the user may have set
+    /// things up by hand.
+    fn prepare_system(&self, mode: ElevationMode) {
+        let hosts_options = hosts::HostsOptions::from(&self.config);
+        let hosts_present = hosts::are_hosts_entries_present(&hosts_options);
+        let certificate_installed = tls::is_certificate_installed();
+        let changes =
+            SystemChanges::needed(&self.config, mode, certificate_installed, hosts_present);
+
+        if !self.config.manage_certificate {
+            tracing::info!("Skipping certificate setup: managed by the user");
+            if !certificate_installed {
+                tracing::warn!(
+                    "The certificate is not installed, so osu! will likely reject the proxy"
+                );
+            }
+        }
+        if !self.config.manage_hosts {
+            tracing::info!("Skipping hosts file setup: managed by the user");
+            if !hosts_present {
+                tracing::warn!("Hosts entries are missing, so osu! may not resolve *.localhost");
+            }
+        }
+
+        if mode == ElevationMode::NoElevation {
+            tracing::info!(
+                "Not running as administrator; listening on port {} and leaving the hosts file alone",
+                mode.https_port(&self.config)
+            );
+            if !hosts_present && self.config.manage_hosts {
+                tracing::warn!(
+                    "Hosts entries are missing, so osu! may not resolve *.localhost. Run rai!connect as administrator once to add them."
+                );
+            }
+            if changes.install_certificate {
+                tracing::warn!("Installing the certificate may fail without administrator rights");
+            }
         }
 
         // Ensure certificate is installed before starting proxy
-        if !tls::is_certificate_installed() {
+        if changes.install_certificate {
             tracing::info!("Certificate not installed, installing now...");
             match tls::install_certificate() {
                 Ok(true) => tracing::info!("Certificate installed successfully"),
@@ -53,9 +280,9 @@ impl ProxyManager {
         }
 
         // Ensure hosts file entries exist for *.localhost resolution
-        if !hosts::are_hosts_entries_present() {
+        if changes.add_hosts_entries {
             tracing::info!("Hosts entries not present, adding now...");
-            match hosts::add_hosts_entries() {
+            match hosts::add_hosts_entries(&hosts_options) {
                 Ok(true) => tracing::info!("Hosts entries added successfully"),
                 Ok(false) => tracing::info!("Hosts entries were already present"),
                 Err(e) => {
@@ -66,7 +293,10 @@ impl ProxyManager {
                 }
             }
         }
+    }
 
+    /// Starts the HTTPS proxy on `port` and waits for it to be listening.
+    async fn listen(&mut self, port: u16, tls_acceptor: TlsAcceptor) -> Result<(), ProxyError> {
         let (http_tx, http_rx) = oneshot::channel();
 
         // Create ready channel to verify port is bound
@@ -75,64 +305,132 @@ impl ProxyManager {
         self.http_shutdown = Some(http_tx);
 
         let https_state = Arc::clone(&self.state);
-        let https_config = self.config.clone();
-        tokio::spawn(async move {
-            if let Err(e) = crate::infrastructure::http_proxy::run_https_proxy(
-                https_config.https_port,
-                &https_config.direct_base_url,
-                https_config.inject_supporter,
-                &https_config.upstream_server,
+        let https_config = ProxyConfig {
+            https_port: port,
+            ..self.config.clone()
+        };
+        let https_supporter = Arc::clone(&self.supporter);
+        let https_clients = self.upstream_clients();
+        let https_capture = Arc::clone(&self.capture);
+        self.http_task = Some(tokio::spawn(async move {
+            crate::infrastructure::http_proxy::run_https_proxy(
+                &https_config,
                 https_state,
+                https_supporter,
+                https_clients,
+                https_capture,
+                tls_acceptor,
                 http_rx,
                 Some(http_ready_tx),
             )
             .await
-            {
+            .map_err(|e| {
                 tracing::error!("HTTPS proxy error: {}", e);
-            }
-        });
+                match e.downcast_ref::<std::io::Error>() {
+                    Some(e) => ProxyError::from_bind_error(https_config.https_port, e),
+                    None => ProxyError::Other(e.to_string()),
+                }
+            })
+        }));
 
         // Wait for HTTPS proxy to be ready (with timeout)
         let timeout = std::time::Duration::from_secs(5);
         match tokio::time::timeout(timeout, http_ready_rx).await {
             Ok(Ok(())) => {
-                let mut state = self.state.write();
-                state.status = ConnectionStatus::Connected;
-                tracing::info!("HTTPS proxy started on port {}", self.config.https_port);
+                self.transition(ConnectionStatus::Connected, |_| {});
+                tracing::info!("HTTPS proxy started on port {}", port);
+
+                let monitor = OsuMonitor::new(self.state(), self.status_listener.clone())
+                    .with_exit_handler(self.osu_exit_handler.clone());
+                let interval = self.osu_poll_interval;
+                self.osu_monitor = Some(monitor.spawn(interval, move || is_osu_running(interval)));
                 Ok(())
             }
-            _ => {
-                // Cleanup on failure
-                if let Some(tx) = self.http_shutdown.take() {
-                    let _ = tx.send(());
-                }
-                let mut state = self.state.write();
-                state.status = ConnectionStatus::Error;
-                state.last_error = Some("Failed to start proxy: port binding timeout".to_string());
-                Err("Failed to start proxy: port binding timeout".to_string())
+            // The proxy task ended before it was ready, so it has the reason
+            Ok(Err(_)) => {
+                let exited = match self.http_task.take() {
+                    Some(task) => task.await.ok().and_then(Result::err),
+                    None => None,
+                };
+                let error = exited.unwrap_or_else(|| {
+                    ProxyError::Other("HTTPS proxy exited unexpectedly".to_string())
+                });
+                Err(self.fail(error))
             }
+            Err(_) => Err(self.fail(ProxyError::StartTimeout)),
         }
     }
 
+    /// Cleans up after a failed start and records `error` in the state.
+    fn fail(&mut self, error: ProxyError) -> ProxyError {
+        if let Some(tx) = self.http_shutdown.take() {
+            let _ = tx.send(());
+        }
+        self.transition(ConnectionStatus::Error, |state| {
+            state.last_error = Some(error.to_string());
+        });
+        error
+    }
+
+    /// Stops the proxy and removes the hosts entries it added.
+    ///
+    /// Returns once the listener task has exited and its port can be bound
+    /// again, or the waits for either have timed out, so starting again
+    /// straight away doesn't fail with `AddrInUse`.
     pub async fn stop(&mut self) -> Result<(), String> {
+        if let Some(monitor) = self.osu_monitor.take() {
+            monitor.abort();
+        }
+
         if let Some(tx) = self.http_shutdown.take() {
             let _ = tx.send(());
         }
 
-        if let Err(e) = hosts::remove_hosts_entries() {
-            tracing::warn!("Failed to remove hosts entries: {}", e);
+        // Wait for open connections to drain so a restart doesn't race them
+        if let Some(mut task) = self.http_task.take() {
+            let (ip, port) = (self.config.bind_ip(), self.https_port());
+            let grace = CONNECTION_DRAIN_TIMEOUT + std::time::Duration::from_secs(1);
+            if tokio::time::timeout(grace, &mut task).await.is_err() {
+                tracing::warn!("HTTPS proxy did not shut down within {:?}", grace);
+                // Aborting drops the listener, releasing the port, but only
+                // once the task is gone, so wait for that too
+                task.abort();
+                let _ = task.await;
+            }
+
+            if !port::wait_for_port_release(ip, port, PORT_RELEASE_TIMEOUT).await {
+                tracing::warn!(
+                    "Port {} was not released within {:?}",
+                    port,
+                    PORT_RELEASE_TIMEOUT
+                );
+            }
         }
 
-        {
-            let mut state = self.state.write();
-            state.status = ConnectionStatus::Disconnected;
+        if self.config.manage_hosts && self.elevation.is_none_or(ElevationMode::edits_hosts_file) {
+            if let Err(e) = hosts::remove_hosts_entries() {
+                tracing::warn!("Failed to remove hosts entries: {}", e);
+            }
         }
 
+        self.transition(ConnectionStatus::Disconnected, |_| {});
+
         tracing::info!("Proxy stopped");
 
         Ok(())
     }
 
+    /// Stops the proxy and starts it again with `config`, without touching
+    /// osu!. [`stop`](Self::stop) waits for the old port to be released, so
+    /// restarting on the same port doesn't fail with `AddrInUse`.
+    pub async fn restart(&mut self, config: ProxyConfig) -> Result<(), ProxyError> {
+        self.stop().await.map_err(ProxyError::Other)?;
+
+        *self.supporter.write() = config.supporter_mode();
+        self.config = config;
+        self.start().await
+    }
+
     pub fn increment_requests(&self) {
         let mut state = self.state.write();
         state.requests_proxied += 1;
@@ -143,10 +441,28 @@ impl ProxyManager {
         state.beatmaps_downloaded += 1;
     }
 
+    /// Zeroes the traffic counters without touching the connection status,
+    /// then notifies the listener so the UI picks up the cleared values.
+    pub fn reset_stats(&self) {
+        let snapshot = {
+            let mut state = self.state.write();
+            state.requests_proxied = 0;
+            state.beatmaps_downloaded = 0;
+            state.route_stats = RouteStats::default();
+            state.bancho_bytes_client_to_server = 0;
+            state.bancho_bytes_server_to_client = 0;
+            state.clone()
+        };
+
+        if let Some(listener) = &self.status_listener {
+            listener(&snapshot);
+        }
+    }
+
     pub fn set_error(&self, error: String) {
-        let mut state = self.state.write();
-        state.status = ConnectionStatus::Error;
-        state.last_error = Some(error);
+        self.transition(ConnectionStatus::Error, |state| {
+            state.last_error = Some(error)
+        });
     }
 }
 
@@ -155,3 +471,285 @@ impl Default for ProxyManager {
         Self::new(ProxyConfig::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::net::Ipv4Addr;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn recording_manager() -> (ProxyManager, Arc<Mutex<Vec<AppState>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let manager =
+            ProxyManager::default().with_status_listener(Arc::new(move |state: &AppState| {
+                sink.lock().push(state.clone())
+            }));
+        (manager, events)
+    }
+
+    #[test]
+    fn test_connecting_sets_started_at_and_stopping_clears_it() {
+        let (manager, events) = recording_manager();
+
+        manager.transition(ConnectionStatus::Connecting, |_| {});
+        assert_eq!(manager.state().read().started_at, None);
+
+        let before = unix_millis();
+        manager.transition(ConnectionStatus::Connected, |_| {});
+        let started_at = manager.state().read().started_at.unwrap();
+        assert!(started_at >= before);
+        assert_eq!(events.lock().last().unwrap().started_at, Some(started_at));
+
+        // Staying connected keeps the original timestamp
+        manager.transition(ConnectionStatus::Connected, |_| {});
+        assert_eq!(manager.state().read().started_at, Some(started_at));
+
+        manager.state().write().current_username = Some("peppy".to_string());
+        manager.transition(ConnectionStatus::Disconnected, |_| {});
+        let state = manager.state().read().clone();
+        assert_eq!(state.started_at, None);
+        assert_eq!(state.uptime_secs, None);
+        assert_eq!(state.current_username, None);
+    }
+
+    #[test]
+    fn test_status_change_emits_state() {
+        let (manager, events) = recording_manager();
+
+        manager.set_error("Failed to bind port 443".to_string());
+
+        let events = events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].status, ConnectionStatus::Error);
+        assert_eq!(
+            events[0].last_error.as_deref(),
+            Some("Failed to bind port 443")
+        );
+    }
+
+    #[test]
+    fn test_unchanged_status_is_not_emitted() {
+        let (manager, events) = recording_manager();
+
+        manager.transition(ConnectionStatus::Disconnected, |_| {});
+        manager.set_error("first".to_string());
+        manager.set_error("second".to_string());
+
+        assert_eq!(events.lock().len(), 1);
+        assert_eq!(manager.state().read().last_error.as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn test_set_supporter_mode_updates_shared_mode() {
+        let mut manager = ProxyManager::default();
+        let mode = SupporterMode {
+            inject: true,
+            strict: true,
+        };
+
+        manager.set_supporter_mode(mode);
+
+        assert_eq!(*manager.supporter.read(), mode);
+        assert!(manager.config.inject_supporter);
+    }
+
+    #[test]
+    fn test_upstream_clients_reused_until_timeouts_change() {
+        let mut manager = ProxyManager::default();
+
+        let first = manager.upstream_clients();
+        manager.config.mirror_avatars = true;
+        let second = manager.upstream_clients();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        manager.config.upstream_request_timeout_secs += 30;
+        let third = manager.upstream_clients();
+        assert!(!Arc::ptr_eq(&second, &third));
+    }
+
+    #[test]
+    fn test_no_elevation_never_edits_hosts_file() {
+        let config = ProxyConfig::default();
+        assert_eq!(
+            SystemChanges::needed(&config, ElevationMode::NoElevation, false, false),
+            SystemChanges {
+                install_certificate: true,
+                add_hosts_entries: false,
+            }
+        );
+        assert_eq!(
+            SystemChanges::needed(&config, ElevationMode::Elevated, true, false),
+            SystemChanges {
+                install_certificate: false,
+                add_hosts_entries: true,
+            }
+        );
+        assert!(
+            !SystemChanges::needed(&config, ElevationMode::Elevated, true, true).add_hosts_entries
+        );
+    }
+
+    #[test]
+    fn test_unmanaged_setup_is_skipped_even_when_missing() {
+        let config = ProxyConfig {
+            manage_certificate: false,
+            manage_hosts: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            SystemChanges::needed(&config, ElevationMode::Elevated, false, false),
+            SystemChanges {
+                install_certificate: false,
+                add_hosts_entries: false,
+            }
+        );
+
+        let config = ProxyConfig {
+            manage_hosts: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            SystemChanges::needed(&config, ElevationMode::Elevated, false, false),
+            SystemChanges {
+                install_certificate: true,
+                add_hosts_entries: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_then_listen_reuses_port() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ProxyConfig {
+            unprivileged_https_port: port,
+            ..Default::default()
+        };
+        let mut manager = ProxyManager::new(config).with_elevation_mode(ElevationMode::NoElevation);
+        let (certs, key) = tls::generate_ephemeral_cert(tls::CertOptions::default()).unwrap();
+
+        for _ in 0..5 {
+            let acceptor = tls::tls_acceptor_for(certs.clone(), key.clone_key()).unwrap();
+            manager.listen(port, acceptor).await.unwrap();
+            // A connection that was open just before stopping
+            drop(
+                tokio::net::TcpStream::connect(("127.0.0.1", port))
+                    .await
+                    .unwrap(),
+            );
+
+            manager.stop().await.unwrap();
+            assert_eq!(
+                port::check_port_available(LOCALHOST, port),
+                port::PortStatus::Free
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_moves_to_new_port() {
+        let free_port = || {
+            std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port()
+        };
+        let (old_port, new_port) = (free_port(), free_port());
+        let config = ProxyConfig {
+            unprivileged_https_port: old_port,
+            manage_certificate: false,
+            manage_hosts: false,
+            ..Default::default()
+        };
+        let (certs, key) = tls::generate_ephemeral_cert(tls::CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs, key).unwrap();
+        let mut manager = ProxyManager::new(config.clone())
+            .with_elevation_mode(ElevationMode::NoElevation)
+            .with_tls_acceptor(acceptor);
+
+        manager.start().await.unwrap();
+        assert_eq!(manager.https_port(), old_port);
+
+        manager
+            .restart(ProxyConfig {
+                unprivileged_https_port: new_port,
+                ..config
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(manager.status(), ConnectionStatus::Connected);
+        assert_eq!(manager.https_port(), new_port);
+        tokio::net::TcpStream::connect(("127.0.0.1", new_port))
+            .await
+            .unwrap();
+        // The old port was released
+        drop(std::net::TcpListener::bind(("127.0.0.1", old_port)).unwrap());
+
+        manager.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_elevation_listens_on_unprivileged_port() {
+        // Bind then drop to get a port nothing is listening on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ProxyConfig {
+            unprivileged_https_port: port,
+            ..Default::default()
+        };
+        let mut manager = ProxyManager::new(config).with_elevation_mode(ElevationMode::NoElevation);
+        assert_eq!(manager.https_port(), port);
+        assert_eq!(manager.devserver_host(), format!("localhost:{}", port));
+
+        let (certs, key) = tls::generate_ephemeral_cert(tls::CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs, key).unwrap();
+        manager
+            .listen(manager.https_port(), acceptor)
+            .await
+            .unwrap();
+
+        assert_eq!(manager.status(), ConnectionStatus::Connected);
+        assert_ne!(
+            port::check_port_available(LOCALHOST, port),
+            port::PortStatus::Free
+        );
+
+        manager.stop().await.unwrap();
+        assert_eq!(manager.status(), ConnectionStatus::Disconnected);
+    }
+
+    #[test]
+    fn test_reset_stats_keeps_status() {
+        let (manager, events) = recording_manager();
+
+        manager.set_error("Upstream unreachable".to_string());
+        manager.increment_requests();
+        manager.increment_requests();
+        manager.increment_downloads();
+        manager.state().write().bancho_bytes_server_to_client = 512;
+
+        manager.reset_stats();
+
+        let state = manager.state().read().clone();
+        assert_eq!(state.requests_proxied, 0);
+        assert_eq!(state.beatmaps_downloaded, 0);
+        assert_eq!(state.bancho_bytes_server_to_client, 0);
+        assert_eq!(state.status, ConnectionStatus::Error);
+        assert_eq!(state.last_error.as_deref(), Some("Upstream unreachable"));
+
+        let events = events.lock();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].requests_proxied, 0);
+    }
+}
@@ -72,7 +72,7 @@ fn create_windows_shortcut() -> Result<PathBuf, String> {
     sl.create_lnk(&shortcut_path)
         .map_err(|e| format!("Failed to save shortcut: {}", e))?;
 
-    tracing::info!("Created desktop shortcut at {:?}", shortcut_path);
+    tracing::info!("Created desktop shortcut at {}", shortcut_path.display());
     Ok(shortcut_path)
 }
 
@@ -117,7 +117,7 @@ Categories=Game;
     fs::set_permissions(&shortcut_path, perms)
         .map_err(|e| format!("Failed to set file permissions: {}", e))?;
 
-    tracing::info!("Created desktop shortcut at {:?}", shortcut_path);
+    tracing::info!("Created desktop shortcut at {}", shortcut_path.display());
     Ok(shortcut_path)
 }
 
@@ -28,6 +28,8 @@ pub enum ServerPacketId {
     ProtocolVersion = 75,
     UserPrivileges = 71,
     UserPresence = 83,
+    /// Keepalive Bancho sends when it has nothing else to say.
+    Ping = 8,
     UserStats = 11,
     ChannelInfo = 64,
     Notification = 24,
@@ -41,6 +43,7 @@ impl From<u16> for ServerPacketId {
             75 => Self::ProtocolVersion,
             71 => Self::UserPrivileges,
             83 => Self::UserPresence,
+            8 => Self::Ping,
             11 => Self::UserStats,
             64 => Self::ChannelInfo,
             24 => Self::Notification,
@@ -49,6 +52,34 @@ impl From<u16> for ServerPacketId {
     }
 }
 
+/// Client packet IDs the proxy looks at. Everything else is `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum ClientPacketId {
+    /// Keepalive osu! sends on polls while the user does nothing.
+    Pong = 4,
+    Unknown = 0,
+}
+
+impl From<u16> for ClientPacketId {
+    fn from(value: u16) -> Self {
+        match value {
+            4 => Self::Pong,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Whether a Bancho poll body carries nothing but keepalives, i.e. the user
+/// is idle but osu! is still connected. An empty body counts too.
+pub fn is_keepalive_only(body: &[u8]) -> bool {
+    let (packets, remaining) = Packet::parse_stream(body);
+    remaining.is_empty()
+        && packets
+            .iter()
+            .all(|p| ClientPacketId::from(p.header.packet_id) == ClientPacketId::Pong)
+}
+
 /// User privilege flags in the Bancho protocol.
 ///
 /// Privileges are stored as a bitfield where each bit represents a different
@@ -63,6 +94,11 @@ impl From<u16> for ServerPacketId {
 ///
 /// let supporter = normal.with_supporter();
 /// assert!(supporter.has_supporter());
+///
+/// let staff = Privileges::from_flags(&[Privileges::NORMAL, Privileges::TOURNAMENT])
+///     .with_bat()
+///     .without(Privileges::TOURNAMENT);
+/// assert!(staff.has(Privileges::BAT));
 /// ```
 #[derive(Debug, Clone, Copy)]
 pub struct Privileges(pub u32);
@@ -77,9 +113,45 @@ impl Privileges {
     /// Beatmap Appreciation Team member.
     pub const BAT: u32 = 2;
 
+    /// Friend of the osu! team.
+    pub const FRIEND: u32 = 8;
+
+    /// The osu! developer account.
+    pub const PEPPY: u32 = 16;
+
     /// Tournament staff permissions.
     pub const TOURNAMENT: u32 = 32;
 
+    /// Combines the given flags, with no others set.
+    pub fn from_flags(flags: &[u32]) -> Self {
+        Self(flags.iter().fold(0, |acc, flag| acc | flag))
+    }
+
+    /// Returns a new `Privileges` with `flag` set as well.
+    pub fn with(self, flag: u32) -> Self {
+        Self(self.0 | flag)
+    }
+
+    /// Returns a new `Privileges` with `flag` cleared.
+    pub fn without(self, flag: u32) -> Self {
+        Self(self.0 & !flag)
+    }
+
+    /// Returns `true` if every bit of `flag` is set.
+    pub fn has(&self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+
+    /// Returns a new `Privileges` with the BAT flag set.
+    pub fn with_bat(self) -> Self {
+        self.with(Self::BAT)
+    }
+
+    /// Returns a new `Privileges` with the tournament staff flag set.
+    pub fn with_tournament(self) -> Self {
+        self.with(Self::TOURNAMENT)
+    }
+
     /// Returns a new `Privileges` with the supporter flag set.
     pub fn with_supporter(self) -> Self {
         Self(self.0 | Self::SUPPORTER)
@@ -189,6 +261,18 @@ pub struct Packet {
 }
 
 impl Packet {
+    /// Creates an uncompressed packet of the given type.
+    pub fn new(packet_id: ServerPacketId, payload: Vec<u8>) -> Self {
+        Self {
+            header: PacketHeader {
+                packet_id: packet_id as u16,
+                compression: 0,
+                length: payload.len() as u32,
+            },
+            payload,
+        }
+    }
+
     /// Parses complete packets from a byte stream.
     ///
     /// This function handles TCP fragmentation by extracting all complete
@@ -267,6 +351,18 @@ impl Packet {
     }
 }
 
+/// Result of [`inject_supporter_privileges`] on a single packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectionOutcome {
+    /// The packet is not a `UserPrivileges` packet.
+    NotApplicable,
+    /// The `SUPPORTER` flag was set.
+    Injected,
+    /// The packet is `UserPrivileges` but its payload isn't the expected
+    /// 4 bytes, which suggests the protocol has changed.
+    UnexpectedLayout { payload_len: usize },
+}
+
 /// Injects supporter privileges into a `UserPrivileges` packet.
 ///
 /// This function modifies the packet in-place to add the `SUPPORTER` flag
@@ -277,14 +373,20 @@ impl Packet {
 ///
 /// * `packet` - The packet to modify
 ///
+/// # Returns
+///
+/// What was done to the packet. A payload that isn't exactly 4 bytes is
+/// reported as [`InjectionOutcome::UnexpectedLayout`]; if it is longer than
+/// 4 bytes the first 4 are still modified.
+///
 /// # Safety
 ///
 /// This function assumes the payload follows the standard `UserPrivileges`
 /// format (4-byte little-endian u32). If the payload format is different,
 /// the modification may produce unexpected results.
-pub fn inject_supporter_privileges(packet: &mut Packet) {
+pub fn inject_supporter_privileges(packet: &mut Packet) -> InjectionOutcome {
     if packet.packet_type() != ServerPacketId::UserPrivileges {
-        return;
+        return InjectionOutcome::NotApplicable;
     }
 
     if packet.payload.len() >= 4 {
@@ -303,12 +405,119 @@ pub fn inject_supporter_privileges(packet: &mut Packet) {
         packet.payload[2] = new_bytes[2];
         packet.payload[3] = new_bytes[3];
     }
+
+    match packet.payload.len() {
+        4 => InjectionOutcome::Injected,
+        payload_len => InjectionOutcome::UnexpectedLayout { payload_len },
+    }
+}
+
+/// What a [`PacketEditor`] did with a packet.
+#[derive(Debug, Clone)]
+pub enum EditOutcome {
+    /// The packet was left alone.
+    Unchanged,
+    /// The packet was changed in place.
+    Modified,
+    /// These packets are to be sent straight after this one, which may
+    /// also have been changed in place.
+    Insert(Vec<Packet>),
+}
+
+/// One step in rewriting the packets of a Bancho response.
+///
+/// Editors run in order over each packet, each seeing it as the previous
+/// ones left it. Packets an editor inserts aren't passed to later editors.
+pub trait PacketEditor: Send + Sync {
+    fn edit(&self, packet: &mut Packet) -> EditOutcome;
+}
+
+/// Runs `editors` over `packets` in order.
+///
+/// # Returns
+///
+/// The packets to send, including any inserted by an editor, and whether
+/// anything was changed or inserted.
+pub fn apply_editors(
+    packets: Vec<Packet>,
+    editors: &[Box<dyn PacketEditor + '_>],
+) -> (Vec<Packet>, bool) {
+    let mut output = Vec::with_capacity(packets.len());
+    let mut changed = false;
+
+    for mut packet in packets {
+        let mut inserted = Vec::new();
+        for editor in editors {
+            match editor.edit(&mut packet) {
+                EditOutcome::Unchanged => {}
+                EditOutcome::Modified => changed = true,
+                EditOutcome::Insert(extra) => {
+                    changed = true;
+                    inserted.extend(extra);
+                }
+            }
+        }
+        output.push(packet);
+        output.append(&mut inserted);
+    }
+
+    (output, changed)
+}
+
+/// Reads the username from a Bancho login request body.
+///
+/// osu! logs in with a POST without an `osu-token` header whose body is
+/// three lines: the username, the MD5 of the password, and client details.
+/// Only the first line is read, so the password hash is never copied.
+pub fn parse_login_username(body: &[u8]) -> Option<String> {
+    let line = body.split(|&b| b == b'\n').next()?;
+    let username = std::str::from_utf8(line).ok()?.trim();
+    (!username.is_empty()).then(|| username.to_string())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_keepalive_only_bodies() {
+        let pong = PacketHeader {
+            packet_id: ClientPacketId::Pong as u16,
+            compression: 0,
+            length: 0,
+        }
+        .to_bytes();
+
+        assert!(is_keepalive_only(&[]));
+        assert!(is_keepalive_only(&pong));
+        assert!(is_keepalive_only(&[pong, pong].concat()));
+
+        // A chat message (id 1) alongside the pong is real activity
+        let message = PacketHeader {
+            packet_id: 1,
+            compression: 0,
+            length: 2,
+        }
+        .to_bytes();
+        assert!(!is_keepalive_only(&[&pong[..], &message, b"hi"].concat()));
+        // A truncated packet isn't a keepalive either
+        assert!(!is_keepalive_only(&pong[..3]));
+
+        assert_eq!(ServerPacketId::from(8), ServerPacketId::Ping);
+    }
+
+    #[test]
+    fn test_parse_login_username() {
+        let body = b"peppy\r\n5f4dcc3b5aa765d61d8327deb882cf99\r\nb20240123|2|1|abc:def|0\r\n";
+        let username = parse_login_username(body).unwrap();
+        assert_eq!(username, "peppy");
+        assert!(!username.contains("5f4dcc3b"));
+
+        assert_eq!(parse_login_username(b"\n5f4dcc3b\n"), None);
+        assert_eq!(parse_login_username(b""), None);
+        assert_eq!(parse_login_username(&[0xff, 0xfe, b'\n']), None);
+    }
+
     #[test]
     fn test_parse_header() {
         let data = [5, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0];
@@ -663,10 +872,11 @@ mod tests {
         };
 
         let payload_before = packet.payload.clone();
-        inject_supporter_privileges(&mut packet);
+        let outcome = inject_supporter_privileges(&mut packet);
 
         // Payload should be unchanged
         assert_eq!(packet.payload, payload_before);
+        assert_eq!(outcome, InjectionOutcome::NotApplicable);
     }
 
     #[test]
@@ -681,10 +891,14 @@ mod tests {
         };
 
         let payload_before = packet.payload.clone();
-        inject_supporter_privileges(&mut packet);
+        let outcome = inject_supporter_privileges(&mut packet);
 
         // Payload should be unchanged (too short to modify)
         assert_eq!(packet.payload, payload_before);
+        assert_eq!(
+            outcome,
+            InjectionOutcome::UnexpectedLayout { payload_len: 2 }
+        );
     }
 
     #[test]
@@ -744,4 +958,153 @@ mod tests {
         assert_eq!(privs.value() & Privileges::BAT, Privileges::BAT);
         assert_eq!(privs.value() & Privileges::NORMAL, Privileges::NORMAL);
     }
+
+    #[test]
+    fn test_privileges_builder_combines_flags() {
+        let privs = Privileges::default()
+            .with_bat()
+            .with_tournament()
+            .with_supporter();
+
+        assert_eq!(privs.value(), 1 | 2 | 4 | 32);
+        assert!(privs.has(Privileges::BAT));
+        assert!(privs.has(Privileges::TOURNAMENT));
+        assert!(!privs.has(Privileges::FRIEND));
+    }
+
+    #[test]
+    fn test_privileges_without_clears_only_that_flag() {
+        let privs = Privileges::from_flags(&[
+            Privileges::NORMAL,
+            Privileges::SUPPORTER,
+            Privileges::TOURNAMENT,
+        ])
+        .without(Privileges::SUPPORTER);
+
+        assert_eq!(privs.value(), Privileges::NORMAL | Privileges::TOURNAMENT);
+        assert!(!privs.has_supporter());
+
+        // Clearing an unset flag changes nothing
+        assert_eq!(privs.without(Privileges::BAT).value(), privs.value());
+    }
+
+    #[test]
+    fn test_privileges_from_flags() {
+        assert_eq!(Privileges::from_flags(&[]).value(), 0);
+        assert_eq!(
+            Privileges::from_flags(&[Privileges::NORMAL, Privileges::PEPPY, Privileges::NORMAL])
+                .value(),
+            17
+        );
+        // `has` needs every bit of a combined mask
+        let privs = Privileges::from_flags(&[Privileges::NORMAL, Privileges::BAT]);
+        assert!(privs.has(Privileges::NORMAL | Privileges::BAT));
+        assert!(!privs.has(Privileges::BAT | Privileges::SUPPORTER));
+    }
+
+    /// Sets supporter on UserPrivileges packets.
+    struct Supporter;
+
+    impl PacketEditor for Supporter {
+        fn edit(&self, packet: &mut Packet) -> EditOutcome {
+            match inject_supporter_privileges(packet) {
+                InjectionOutcome::NotApplicable => EditOutcome::Unchanged,
+                _ => EditOutcome::Modified,
+            }
+        }
+    }
+
+    /// Clears the BAT flag on UserPrivileges packets.
+    struct StripBat;
+
+    impl PacketEditor for StripBat {
+        fn edit(&self, packet: &mut Packet) -> EditOutcome {
+            if packet.packet_type() != ServerPacketId::UserPrivileges {
+                return EditOutcome::Unchanged;
+            }
+            let current = u32::from_le_bytes(packet.payload[..4].try_into().unwrap());
+            let stripped = Privileges(current).without(Privileges::BAT);
+            packet.payload = stripped.value().to_le_bytes().to_vec();
+            EditOutcome::Modified
+        }
+    }
+
+    /// Follows every LoginReply with a notification.
+    struct Greeter;
+
+    impl PacketEditor for Greeter {
+        fn edit(&self, packet: &mut Packet) -> EditOutcome {
+            if packet.packet_type() != ServerPacketId::LoginReply {
+                return EditOutcome::Unchanged;
+            }
+            // An osu! string: marker, ULEB128 length, UTF-8 bytes
+            let mut payload = vec![0x0b, 5];
+            payload.extend_from_slice(b"hello");
+            EditOutcome::Insert(vec![Packet::new(ServerPacketId::Notification, payload)])
+        }
+    }
+
+    fn privileges_packet(privileges: u32) -> Packet {
+        Packet::new(
+            ServerPacketId::UserPrivileges,
+            privileges.to_le_bytes().to_vec(),
+        )
+    }
+
+    fn privileges_of(packet: &Packet) -> u32 {
+        u32::from_le_bytes(packet.payload[..4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_editors_run_in_order() {
+        let editors: Vec<Box<dyn PacketEditor>> = vec![Box::new(Supporter), Box::new(StripBat)];
+        let packets = vec![
+            Packet::new(ServerPacketId::UserStats, vec![1, 2, 3]),
+            privileges_packet(Privileges::NORMAL | Privileges::BAT),
+        ];
+
+        let (out, changed) = apply_editors(packets, &editors);
+
+        assert!(changed);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].payload, vec![1, 2, 3]);
+        assert_eq!(
+            privileges_of(&out[1]),
+            Privileges::NORMAL | Privileges::SUPPORTER
+        );
+    }
+
+    #[test]
+    fn test_editor_inserts_packet_after_current() {
+        let editors: Vec<Box<dyn PacketEditor>> = vec![Box::new(Greeter), Box::new(Supporter)];
+        let packets = vec![
+            Packet::new(ServerPacketId::LoginReply, 1000i32.to_le_bytes().to_vec()),
+            privileges_packet(Privileges::NORMAL),
+        ];
+
+        let (out, changed) = apply_editors(packets, &editors);
+
+        assert!(changed);
+        let types: Vec<_> = out.iter().map(Packet::packet_type).collect();
+        assert_eq!(
+            types,
+            [
+                ServerPacketId::LoginReply,
+                ServerPacketId::Notification,
+                ServerPacketId::UserPrivileges,
+            ]
+        );
+        assert_eq!(out[1].header.length, 7);
+        assert_eq!(&out[1].payload[2..], b"hello");
+    }
+
+    #[test]
+    fn test_no_editors_changes_nothing() {
+        let packets = vec![privileges_packet(Privileges::NORMAL)];
+
+        let (out, changed) = apply_editors(packets, &[]);
+
+        assert!(!changed);
+        assert_eq!(privileges_of(&out[0]), Privileges::NORMAL);
+    }
 }
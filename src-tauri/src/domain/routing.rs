@@ -1,13 +1,112 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+use super::config::ProxyConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RouteDecision {
     HandleLocally,
     ForwardToUpstream,
     RedirectToUpstream,
 }
 
-pub fn route_request(host: &str, path: &str) -> RouteDecision {
+/// A user-defined routing rule, checked before the built-in routes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteRule {
+    /// Domain the host must equal or be a subdomain of, e.g. `ppy.sh`.
+    /// Empty matches any host.
+    #[serde(default)]
+    pub host_suffix: String,
+    /// Prefix the request path must start with. Empty matches any path.
+    #[serde(default)]
+    pub path_prefix: String,
+    pub decision: RouteDecision,
+}
+
+impl RouteRule {
+    pub fn matches(&self, host: &str, path: &str) -> bool {
+        host_matches_suffix(host, &self.host_suffix) && path.starts_with(&self.path_prefix)
+    }
+}
+
+/// Matches whole domain labels only, so `ppy.sh` matches `osu.ppy.sh`
+/// but neither `osu.ppy.sh.evil.com` nor `evilppy.sh`.
+fn host_matches_suffix(host: &str, suffix: &str) -> bool {
+    let suffix = suffix.trim_start_matches('.');
+    if suffix.is_empty() {
+        return true;
+    }
+
+    let host = host.to_ascii_lowercase();
+    let suffix = suffix.to_ascii_lowercase();
+    host == suffix
+        || host
+            .strip_suffix(suffix.as_str())
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Endpoints the mirror can serve but which go to the official servers
+/// unless switched on, since not every mirror supports them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MirrorEndpoints {
+    /// Avatars (`a.` hosts).
+    pub avatars: bool,
+    /// Replay downloads (`/web/osu-getreplay.php`).
+    pub replays: bool,
+    /// Beatmap comments (`/web/osu-comment.php`).
+    pub comments: bool,
+}
+
+impl MirrorEndpoints {
+    pub fn from_config(config: &ProxyConfig) -> Self {
+        Self {
+            avatars: config.mirror_avatars,
+            replays: config.mirror_replays,
+            comments: config.mirror_comments,
+        }
+    }
+}
+
+/// Decides how to handle a request, trying `rules` in order before
+/// falling back to the built-in routes.
+///
+/// Endpoints switched on in `mirror` are served from the mirror instead of
+/// being proxied to the official servers.
+pub fn route_request(
+    host: &str,
+    path: &str,
+    rules: &[RouteRule],
+    mirror: MirrorEndpoints,
+) -> RouteDecision {
     let host = host.split(':').next().unwrap_or(host);
 
+    if let Some(rule) = rules.iter().find(|r| r.matches(host, path)) {
+        return rule.decision;
+    }
+
+    if mirror.avatars && is_avatar_host(host) {
+        return RouteDecision::HandleLocally;
+    }
+
+    if host.ends_with("osu.ppy.sh") || host.ends_with("localhost") {
+        if mirror.replays && path.starts_with("/web/osu-getreplay.php") {
+            return RouteDecision::HandleLocally;
+        }
+        if mirror.comments && path.starts_with("/web/osu-comment.php") {
+            return RouteDecision::HandleLocally;
+        }
+    }
+
+    default_route(host, path)
+}
+
+/// Whether `host` is the avatar subdomain (`a.ppy.sh` or `a.localhost`).
+pub fn is_avatar_host(host: &str) -> bool {
+    let host = host.split(':').next().unwrap_or(host);
+    host.starts_with("a.")
+}
+
+fn default_route(host: &str, path: &str) -> RouteDecision {
     if host.ends_with("osu.ppy.sh") || host.ends_with("localhost") {
         if path.starts_with("/web/osu-search.php") || path.starts_with("/web/osu-search-set.php") {
             return RouteDecision::HandleLocally;
@@ -51,8 +150,166 @@ pub fn route_request(host: &str, path: &str) -> RouteDecision {
     RouteDecision::RedirectToUpstream
 }
 
+/// Whether `path` starts with one of the configured bypass prefixes.
+pub fn is_bypassed(path: &str, bypass_paths: &[String]) -> bool {
+    bypass_paths
+        .iter()
+        .any(|prefix| !prefix.is_empty() && path.starts_with(prefix.as_str()))
+}
+
+/// Requests counted by [`RouteStats`], told apart by route and path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteCategory {
+    /// osu!direct searches served by the mirror.
+    Search,
+    /// Beatmap downloads served by the mirror.
+    Download,
+    /// Beatmap thumbnails and audio previews served by the mirror.
+    Thumbnail,
+    /// Anything else served by the mirror, such as avatars or beatmap info.
+    OtherMirror,
+    /// Requests forwarded to the official servers, Bancho included.
+    Passthrough,
+    /// Website requests the browser was redirected for.
+    Redirect,
+}
+
+impl RouteCategory {
+    pub fn classify(decision: RouteDecision, path: &str) -> Self {
+        match decision {
+            RouteDecision::HandleLocally => {
+                if path.starts_with("/web/osu-search.php")
+                    || path.starts_with("/web/osu-search-set.php")
+                {
+                    Self::Search
+                } else if path.starts_with("/d/") {
+                    Self::Download
+                } else if path.starts_with("/thumb/") || path.starts_with("/preview/") {
+                    Self::Thumbnail
+                } else {
+                    Self::OtherMirror
+                }
+            }
+            RouteDecision::ForwardToUpstream => Self::Passthrough,
+            RouteDecision::RedirectToUpstream => Self::Redirect,
+        }
+    }
+}
+
+/// Routed requests by [`RouteCategory`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteStats {
+    pub searches: u64,
+    pub downloads: u64,
+    pub thumbnails: u64,
+    pub other_mirror: u64,
+    pub passthrough: u64,
+    pub redirects: u64,
+}
+
+impl RouteStats {
+    pub fn record(&mut self, category: RouteCategory) {
+        let counter = match category {
+            RouteCategory::Search => &mut self.searches,
+            RouteCategory::Download => &mut self.downloads,
+            RouteCategory::Thumbnail => &mut self.thumbnails,
+            RouteCategory::OtherMirror => &mut self.other_mirror,
+            RouteCategory::Passthrough => &mut self.passthrough,
+            RouteCategory::Redirect => &mut self.redirects,
+        };
+        *counter += 1;
+    }
+
+    /// Every request counted, which matches `AppState::requests_proxied`.
+    pub fn total(&self) -> u64 {
+        self.searches
+            + self.downloads
+            + self.thumbnails
+            + self.other_mirror
+            + self.passthrough
+            + self.redirects
+    }
+}
+
+/// What the proxy would do with a request, without sending it anywhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteSimulation {
+    pub decision: RouteDecision,
+    /// Where the request would be forwarded or redirected to.
+    pub upstream_url: String,
+    /// The path matched one of `bypass_paths`, so it goes to the official
+    /// servers untouched.
+    pub bypassed: bool,
+    /// Supporter privileges would be injected into the response.
+    pub injects_supporter: bool,
+}
+
+/// Works out how the proxy would route a request for `host` and `path`
+/// under `config`, for checking routing rules without running osu!.
+pub fn simulate_route(host: &str, path: &str, config: &ProxyConfig) -> RouteSimulation {
+    let upstream_url = |host: &str| {
+        format!(
+            "https://{}{}",
+            map_host_to_upstream(host, &config.upstream_server),
+            path
+        )
+    };
+
+    if is_bypassed(path, &config.bypass_paths) {
+        return RouteSimulation {
+            decision: RouteDecision::ForwardToUpstream,
+            upstream_url: upstream_url(host),
+            bypassed: true,
+            injects_supporter: false,
+        };
+    }
+
+    let decision = route_request(
+        host,
+        path,
+        &config.routes,
+        MirrorEndpoints::from_config(config),
+    );
+    let upstream_url = match decision {
+        RouteDecision::HandleLocally if is_avatar_host(host) => {
+            map_avatar_to_raimoe_url(path, &config.direct_base_url)
+        }
+        RouteDecision::HandleLocally => map_to_raimoe_url(path, &config.direct_base_url),
+        RouteDecision::ForwardToUpstream | RouteDecision::RedirectToUpstream => upstream_url(host),
+    };
+    let is_bancho = map_host_to_upstream(host, &config.upstream_server).starts_with("c.");
+
+    RouteSimulation {
+        decision,
+        upstream_url,
+        bypassed: false,
+        injects_supporter: decision == RouteDecision::ForwardToUpstream
+            && is_bancho
+            && config.inject_supporter,
+    }
+}
+
 pub fn map_to_raimoe_url(original_path: &str, direct_base_url: &str) -> String {
-    format!("{}{}", direct_base_url.trim_end_matches('/'), original_path)
+    join_base_url(direct_base_url, original_path)
+}
+
+/// Maps an avatar path such as `/12345` to its location on the mirror.
+pub fn map_avatar_to_raimoe_url(original_path: &str, direct_base_url: &str) -> String {
+    join_base_url(
+        direct_base_url,
+        &format!("/a/{}", original_path.trim_start_matches('/')),
+    )
+}
+
+/// Appends a request path (and query) to a base URL, which may itself end in
+/// a path prefix such as `https://example.com/osu-mirror`. Exactly one slash
+/// separates the two, however many either side has.
+pub fn join_base_url(base_url: &str, path_and_query: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path_and_query.trim_start_matches('/')
+    )
 }
 
 pub fn map_host_to_upstream(host: &str, upstream_server: &str) -> String {
@@ -69,10 +326,51 @@ pub fn map_host_to_upstream(host: &str, upstream_server: &str) -> String {
 mod tests {
     use super::*;
 
+    const AVATARS: MirrorEndpoints = MirrorEndpoints {
+        avatars: true,
+        replays: false,
+        comments: false,
+    };
+
+    #[test]
+    fn test_route_categories() {
+        use RouteCategory::*;
+        use RouteDecision::*;
+
+        for (decision, path, category) in [
+            (HandleLocally, "/web/osu-search.php?q=x", Search),
+            (HandleLocally, "/d/123n", Download),
+            (HandleLocally, "/preview/123.mp3", Thumbnail),
+            (HandleLocally, "/12345", OtherMirror),
+            (ForwardToUpstream, "/d/123", Passthrough),
+            (RedirectToUpstream, "/home", Redirect),
+        ] {
+            assert_eq!(
+                RouteCategory::classify(decision, path),
+                category,
+                "{}",
+                path
+            );
+        }
+
+        let mut stats = RouteStats::default();
+        stats.record(Search);
+        stats.record(Search);
+        stats.record(Redirect);
+        assert_eq!(stats.searches, 2);
+        assert_eq!(stats.redirects, 1);
+        assert_eq!(stats.total(), 3);
+    }
+
     #[test]
     fn test_route_osu_search() {
         assert_eq!(
-            route_request("osu.ppy.sh", "/web/osu-search.php?q=test"),
+            route_request(
+                "osu.ppy.sh",
+                "/web/osu-search.php?q=test",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -80,7 +378,7 @@ mod tests {
     #[test]
     fn test_route_download() {
         assert_eq!(
-            route_request("osu.ppy.sh", "/d/123456"),
+            route_request("osu.ppy.sh", "/d/123456", &[], MirrorEndpoints::default()),
             RouteDecision::HandleLocally
         );
     }
@@ -88,7 +386,12 @@ mod tests {
     #[test]
     fn test_route_login_forwards() {
         assert_eq!(
-            route_request("osu.ppy.sh", "/web/osu-submit-modular-selector.php"),
+            route_request(
+                "osu.ppy.sh",
+                "/web/osu-submit-modular-selector.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::ForwardToUpstream
         );
     }
@@ -96,7 +399,7 @@ mod tests {
     #[test]
     fn test_route_bancho_forwards() {
         assert_eq!(
-            route_request("c.ppy.sh", "/"),
+            route_request("c.ppy.sh", "/", &[], MirrorEndpoints::default()),
             RouteDecision::ForwardToUpstream
         );
     }
@@ -104,7 +407,12 @@ mod tests {
     #[test]
     fn test_thumbnail_routes_locally() {
         assert_eq!(
-            route_request("b.ppy.sh", "/thumb/123456l.jpg"),
+            route_request(
+                "b.ppy.sh",
+                "/thumb/123456l.jpg",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -127,15 +435,30 @@ mod tests {
     fn test_port_stripping_from_host() {
         // route_request should strip port from host
         assert_eq!(
-            route_request("osu.ppy.sh:443", "/web/osu-search.php"),
+            route_request(
+                "osu.ppy.sh:443",
+                "/web/osu-search.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
         assert_eq!(
-            route_request("osu.ppy.sh:80", "/d/123456"),
+            route_request(
+                "osu.ppy.sh:80",
+                "/d/123456",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
         assert_eq!(
-            route_request("b.ppy.sh:443", "/thumb/123.jpg"),
+            route_request(
+                "b.ppy.sh:443",
+                "/thumb/123.jpg",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -154,7 +477,7 @@ mod tests {
     #[test]
     fn test_empty_path_redirects() {
         assert_eq!(
-            route_request("osu.ppy.sh", ""),
+            route_request("osu.ppy.sh", "", &[], MirrorEndpoints::default()),
             RouteDecision::RedirectToUpstream
         );
     }
@@ -162,7 +485,7 @@ mod tests {
     #[test]
     fn test_root_path_redirects() {
         assert_eq!(
-            route_request("osu.ppy.sh", "/"),
+            route_request("osu.ppy.sh", "/", &[], MirrorEndpoints::default()),
             RouteDecision::RedirectToUpstream
         );
     }
@@ -171,11 +494,16 @@ mod tests {
     fn test_path_without_leading_slash() {
         // Paths without leading slash shouldn't match our patterns, redirect to website
         assert_eq!(
-            route_request("osu.ppy.sh", "d/123456"),
+            route_request("osu.ppy.sh", "d/123456", &[], MirrorEndpoints::default()),
             RouteDecision::RedirectToUpstream
         );
         assert_eq!(
-            route_request("osu.ppy.sh", "web/osu-search.php"),
+            route_request(
+                "osu.ppy.sh",
+                "web/osu-search.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::RedirectToUpstream
         );
     }
@@ -187,11 +515,21 @@ mod tests {
         // osu.ppy.sh.evil.com should NOT be treated as osu.ppy.sh
         // /web/ paths forward (API pattern), /d/ paths redirect (not locally handled)
         assert_eq!(
-            route_request("osu.ppy.sh.evil.com", "/web/osu-search.php"),
+            route_request(
+                "osu.ppy.sh.evil.com",
+                "/web/osu-search.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::ForwardToUpstream // matches /web/ API pattern
         );
         assert_eq!(
-            route_request("osu.ppy.sh.evil.com", "/d/123456"),
+            route_request(
+                "osu.ppy.sh.evil.com",
+                "/d/123456",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::RedirectToUpstream // doesn't match any pattern
         );
     }
@@ -205,13 +543,18 @@ mod tests {
 
         // Subdomains of osu.ppy.sh are handled locally for osu!direct paths
         assert_eq!(
-            route_request("sub.osu.ppy.sh", "/web/osu-search.php"),
+            route_request(
+                "sub.osu.ppy.sh",
+                "/web/osu-search.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
 
         // Non-osu!direct paths redirect to the website
         assert_eq!(
-            route_request("sub.osu.ppy.sh", "/home"),
+            route_request("sub.osu.ppy.sh", "/home", &[], MirrorEndpoints::default()),
             RouteDecision::RedirectToUpstream
         );
     }
@@ -221,7 +564,12 @@ mod tests {
         // b.ppy.sh.evil.com should NOT be treated as b.ppy.sh
         // Redirects because it doesn't match known asset domains
         assert_eq!(
-            route_request("b.ppy.sh.evil.com", "/thumb/123.jpg"),
+            route_request(
+                "b.ppy.sh.evil.com",
+                "/thumb/123.jpg",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::RedirectToUpstream
         );
     }
@@ -230,7 +578,12 @@ mod tests {
     #[test]
     fn test_preview_routes_locally() {
         assert_eq!(
-            route_request("b.ppy.sh", "/preview/123456.mp3"),
+            route_request(
+                "b.ppy.sh",
+                "/preview/123456.mp3",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -239,7 +592,12 @@ mod tests {
     #[test]
     fn test_osu_search_set_routes_locally() {
         assert_eq!(
-            route_request("osu.ppy.sh", "/web/osu-search-set.php?b=123"),
+            route_request(
+                "osu.ppy.sh",
+                "/web/osu-search-set.php?b=123",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -248,7 +606,12 @@ mod tests {
     #[test]
     fn test_osu_getbeatmapinfo_routes_locally() {
         assert_eq!(
-            route_request("osu.ppy.sh", "/web/osu-getbeatmapinfo.php"),
+            route_request(
+                "osu.ppy.sh",
+                "/web/osu-getbeatmapinfo.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -257,7 +620,12 @@ mod tests {
     #[test]
     fn test_localhost_search_routes_locally() {
         assert_eq!(
-            route_request("localhost", "/web/osu-search.php"),
+            route_request(
+                "localhost",
+                "/web/osu-search.php",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -265,7 +633,7 @@ mod tests {
     #[test]
     fn test_localhost_download_routes_locally() {
         assert_eq!(
-            route_request("localhost", "/d/123456"),
+            route_request("localhost", "/d/123456", &[], MirrorEndpoints::default()),
             RouteDecision::HandleLocally
         );
     }
@@ -273,7 +641,12 @@ mod tests {
     #[test]
     fn test_localhost_thumb_routes_locally() {
         assert_eq!(
-            route_request("localhost", "/thumb/123.jpg"),
+            route_request(
+                "localhost",
+                "/thumb/123.jpg",
+                &[],
+                MirrorEndpoints::default()
+            ),
             RouteDecision::HandleLocally
         );
     }
@@ -295,6 +668,88 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_map_to_raimoe_url_with_path_prefix() {
+        for base in [
+            "https://example.com/osu-mirror",
+            "https://example.com/osu-mirror/",
+        ] {
+            assert_eq!(
+                map_to_raimoe_url("/web/osu-search.php?q=test&m=0", base),
+                "https://example.com/osu-mirror/web/osu-search.php?q=test&m=0"
+            );
+        }
+        assert_eq!(
+            map_avatar_to_raimoe_url("/12345?1700000000", "https://example.com/osu-mirror/"),
+            "https://example.com/osu-mirror/a/12345?1700000000"
+        );
+    }
+
+    #[test]
+    fn test_join_base_url_collapses_slashes() {
+        assert_eq!(
+            join_base_url("https://direct.rai.moe//", "//d/1"),
+            "https://direct.rai.moe/d/1"
+        );
+        assert_eq!(
+            join_base_url("https://direct.rai.moe", "d/1"),
+            "https://direct.rai.moe/d/1"
+        );
+    }
+
+    #[test]
+    fn test_is_bypassed_matches_prefixes() {
+        let bypass = vec!["/web/osu-submit".to_string(), String::new()];
+
+        assert!(is_bypassed("/web/osu-submit-modular-selector.php", &bypass));
+        assert!(!is_bypassed("/web/osu-search.php", &bypass));
+    }
+
+    #[test]
+    fn test_simulate_download() {
+        let sim = simulate_route("osu.localhost", "/d/123", &ProxyConfig::default());
+
+        assert_eq!(sim.decision, RouteDecision::HandleLocally);
+        assert_eq!(sim.upstream_url, "https://direct.rai.moe/d/123");
+        assert!(!sim.injects_supporter);
+    }
+
+    #[test]
+    fn test_simulate_bancho() {
+        let config = ProxyConfig {
+            inject_supporter: true,
+            ..ProxyConfig::default()
+        };
+        let sim = simulate_route("c.localhost", "/", &config);
+
+        assert_eq!(sim.decision, RouteDecision::ForwardToUpstream);
+        assert_eq!(sim.upstream_url, "https://c.ppy.sh/");
+        assert!(sim.injects_supporter);
+
+        let bypassed = simulate_route(
+            "c.localhost",
+            "/",
+            &ProxyConfig {
+                bypass_paths: vec!["/".to_string()],
+                ..config
+            },
+        );
+        assert!(bypassed.bypassed);
+        assert!(!bypassed.injects_supporter);
+    }
+
+    #[test]
+    fn test_simulate_spoofed_domain_is_not_mirrored() {
+        let sim = simulate_route(
+            "osu.ppy.sh.evil.com",
+            "/web/osu-search.php?q=x",
+            &ProxyConfig::default(),
+        );
+
+        assert_eq!(sim.decision, RouteDecision::ForwardToUpstream);
+        assert!(!sim.upstream_url.contains("rai.moe"));
+    }
+
     // map_host_to_upstream tests
     #[test]
     fn test_map_host_to_upstream_known_subdomains() {
@@ -347,4 +802,198 @@ mod tests {
     fn test_map_host_to_upstream_fallback() {
         assert_eq!(map_host_to_upstream("localhost", "ppy.sh"), "osu.ppy.sh");
     }
+
+    fn rule(host_suffix: &str, path_prefix: &str, decision: RouteDecision) -> RouteRule {
+        RouteRule {
+            host_suffix: host_suffix.to_string(),
+            path_prefix: path_prefix.to_string(),
+            decision,
+        }
+    }
+
+    #[test]
+    fn test_custom_rule_takes_precedence() {
+        let rules = [
+            rule("osu.ppy.sh", "/d/", RouteDecision::ForwardToUpstream),
+            rule("osu.ppy.sh", "/", RouteDecision::HandleLocally),
+        ];
+
+        // The first matching rule wins over both later rules and the defaults
+        assert_eq!(
+            route_request(
+                "osu.ppy.sh",
+                "/d/123456",
+                &rules,
+                MirrorEndpoints::default()
+            ),
+            RouteDecision::ForwardToUpstream
+        );
+        assert_eq!(
+            route_request(
+                "osu.ppy.sh:443",
+                "/home",
+                &rules,
+                MirrorEndpoints::default()
+            ),
+            RouteDecision::HandleLocally
+        );
+
+        // Requests no rule matches fall back to the built-in routes
+        assert_eq!(
+            route_request("c.ppy.sh", "/", &rules, MirrorEndpoints::default()),
+            RouteDecision::ForwardToUpstream
+        );
+    }
+
+    #[test]
+    fn test_custom_rule_does_not_match_spoofed_host() {
+        let rules = [rule("osu.ppy.sh", "/", RouteDecision::HandleLocally)];
+
+        assert_eq!(
+            route_request(
+                "sub.osu.ppy.sh",
+                "/home",
+                &rules,
+                MirrorEndpoints::default()
+            ),
+            RouteDecision::HandleLocally
+        );
+        assert_eq!(
+            route_request(
+                "osu.ppy.sh.evil.com",
+                "/home",
+                &rules,
+                MirrorEndpoints::default()
+            ),
+            RouteDecision::RedirectToUpstream
+        );
+        assert_eq!(
+            route_request(
+                "evilosu.ppy.sh",
+                "/home",
+                &rules,
+                MirrorEndpoints::default()
+            ),
+            RouteDecision::RedirectToUpstream
+        );
+    }
+
+    #[test]
+    fn test_avatars_forward_by_default() {
+        assert_eq!(
+            route_request("a.ppy.sh", "/12345", &[], MirrorEndpoints::default()),
+            RouteDecision::ForwardToUpstream
+        );
+        assert_eq!(
+            route_request("a.localhost:443", "/12345", &[], MirrorEndpoints::default()),
+            RouteDecision::ForwardToUpstream
+        );
+    }
+
+    #[test]
+    fn test_avatars_handled_locally_when_mirrored() {
+        assert_eq!(
+            route_request("a.ppy.sh", "/12345", &[], AVATARS),
+            RouteDecision::HandleLocally
+        );
+        assert_eq!(
+            route_request("a.localhost:443", "/12345", &[], AVATARS),
+            RouteDecision::HandleLocally
+        );
+
+        // Other hosts are unaffected
+        assert_eq!(
+            route_request("b.ppy.sh", "/12345", &[], AVATARS),
+            RouteDecision::ForwardToUpstream
+        );
+        assert_eq!(
+            route_request("osu.ppy.sh", "/home", &[], AVATARS),
+            RouteDecision::RedirectToUpstream
+        );
+    }
+
+    #[test]
+    fn test_replays_and_comments_forward_by_default() {
+        for path in ["/web/osu-getreplay.php?c=123&m=0", "/web/osu-comment.php"] {
+            assert_eq!(
+                route_request("osu.ppy.sh", path, &[], MirrorEndpoints::default()),
+                RouteDecision::ForwardToUpstream
+            );
+        }
+    }
+
+    #[test]
+    fn test_replays_handled_locally_when_mirrored() {
+        let mirror = MirrorEndpoints {
+            replays: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            route_request(
+                "osu.ppy.sh",
+                "/web/osu-getreplay.php?c=123&m=0",
+                &[],
+                mirror
+            ),
+            RouteDecision::HandleLocally
+        );
+        assert_eq!(
+            route_request("localhost:443", "/web/osu-getreplay.php?c=123", &[], mirror),
+            RouteDecision::HandleLocally
+        );
+
+        // Score submission and comments still go to the official servers
+        assert_eq!(
+            route_request(
+                "osu.ppy.sh",
+                "/web/osu-submit-modular-selector.php",
+                &[],
+                mirror
+            ),
+            RouteDecision::ForwardToUpstream
+        );
+        assert_eq!(
+            route_request("osu.ppy.sh", "/web/osu-comment.php", &[], mirror),
+            RouteDecision::ForwardToUpstream
+        );
+        // Only on the osu! host
+        assert_eq!(
+            route_request("c.ppy.sh", "/web/osu-getreplay.php?c=123", &[], mirror),
+            RouteDecision::ForwardToUpstream
+        );
+        assert_eq!(
+            route_request("osu.ppy.sh.evil.com", "/web/osu-getreplay.php", &[], mirror),
+            RouteDecision::ForwardToUpstream
+        );
+    }
+
+    #[test]
+    fn test_comments_handled_locally_when_mirrored() {
+        let mirror = MirrorEndpoints {
+            comments: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            route_request("osu.ppy.sh", "/web/osu-comment.php", &[], mirror),
+            RouteDecision::HandleLocally
+        );
+        assert_eq!(
+            route_request("osu.ppy.sh", "/web/osu-getreplay.php?c=123", &[], mirror),
+            RouteDecision::ForwardToUpstream
+        );
+        assert_eq!(
+            route_request("osu.ppy.sh", "/web/osu-comments.php", &[], mirror),
+            RouteDecision::ForwardToUpstream
+        );
+    }
+
+    #[test]
+    fn test_map_avatar_to_raimoe_url() {
+        assert_eq!(
+            map_avatar_to_raimoe_url("/12345?1700000000", "https://direct.rai.moe/"),
+            "https://direct.rai.moe/a/12345?1700000000"
+        );
+    }
 }
@@ -0,0 +1,92 @@
+//! Crash-safe file replacement.
+//!
+//! Writing a file in place can leave it truncated if the process dies
+//! partway through. Here the new contents go to a temporary file next to the
+//! target, which is flushed to disk and then renamed over it. A crash before
+//! the rename leaves the original untouched.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Replaces the file at `path` with `contents` without ever leaving it half
+/// written.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = write_temp(path, contents)?;
+    commit(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Returns the temporary path used while replacing `path`.
+fn temp_path(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".rai-connect.tmp");
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// Writes `contents` to the temporary file for `path` and flushes it to disk.
+fn write_temp(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    let tmp = temp_path(path)?;
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        // Keep the original's permissions rather than the temp file's defaults
+        if let Ok(meta) = fs::metadata(path) {
+            fs::set_permissions(&tmp, meta.permissions())?;
+        }
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => Ok(tmp),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Moves a fully written temporary file over `path`.
+fn commit(tmp: &Path, path: &Path) -> io::Result<()> {
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomically_replaces_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hosts");
+        fs::write(&path, "old\n").unwrap();
+
+        write_atomically(&path, b"new\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        // Only the file itself is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_crash_before_rename_keeps_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "good").unwrap();
+
+        // Simulate dying after the temp file was written but before the rename
+        write_temp(&path, b"partial").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "good");
+
+        // The leftover temp file doesn't get in the way of the next write
+        write_atomically(&path, b"better").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "better");
+    }
+}
@@ -0,0 +1,385 @@
+//! On-disk cache for beatmap downloads.
+//!
+//! Beatmapsets downloaded through the mirror are kept under the app data
+//! directory, keyed by beatmapset id, so downloading the same map again
+//! doesn't hit rai.moe. Once the cache grows past its size limit the least
+//! recently used files are evicted.
+//!
+//! Each entry is an `{key}.osz` file plus an optional `{key}.type` sidecar
+//! holding the original `Content-Type`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+use tokio::io::AsyncWriteExt;
+
+const DATA_EXT: &str = "osz";
+const TYPE_EXT: &str = "type";
+
+/// A beatmapset found in the cache, opened for reading.
+#[derive(Debug)]
+pub struct CachedBeatmap {
+    pub file: fs::File,
+    /// Size of `file` in bytes.
+    pub len: u64,
+    pub content_type: Option<String>,
+}
+
+/// Size-bounded LRU cache of `.osz` downloads.
+pub struct BeatmapCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serializes inserts so concurrent evictions don't race each other.
+    write_lock: Mutex<()>,
+    /// Suffix for temporary files, so parallel downloads of one set don't collide.
+    tmp_counter: AtomicU64,
+}
+
+impl BeatmapCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            write_lock: Mutex::new(()),
+            tmp_counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the default cache directory under the local app data directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_local_dir().map(|d| d.join("rai-connect").join("beatmaps"))
+    }
+
+    /// Extracts the cache key from a download path such as `/d/123456`.
+    ///
+    /// The key is the beatmapset id, keeping the `n` suffix osu! appends when
+    /// requesting the no-video variant so the two aren't confused. Returns
+    /// `None` for anything that isn't a plain download path.
+    pub fn key_for_path(path: &str) -> Option<String> {
+        let rest = path.strip_prefix("/d/")?;
+        let key = rest.split(['?', '#']).next().unwrap_or_default();
+        let id = key.strip_suffix('n').unwrap_or(key);
+
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(key.to_string())
+    }
+
+    fn data_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, DATA_EXT))
+    }
+
+    fn type_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, TYPE_EXT))
+    }
+
+    /// Looks up a cached beatmapset, marking it as recently used.
+    ///
+    /// The file is only opened, so it can be streamed rather than read into
+    /// memory whole.
+    pub fn get(&self, key: &str) -> Option<CachedBeatmap> {
+        let path = self.data_path(key);
+        let file = fs::File::open(&path).ok()?;
+        let len = file.metadata().ok()?.len();
+
+        // Eviction goes by modification time, so a hit bumps it
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        let content_type = fs::read_to_string(self.type_path(key))
+            .ok()
+            .filter(|t| !t.is_empty());
+
+        Some(CachedBeatmap {
+            file,
+            len,
+            content_type,
+        })
+    }
+
+    /// Whether `key` is cached, without opening it or marking it as used.
+    pub fn contains(&self, key: &str) -> bool {
+        self.data_path(key).is_file()
+    }
+
+    /// Returns a fresh temporary path for writing an entry before it's committed.
+    fn tmp_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{}.{}.tmp",
+            key,
+            self.tmp_counter.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    /// Stores a downloaded beatmapset, then evicts old entries if over the limit.
+    pub fn insert(&self, key: &str, data: &[u8], content_type: Option<&str>) -> io::Result<()> {
+        if data.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+
+        // Write to a temporary file first so a partial write is never served
+        let tmp = self.tmp_path(key);
+        fs::write(&tmp, data)?;
+        self.commit(key, &tmp, content_type)
+    }
+
+    /// Starts writing a download into the cache as it streams in.
+    pub async fn writer(
+        self: &Arc<Self>,
+        key: &str,
+        content_type: Option<String>,
+    ) -> io::Result<CacheWriter> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let tmp = self.tmp_path(key);
+        let file = tokio::fs::File::create(&tmp).await?;
+
+        Ok(CacheWriter {
+            cache: Arc::clone(self),
+            key: key.to_string(),
+            content_type,
+            tmp,
+            file: Some(file),
+            written: 0,
+        })
+    }
+
+    /// Moves a fully written temporary file into place as the entry for `key`.
+    fn commit(&self, key: &str, tmp: &Path, content_type: Option<&str>) -> io::Result<()> {
+        let _guard = self.write_lock.lock();
+        fs::rename(tmp, self.data_path(key))?;
+
+        match content_type {
+            Some(t) => fs::write(self.type_path(key), t)?,
+            None => {
+                let _ = fs::remove_file(self.type_path(key));
+            }
+        }
+
+        self.evict()
+    }
+
+    /// Removes least recently used entries until the cache fits in `max_bytes`.
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total: u64 = 0;
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(DATA_EXT) {
+                continue;
+            }
+            let meta = fs::metadata(&path)?;
+            let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            total += meta.len();
+            entries.push((used, meta.len(), path));
+        }
+
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(used, _, _)| *used);
+
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            remove_entry(&path)?;
+            total -= len;
+            tracing::debug!("Evicted {} from beatmap cache", path.display());
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a download into the cache while it streams through the proxy.
+///
+/// The entry only becomes visible once [`CacheWriter::finish`] is called. A
+/// writer dropped before then, for example because the client disconnected
+/// mid-download, discards what it wrote.
+pub struct CacheWriter {
+    cache: Arc<BeatmapCache>,
+    key: String,
+    content_type: Option<String>,
+    tmp: PathBuf,
+    file: Option<tokio::fs::File>,
+    written: u64,
+}
+
+impl CacheWriter {
+    /// Appends a chunk of the download.
+    ///
+    /// Fails once the download grows past the cache's size limit, since it
+    /// would be evicted immediately anyway.
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.written += data.len() as u64;
+        if self.written > self.cache.max_bytes {
+            return Err(io::Error::other("download is larger than the cache"));
+        }
+
+        match self.file.as_mut() {
+            Some(file) => file.write_all(data).await,
+            None => Err(io::Error::other("cache writer already closed")),
+        }
+    }
+
+    /// Completes the entry and makes it available to [`BeatmapCache::get`].
+    pub async fn finish(mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush().await?;
+        }
+
+        let cache = Arc::clone(&self.cache);
+        let key = self.key.clone();
+        let tmp = self.tmp.clone();
+        let content_type = self.content_type.take();
+
+        tokio::task::spawn_blocking(move || cache.commit(&key, &tmp, content_type.as_deref()))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        // Close the file first; Windows won't delete an open file.
+        // After a successful commit the temporary file is already gone.
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+fn remove_entry(data_path: &Path) -> io::Result<()> {
+    fs::remove_file(data_path)?;
+    let _ = fs::remove_file(data_path.with_extension(TYPE_EXT));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Duration;
+
+    fn read_hit(cache: &BeatmapCache, key: &str) -> Vec<u8> {
+        let mut hit = cache.get(key).unwrap();
+        let mut data = Vec::new();
+        hit.file.read_to_end(&mut data).unwrap();
+        assert_eq!(hit.len, data.len() as u64);
+        data
+    }
+
+    fn backdate(cache: &BeatmapCache, key: &str, secs: u64) {
+        let file = fs::File::options()
+            .write(true)
+            .open(cache.data_path(key))
+            .unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_key_for_path() {
+        assert_eq!(BeatmapCache::key_for_path("/d/123"), Some("123".into()));
+        assert_eq!(BeatmapCache::key_for_path("/d/123n"), Some("123n".into()));
+        assert_eq!(
+            BeatmapCache::key_for_path("/d/123?u=user&h=hash"),
+            Some("123".into())
+        );
+        assert_eq!(BeatmapCache::key_for_path("/d/"), None);
+        assert_eq!(BeatmapCache::key_for_path("/d/../secret"), None);
+        assert_eq!(BeatmapCache::key_for_path("/web/osu-search.php"), None);
+    }
+
+    #[test]
+    fn test_hit_returns_data_and_content_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BeatmapCache::new(dir.path().to_path_buf(), 1024);
+
+        cache
+            .insert("1", b"osz-data", Some("application/x-osu-beatmap-archive"))
+            .unwrap();
+        assert_eq!(read_hit(&cache, "1"), b"osz-data");
+        let hit = cache.get("1").unwrap();
+        assert_eq!(
+            hit.content_type.as_deref(),
+            Some("application/x-osu-beatmap-archive")
+        );
+    }
+
+    #[test]
+    fn test_miss_returns_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BeatmapCache::new(dir.path().to_path_buf(), 1024);
+
+        assert!(cache.get("1").is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BeatmapCache::new(dir.path().to_path_buf(), 10);
+
+        cache.insert("1", b"aaaa", None).unwrap();
+        cache.insert("2", b"bbbb", None).unwrap();
+        backdate(&cache, "1", 60);
+        backdate(&cache, "2", 30);
+
+        // Reading "1" makes "2" the least recently used
+        assert!(cache.get("1").is_some());
+        cache.insert("3", b"cccc", None).unwrap();
+
+        assert!(cache.get("1").is_some());
+        assert!(cache.get("2").is_none());
+        assert!(cache.get("3").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_writer_commits_on_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(BeatmapCache::new(dir.path().to_path_buf(), 1024));
+
+        let mut writer = cache.writer("1", None).await.unwrap();
+        writer.write(b"first ").await.unwrap();
+        writer.write(b"second").await.unwrap();
+        assert!(cache.get("1").is_none());
+
+        writer.finish().await.unwrap();
+
+        assert_eq!(read_hit(&cache, "1"), b"first second");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_writer_leaves_nothing_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(BeatmapCache::new(dir.path().to_path_buf(), 1024));
+
+        let mut writer = cache.writer("1", None).await.unwrap();
+        writer.write(b"partial").await.unwrap();
+        drop(writer);
+
+        assert!(cache.get("1").is_none());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_oversized_entry_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BeatmapCache::new(dir.path().to_path_buf(), 4);
+
+        cache.insert("1", b"too large", None).unwrap();
+
+        assert!(cache.get("1").is_none());
+    }
+}
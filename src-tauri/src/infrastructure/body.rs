@@ -0,0 +1,173 @@
+//! Response body types used by the proxy.
+
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use tokio::sync::mpsc;
+
+use crate::infrastructure::throughput::TrafficMeters;
+
+/// A body fed chunk by chunk through a channel.
+///
+/// Lets a background task forward an upstream body as it arrives rather than
+/// buffering it in full. The body ends when the sender is dropped.
+pub struct ChannelBody {
+    rx: mpsc::Receiver<Bytes>,
+}
+
+impl ChannelBody {
+    pub fn new(rx: mpsc::Receiver<Bytes>) -> Self {
+        Self { rx }
+    }
+}
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.rx
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|data| Ok(Frame::data(data))))
+    }
+}
+
+/// Callback receiving the number of bytes a [`CountingBody`] yielded.
+type OnDone = Box<dyn FnOnce(u64) + Send + Sync>;
+
+/// Wraps a body and reports how many data bytes it yielded once it's dropped,
+/// whether it was read to the end or abandoned partway through.
+pub struct CountingBody<B> {
+    inner: B,
+    count: u64,
+    on_done: Option<OnDone>,
+}
+
+impl<B> CountingBody<B> {
+    pub fn new(inner: B, on_done: impl FnOnce(u64) + Send + Sync + 'static) -> Self {
+        Self {
+            inner,
+            count: 0,
+            on_done: Some(Box::new(on_done)),
+        }
+    }
+}
+
+impl<B> Body for CountingBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                self.count += data.len() as u64;
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for CountingBody<B> {
+    fn drop(&mut self) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done(self.count);
+        }
+    }
+}
+
+/// Wraps a response body and records each chunk in the download meter as it
+/// is sent, so streamed responses are measured while they flow.
+pub struct MeteredBody<B> {
+    inner: B,
+    meters: Arc<TrafficMeters>,
+}
+
+impl<B> MeteredBody<B> {
+    pub fn new(inner: B, meters: Arc<TrafficMeters>) -> Self {
+        Self { inner, meters }
+    }
+}
+
+impl<B> Body for MeteredBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                self.meters.download.record(data.len() as u64);
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use http_body_util::{BodyExt, Full};
+
+    #[tokio::test]
+    async fn test_channel_body_yields_chunks_in_order() {
+        let (tx, rx) = mpsc::channel(4);
+        tx.send(Bytes::from_static(b"hello ")).await.unwrap();
+        tx.send(Bytes::from_static(b"world")).await.unwrap();
+        drop(tx);
+
+        let body = ChannelBody::new(rx).collect().await.unwrap().to_bytes();
+
+        assert_eq!(body, Bytes::from_static(b"hello world"));
+    }
+
+    #[tokio::test]
+    async fn test_counting_body_reports_total_on_drop() {
+        let total = Arc::new(AtomicU64::new(0));
+        let reported = Arc::clone(&total);
+
+        let body = CountingBody::new(Full::new(Bytes::from_static(b"12345")), move |n| {
+            reported.store(n, Ordering::SeqCst);
+        });
+        body.collect().await.unwrap();
+
+        assert_eq!(total.load(Ordering::SeqCst), 5);
+    }
+}
@@ -0,0 +1,59 @@
+//! Detection of whether the app runs with administrator privileges.
+//!
+//! Editing the hosts file and installing the certificate both need them, so
+//! the UI checks this up front instead of waiting for those steps to fail.
+
+/// Returns `true` if the process runs elevated: an elevated token on
+/// Windows, or an effective user id of 0 elsewhere.
+#[cfg(target_os = "windows")]
+pub fn is_elevated() -> bool {
+    use windows::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows::Win32::Security::{
+        GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY,
+    };
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    unsafe {
+        let mut token = HANDLE::default();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).is_err() {
+            return false;
+        }
+
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut returned = 0u32;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut core::ffi::c_void),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut returned,
+        );
+        let _ = CloseHandle(token);
+
+        result.is_ok() && elevation.TokenIsElevated != 0
+    }
+}
+
+/// Returns `true` if the process runs elevated: an elevated token on
+/// Windows, or an effective user id of 0 elsewhere.
+#[cfg(unix)]
+pub fn is_elevated() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(any(target_os = "windows", unix)))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_elevated_does_not_panic() {
+        // CI may or may not run as root; only the call itself is checked
+        let _elevated: bool = is_elevated();
+    }
+}
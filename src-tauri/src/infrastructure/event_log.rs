@@ -0,0 +1,117 @@
+//! Tracing layer that forwards warnings and errors to the Windows Event Log.
+//!
+//! Intended for managed deployments where IT monitors machines through the
+//! standard Event Viewer tooling. Forwarding is off until enabled from
+//! `AppConfig::windows_event_log`, and only WARN and ERROR events are written
+//! to avoid flooding the log. On other platforms the layer is a no-op.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::infrastructure::logging::MessageVisitor;
+
+/// Event source name shown in Event Viewer.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+const EVENT_SOURCE: &str = "rai!connect";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables forwarding to the Event Log.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns `true` if events are currently forwarded to the Event Log.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Only warnings and errors are worth an administrator's attention.
+fn should_forward(level: Level) -> bool {
+    level <= Level::WARN
+}
+
+/// A tracing layer that writes WARN and ERROR events to the Windows Event Log.
+pub struct EventLogLayer;
+
+impl<S> Layer<S> for EventLogLayer
+where
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if !is_enabled() || !should_forward(level) {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::new();
+        event.record(&mut visitor);
+
+        let message = format!("[{}] {}", event.metadata().target(), visitor.message);
+        report_event(level, &message);
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn report_event(level: Level, message: &str) {
+    use std::sync::OnceLock;
+
+    use windows::core::{HSTRING, PCWSTR};
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::EventLog::{
+        RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_WARNING_TYPE,
+    };
+
+    // Registered on first use. Kept as an integer because HANDLE isn't Send/Sync.
+    static SOURCE: OnceLock<Option<isize>> = OnceLock::new();
+
+    let source = SOURCE.get_or_init(|| unsafe {
+        RegisterEventSourceW(PCWSTR::null(), &HSTRING::from(EVENT_SOURCE))
+            .ok()
+            .map(|handle| handle.0 as isize)
+    });
+
+    let Some(source) = *source else {
+        return;
+    };
+
+    let event_type = if level == Level::ERROR {
+        EVENTLOG_ERROR_TYPE
+    } else {
+        EVENTLOG_WARNING_TYPE
+    };
+    let message = HSTRING::from(message);
+
+    unsafe {
+        let _ = ReportEventW(
+            HANDLE(source as *mut _),
+            event_type,
+            0,
+            0,
+            None,
+            0,
+            Some(&[PCWSTR(message.as_ptr())]),
+            None,
+        );
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn report_event(_level: Level, _message: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_warnings_and_errors_forwarded() {
+        assert!(should_forward(Level::ERROR));
+        assert!(should_forward(Level::WARN));
+        assert!(!should_forward(Level::INFO));
+        assert!(!should_forward(Level::DEBUG));
+        assert!(!should_forward(Level::TRACE));
+    }
+}
@@ -5,12 +5,20 @@
 //!
 //! Note: This requires the application to run with administrator privileges.
 
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::domain::ProxyConfig;
+use crate::infrastructure::atomic_file::write_atomically;
+use crate::infrastructure::process::{background_command, run_with_timeout};
 
 const HOSTS_MARKER_START: &str = "# BEGIN rai-connect";
 const HOSTS_MARKER_END: &str = "# END rai-connect";
 
+/// Upper bound on how long flushing the DNS cache may take.
+const DNS_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
 const LOCALHOST_ENTRIES: &[(&str, &str)] = &[
     ("127.0.0.1", "osu.localhost"),
     ("127.0.0.1", "c.localhost"),
@@ -19,26 +27,140 @@ const LOCALHOST_ENTRIES: &[(&str, &str)] = &[
     ("127.0.0.1", "i.localhost"),
 ];
 
+/// Official osu! hosts, mapped to loopback only with `intercept_real_hosts`.
+const REAL_HOST_ENTRIES: &[(&str, &str)] = &[
+    ("127.0.0.1", "osu.ppy.sh"),
+    ("127.0.0.1", "c.ppy.sh"),
+    ("127.0.0.1", "c1.ppy.sh"),
+    ("127.0.0.1", "ce.ppy.sh"),
+    ("127.0.0.1", "a.ppy.sh"),
+    ("127.0.0.1", "b.ppy.sh"),
+    ("127.0.0.1", "i.ppy.sh"),
+];
+
 #[cfg(target_os = "windows")]
 const HOSTS_PATH: &str = r"C:\Windows\System32\drivers\etc\hosts";
 
 #[cfg(not(target_os = "windows"))]
 const HOSTS_PATH: &str = "/etc/hosts";
 
-/// Checks if the rai-connect hosts entries are already present.
-pub fn are_hosts_entries_present() -> bool {
+/// Returns the hosts file entries for the given mode.
+fn hosts_entries(
+    intercept_real_hosts: bool,
+) -> impl Iterator<Item = &'static (&'static str, &'static str)> {
+    let real: &[_] = if intercept_real_hosts {
+        REAL_HOST_ENTRIES
+    } else {
+        &[]
+    };
+    LOCALHOST_ENTRIES.iter().chain(real)
+}
+
+/// Returns the hostnames that rai-connect maps to loopback.
+///
+/// These are the subdomains the osu! client connects to with
+/// `-devserver localhost`, plus the official hosts when `intercept_real_hosts`
+/// is set, so the TLS certificate must cover all of them.
+pub fn intercepted_hostnames(intercept_real_hosts: bool) -> impl Iterator<Item = &'static str> {
+    hosts_entries(intercept_real_hosts).map(|(_, hostname)| *hostname)
+}
+
+/// Returns `true` if `host` (optionally with a port) is one of the official
+/// osu! hosts that `intercept_real_hosts` redirects to the proxy.
+pub fn is_real_host(host: &str) -> bool {
+    let host = host.split(':').next().unwrap_or(host);
+    REAL_HOST_ENTRIES
+        .iter()
+        .any(|(_, real)| real.eq_ignore_ascii_case(host))
+}
+
+/// Settings that determine which entries go into the hosts block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostsOptions {
+    /// Also redirect the official osu! hosts.
+    pub intercept_real_hosts: bool,
+    /// Additional `(ip, hostname)` pairs configured by the user.
+    pub extra_entries: Vec<(String, String)>,
+}
+
+impl From<&ProxyConfig> for HostsOptions {
+    fn from(config: &ProxyConfig) -> Self {
+        Self {
+            intercept_real_hosts: config.intercept_real_hosts,
+            extra_entries: config.extra_hosts_entries.clone(),
+        }
+    }
+}
+
+impl HostsOptions {
+    /// Checks that every extra entry is safe to write to the hosts file.
+    ///
+    /// A malformed entry could break resolution for the whole system, or end
+    /// the rai-connect block early if it smuggled in a newline.
+    pub fn validate(&self) -> Result<(), String> {
+        for (ip, hostname) in &self.extra_entries {
+            if ip.parse::<std::net::IpAddr>().is_err() {
+                return Err(format!("Invalid IP address in hosts entry: {:?}", ip));
+            }
+            if !is_plausible_hostname(hostname) {
+                return Err(format!("Invalid hostname in hosts entry: {:?}", hostname));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `hostname` looks like a DNS name: dot-separated labels of
+/// 1-63 letters, digits or hyphens, not starting or ending with a hyphen.
+fn is_plausible_hostname(hostname: &str) -> bool {
+    hostname.len() <= 253
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+/// Checks if the hosts file holds exactly one rai-connect block, with the
+/// entries for the given options.
+pub fn are_hosts_entries_present(options: &HostsOptions) -> bool {
     match fs::read_to_string(HOSTS_PATH) {
-        Ok(content) => content.contains(HOSTS_MARKER_START),
+        Ok(content) => has_single_block(&content, &generate_hosts_block(options)),
         Err(_) => false,
     }
 }
 
+/// Checks if any rai-connect marker is present, even an orphaned one.
+fn is_hosts_block_present() -> bool {
+    match fs::read_to_string(HOSTS_PATH) {
+        Ok(content) => has_any_marker(&content),
+        Err(_) => false,
+    }
+}
+
+fn has_any_marker(content: &str) -> bool {
+    content.contains(HOSTS_MARKER_START) || content.contains(HOSTS_MARKER_END)
+}
+
+fn has_single_block(content: &str, block: &str) -> bool {
+    content.matches(HOSTS_MARKER_START).count() == 1
+        && content.matches(HOSTS_MARKER_END).count() == 1
+        && content.contains(block)
+}
+
 /// Generates the hosts file content block for rai-connect.
-fn generate_hosts_block() -> String {
+fn generate_hosts_block(options: &HostsOptions) -> String {
     let mut block = String::new();
     block.push_str(HOSTS_MARKER_START);
     block.push('\n');
-    for (ip, hostname) in LOCALHOST_ENTRIES {
+    for (ip, hostname) in hosts_entries(options.intercept_real_hosts) {
+        block.push_str(&format!("{} {}\n", ip, hostname));
+    }
+    for (ip, hostname) in &options.extra_entries {
         block.push_str(&format!("{} {}\n", ip, hostname));
     }
     block.push_str(HOSTS_MARKER_END);
@@ -47,6 +169,10 @@ fn generate_hosts_block() -> String {
 
 /// Adds localhost subdomain entries to the hosts file.
 ///
+/// With `intercept_real_hosts`, the official osu! hosts are redirected to
+/// loopback as well, and any extra entries are appended. Existing rai-connect
+/// blocks written with other options are replaced.
+///
 /// This requires administrator privileges. The application should be
 /// run as admin (via Windows manifest) for this to work.
 ///
@@ -54,35 +180,20 @@ fn generate_hosts_block() -> String {
 ///
 /// Returns `Ok(true)` if entries were added, `Ok(false)` if they already exist,
 /// or an error if the operation failed.
-pub fn add_hosts_entries() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-    if are_hosts_entries_present() {
+pub fn add_hosts_entries(
+    options: &HostsOptions,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    if are_hosts_entries_present(options) {
         tracing::info!("Hosts entries already present");
         return Ok(false);
     }
 
-    let block = generate_hosts_block();
-
-    // Append the block to the hosts file
-    let mut file = OpenOptions::new()
-        .append(true)
-        .open(HOSTS_PATH)
-        .map_err(|e| {
-            format!(
-                "Failed to open hosts file: {}. Make sure the app is running as administrator.",
-                e
-            )
-        })?;
-
-    // Add a newline before our block if the file doesn't end with one
-    let content = fs::read_to_string(HOSTS_PATH)?;
-    let prefix = if content.ends_with('\n') { "" } else { "\n" };
-
-    file.write_all(format!("{}{}\n", prefix, block).as_bytes())
-        .map_err(|e| format!("Failed to write to hosts file: {}", e))?;
+    normalize_hosts_entries(options)?;
 
     // Verify the entries were added
-    if are_hosts_entries_present() {
+    if are_hosts_entries_present(options) {
         tracing::info!("Successfully added hosts entries");
+        flush_dns_cache_or_warn();
         Ok(true)
     } else {
         Err("Failed to verify hosts entries were added".into())
@@ -93,52 +204,340 @@ pub fn add_hosts_entries() -> Result<bool, Box<dyn std::error::Error + Send + Sy
 ///
 /// This requires administrator privileges.
 pub fn remove_hosts_entries() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-    if !are_hosts_entries_present() {
+    if !is_hosts_block_present() {
         return Ok(false);
     }
 
-    let content = fs::read_to_string(HOSTS_PATH)?;
+    let removed = remove_hosts_entries_from(Path::new(HOSTS_PATH))?;
+    if removed {
+        tracing::info!("Successfully removed hosts entries");
+        flush_dns_cache_or_warn();
+    }
+    Ok(removed)
+}
 
-    // Find and remove the rai-connect block
-    let start_idx = content.find(HOSTS_MARKER_START);
-    let end_idx = content.find(HOSTS_MARKER_END);
+/// Strips rai-connect blocks from the hosts file at `path`, returning
+/// whether there were any.
+fn remove_hosts_entries_from(
+    path: &Path,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let content = fs::read_to_string(path)?;
+    if !has_any_marker(&content) {
+        return Ok(false);
+    }
 
-    if let (Some(start), Some(end)) = (start_idx, end_idx) {
-        // Find the start of the line containing the marker
-        let line_start = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
-        // Find the end of the line containing the end marker
-        let line_end = content[end..]
-            .find('\n')
-            .map(|i| end + i + 1)
-            .unwrap_or(content.len());
+    write_atomically(path, strip_hosts_blocks(&content).as_bytes())
+        .map_err(|e| format!("Failed to write hosts file: {}", e))?;
+    Ok(true)
+}
 
-        let mut new_content = String::new();
-        new_content.push_str(&content[..line_start]);
-        new_content.push_str(&content[line_end..]);
+/// Returns the command that flushes the resolver cache on `os`, as named by
+/// [`std::env::consts::OS`].
+fn flush_dns_command(os: &str) -> Option<(&'static str, &'static [&'static str])> {
+    match os {
+        "windows" => Some(("ipconfig", &["/flushdns"])),
+        "macos" => Some(("dscacheutil", &["-flushcache"])),
+        "linux" => Some(("resolvectl", &["flush-caches"])),
+        _ => None,
+    }
+}
 
-        // Remove any double newlines that might result
-        let new_content = new_content.replace("\n\n\n", "\n\n");
+/// Flushes the OS DNS cache so new hosts entries take effect right away.
+///
+/// Windows in particular caches negative lookups, so without this osu! can
+/// keep failing to resolve `*.localhost` until the next reboot.
+pub fn flush_dns_cache() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (program, args) =
+        flush_dns_command(std::env::consts::OS).ok_or("DNS cache flushing is not supported")?;
 
-        fs::write(HOSTS_PATH, new_content)
-            .map_err(|e| format!("Failed to write hosts file: {}", e))?;
+    let output = run_with_timeout(background_command(program).args(args), DNS_FLUSH_TIMEOUT)
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
 
-        tracing::info!("Successfully removed hosts entries");
-        Ok(true)
+    if output.status.success() {
+        tracing::debug!("Flushed DNS cache");
+        Ok(())
     } else {
-        Err("Failed to find hosts block boundaries".into())
+        Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into())
+    }
+}
+
+/// A stale cache only delays resolution, so a failed flush shouldn't fail
+/// the hosts edit that triggered it.
+fn flush_dns_cache_or_warn() {
+    if let Err(e) = flush_dns_cache() {
+        tracing::warn!("Could not flush DNS cache: {}", e);
     }
 }
 
+/// Rewrites the hosts file so it holds exactly one correct rai-connect block.
+///
+/// A crash between edits can leave several blocks behind, or a start marker
+/// without its end. All of them are removed and a single fresh block for the
+/// given options is appended.
+///
+/// Returns `Ok(true)` if the file had to be changed.
+pub fn normalize_hosts_entries(
+    options: &HostsOptions,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    options.validate()?;
+
+    let content = fs::read_to_string(HOSTS_PATH).map_err(|e| {
+        format!(
+            "Failed to read hosts file: {}. Make sure the app is running as administrator.",
+            e
+        )
+    })?;
+
+    let block = generate_hosts_block(options);
+    if has_single_block(&content, &block) {
+        return Ok(false);
+    }
+
+    if has_any_marker(&content) {
+        tracing::info!("Repairing stale rai-connect hosts entries");
+    }
+
+    let new_content = with_hosts_block(&strip_hosts_blocks(&content), &block);
+    write_atomically(Path::new(HOSTS_PATH), new_content.as_bytes()).map_err(|e| {
+        format!(
+            "Failed to write hosts file: {}. Make sure the app is running as administrator.",
+            e
+        )
+    })?;
+
+    Ok(true)
+}
+
+/// Returns `content` with `block` appended on its own lines.
+fn with_hosts_block(content: &str, block: &str) -> String {
+    let mut new_content = content.to_string();
+    // Add a newline before our block if the file doesn't end with one
+    if !new_content.is_empty() && !new_content.ends_with('\n') {
+        new_content.push('\n');
+    }
+    new_content.push_str(block);
+    new_content.push('\n');
+    new_content
+}
+
+/// Returns `true` if `line` is an entry rai-connect writes, in either mode.
+fn is_own_entry(line: &str) -> bool {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(ip), Some(hostname), None) => {
+            hosts_entries(true).any(|(i, h)| *i == ip && *h == hostname)
+        }
+        _ => false,
+    }
+}
+
+/// Returns `content` with every rai-connect block and orphaned marker removed.
+///
+/// A start marker without a matching end only takes the entry lines right
+/// after it along, so user entries further down the file are never lost.
+fn strip_hosts_blocks(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut kept = String::with_capacity(content.len());
+    let mut i = 0;
+
+    while i < lines.len() {
+        match lines[i].trim() {
+            HOSTS_MARKER_START => {
+                let end = lines[i + 1..]
+                    .iter()
+                    .map(|line| line.trim())
+                    .take_while(|line| *line != HOSTS_MARKER_START)
+                    .position(|line| line == HOSTS_MARKER_END);
+
+                i += 1;
+                match end {
+                    Some(offset) => i += offset + 1,
+                    None => {
+                        while i < lines.len() && is_own_entry(lines[i]) {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            // An end marker with no start
+            HOSTS_MARKER_END => i += 1,
+            _ => {
+                kept.push_str(lines[i]);
+                i += 1;
+            }
+        }
+    }
+
+    // Remove any double newlines that might result
+    kept.replace("\n\n\n", "\n\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn real_hosts() -> HostsOptions {
+        HostsOptions {
+            intercept_real_hosts: true,
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_generate_hosts_block() {
-        let block = generate_hosts_block();
+        let block = generate_hosts_block(&HostsOptions::default());
         assert!(block.contains(HOSTS_MARKER_START));
         assert!(block.contains(HOSTS_MARKER_END));
         assert!(block.contains("osu.localhost"));
         assert!(block.contains("c.localhost"));
+        assert!(!block.contains("ppy.sh"));
+    }
+
+    #[test]
+    fn test_real_hosts_block() {
+        let block = generate_hosts_block(&real_hosts());
+        assert!(block.contains("127.0.0.1 c.localhost\n"));
+        assert!(block.contains("127.0.0.1 c.ppy.sh\n"));
+        assert!(block.contains("127.0.0.1 osu.ppy.sh\n"));
+    }
+
+    #[test]
+    fn test_add_block_with_and_without_trailing_newline() {
+        let block = generate_hosts_block(&HostsOptions::default());
+
+        let added = with_hosts_block("127.0.0.1 localhost\n", &block);
+        assert_eq!(added, format!("127.0.0.1 localhost\n{}\n", block));
+
+        let added = with_hosts_block("127.0.0.1 localhost", &block);
+        assert_eq!(added, format!("127.0.0.1 localhost\n{}\n", block));
+    }
+
+    #[test]
+    fn test_remove_block_with_and_without_trailing_newline() {
+        let block = generate_hosts_block(&HostsOptions::default());
+        let original = "127.0.0.1 localhost\n";
+
+        let added = with_hosts_block(original, &block);
+        assert_eq!(strip_hosts_blocks(&added), original);
+
+        // Block at the very end of a file with no final newline
+        let unterminated = added.trim_end_matches('\n');
+        assert_eq!(strip_hosts_blocks(unterminated), original);
+
+        assert_eq!(strip_hosts_blocks(original), original);
+    }
+
+    #[test]
+    fn test_removing_entries_twice_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hosts");
+        let original = "127.0.0.1 localhost\n";
+        let block = generate_hosts_block(&HostsOptions::default());
+        fs::write(&path, with_hosts_block(original, &block)).unwrap();
+
+        assert!(remove_hosts_entries_from(&path).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        assert!(!remove_hosts_entries_from(&path).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_doubled_blocks_collapse_to_one() {
+        let block = generate_hosts_block(&HostsOptions::default());
+        let stale = generate_hosts_block(&real_hosts());
+        let content = format!("127.0.0.1 localhost\n{}\n{}\n", stale, block);
+
+        let normalized = with_hosts_block(&strip_hosts_blocks(&content), &block);
+
+        assert_eq!(normalized, format!("127.0.0.1 localhost\n{}\n", block));
+        assert!(has_single_block(&normalized, &block));
+        assert!(!has_single_block(&content, &block));
+    }
+
+    #[test]
+    fn test_orphaned_markers_removed() {
+        let block = generate_hosts_block(&HostsOptions::default());
+        // An end left without its start, then a start cut off before its end
+        let content = format!(
+            "{}\n127.0.0.1 localhost\n{}\n127.0.0.1 c.localhost\n10.0.0.1 nas.lan\n",
+            HOSTS_MARKER_END, HOSTS_MARKER_START
+        );
+
+        let stripped = strip_hosts_blocks(&content);
+        assert_eq!(stripped, "127.0.0.1 localhost\n10.0.0.1 nas.lan\n");
+
+        let normalized = with_hosts_block(&stripped, &block);
+        assert_eq!(normalized.matches(HOSTS_MARKER_START).count(), 1);
+        assert!(has_single_block(&normalized, &block));
+    }
+
+    #[test]
+    fn test_extra_entries_merged_into_block() {
+        let options = HostsOptions {
+            intercept_real_hosts: false,
+            extra_entries: vec![
+                ("127.0.0.1".into(), "ce.localhost".into()),
+                ("::1".into(), "osu2.localhost".into()),
+            ],
+        };
+        let block = generate_hosts_block(&options);
+
+        assert!(options.validate().is_ok());
+        assert!(block.contains("127.0.0.1 c.localhost\n"));
+        assert!(block.contains("127.0.0.1 ce.localhost\n::1 osu2.localhost\n"));
+        assert!(block.ends_with(HOSTS_MARKER_END));
+    }
+
+    #[test]
+    fn test_invalid_extra_entries_rejected() {
+        let with_entry = |ip: &str, hostname: &str| HostsOptions {
+            intercept_real_hosts: false,
+            extra_entries: vec![(ip.into(), hostname.into())],
+        };
+
+        assert!(with_entry("127.0.0.1", "bad host").validate().is_err());
+        assert!(with_entry("127.0.0.1", "x.localhost\n# END rai-connect")
+            .validate()
+            .is_err());
+        assert!(with_entry("127.0.0.1", "-bad.localhost")
+            .validate()
+            .is_err());
+        assert!(with_entry("127.0.0.1", "a..localhost").validate().is_err());
+        assert!(with_entry("127.0.0.1", "").validate().is_err());
+        assert!(with_entry("localhost", "ce.localhost").validate().is_err());
+        assert!(with_entry("127.0.0.1", "osu-2.localhost")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_flush_dns_command_per_platform() {
+        assert_eq!(
+            flush_dns_command("windows"),
+            Some(("ipconfig", &["/flushdns"][..]))
+        );
+        assert_eq!(
+            flush_dns_command("macos"),
+            Some(("dscacheutil", &["-flushcache"][..]))
+        );
+        assert_eq!(
+            flush_dns_command("linux"),
+            Some(("resolvectl", &["flush-caches"][..]))
+        );
+        assert_eq!(flush_dns_command("freebsd"), None);
+    }
+
+    #[test]
+    fn test_is_real_host() {
+        assert!(is_real_host("c.ppy.sh"));
+        assert!(is_real_host("OSU.ppy.sh:443"));
+        assert!(!is_real_host("ppy.sh.evil.com"));
+        assert!(!is_real_host("c.localhost"));
     }
 }
@@ -13,26 +13,83 @@
 //!
 //! This selective routing ensures that only beatmap-related traffic goes through
 //! the mirror, while sensitive operations remain on official servers.
+//!
+//! # Local Endpoints
+//!
+//! A couple of paths are answered by the proxy itself, so tooling can check on
+//! it without going through the UI. They're never forwarded and don't count as
+//! proxied requests:
+//!
+//! - `GET` [`HEALTH_PATH`] returns a JSON summary of the proxy's state
+//! - `GET` [`METRICS_PATH`] returns counters in the Prometheus text format
+//!
+//! They're only answered on connections made over loopback, so a proxy
+//! listening on a LAN address doesn't expose them to the network.
+//!
+//! # CONNECT Tunnels
+//!
+//! The proxy also accepts `CONNECT`, so it can be configured as an HTTP proxy
+//! instead of relying on the hosts file. HTTP proxy clients send `CONNECT` in
+//! plain text, so a connection that doesn't open with a TLS handshake is
+//! served without TLS, but only `CONNECT` is accepted on it. Only tunnels to the official osu!
+//! hosts are accepted: they're terminated by the proxy itself and their
+//! requests are routed as usual. Any other target is refused with 403, so
+//! the proxy can't be used as a relay into other hosts or into services
+//! listening only on loopback. Like the local endpoints, tunnels are only
+//! opened for connections made over loopback. Intercepting a tunnel still means presenting
+//! the proxy's certificate, so it has to be trusted.
 
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::future::Future;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
-use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use flate2::read::GzDecoder;
+use http_body_util::{combinators::BoxBody, BodyExt, Full, LengthLimitError, Limited, StreamBody};
 use hyper::server::conn::http1;
-use hyper::service::service_fn;
-use hyper::{body::Incoming, Method, Request, Response, StatusCode};
+use hyper::service::{service_fn, HttpService};
+use hyper::{
+    body::{Body, Frame, Incoming},
+    header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, USER_AGENT},
+    Method, Request, Response, StatusCode,
+};
 use hyper_util::rt::TokioIo;
 use parking_lot::RwLock;
-use tokio::net::TcpListener;
-use tokio::sync::oneshot;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::io::ReaderStream;
+use tracing::Instrument;
 
 use crate::domain::{
-    inject_supporter_privileges, map_host_to_upstream, route_request, AppState, Packet,
-    RouteDecision, ServerPacketId,
+    apply_editors, inject_supporter_privileges, is_avatar_host, is_bypassed, is_keepalive_only,
+    map_avatar_to_raimoe_url, map_host_to_upstream, map_to_raimoe_url, parse_login_username,
+    route_request, AppState, EditOutcome, InjectionOutcome, MirrorEndpoints, Packet, PacketEditor,
+    ProxyConfig, RouteCategory, RouteDecision, SupporterMode,
 };
-use crate::infrastructure::tls::create_tls_acceptor;
+use crate::infrastructure::beatmap_cache::{BeatmapCache, CacheWriter, CachedBeatmap};
+use crate::infrastructure::body::{ChannelBody, CountingBody, MeteredBody};
+use crate::infrastructure::hosts;
+use crate::infrastructure::idle::{Activity, ActivityStream};
+use crate::infrastructure::logging::{sanitize_for_log, REDACTED, SENSITIVE_KEYS};
+use crate::infrastructure::metrics;
+use crate::infrastructure::packet_capture::PacketCapture;
+use crate::infrastructure::port::port_owner;
+use crate::infrastructure::rate_limit::MirrorRateLimiter;
+use crate::infrastructure::search_cache::SearchCache;
+use crate::infrastructure::throughput::TrafficMeters;
+
+/// How long in-flight connections are given to finish after shutdown is requested.
+pub const CONNECTION_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// First byte of a TLS handshake record, which every ClientHello starts with.
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
 
 /// Checks if host is localhost, 127.0.0.1, [::1], or *.localhost (with optional port).
 fn is_valid_localhost_host(host: &str) -> bool {
@@ -46,119 +103,693 @@ fn is_valid_localhost_host(host: &str) -> bool {
     h == "localhost" || h == "127.0.0.1" || h == "[::1]" || h.ends_with(".localhost")
 }
 
+/// Builds the HTTP client used for upstream requests, with connection pooling
+/// and the timeouts set in `config`.
+pub fn build_upstream_client(config: &ProxyConfig) -> reqwest::Client {
+    let mut builder = upstream_client_builder(config);
+    if config.upstream_request_timeout_secs > 0 {
+        builder = builder.timeout(Duration::from_secs(config.upstream_request_timeout_secs));
+    }
+    builder.build().unwrap_or_default()
+}
+
+/// Builds the HTTP client used for beatmap downloads.
+///
+/// Same as [`build_upstream_client`] but without an overall request timeout,
+/// since a large beatmapset can take minutes to arrive over a slow connection.
+pub fn build_download_client(config: &ProxyConfig) -> reqwest::Client {
+    upstream_client_builder(config).build().unwrap_or_default()
+}
+
+fn upstream_client_builder(config: &ProxyConfig) -> reqwest::ClientBuilder {
+    let mut builder = reqwest::Client::builder()
+        .pool_max_idle_per_host(10)
+        .pool_idle_timeout(Duration::from_secs(30));
+    if config.upstream_connect_timeout_secs > 0 {
+        builder =
+            builder.connect_timeout(Duration::from_secs(config.upstream_connect_timeout_secs));
+    }
+    builder
+}
+
+/// The pooled HTTP clients the proxy sends upstream requests with.
+///
+/// Kept by the proxy manager across restarts so each session doesn't start
+/// with a cold connection pool. Only rebuilt when the timeouts change.
+pub struct UpstreamClients {
+    /// Request and connect timeouts the clients were built with, in seconds.
+    timeouts: (u64, u64),
+    /// Client for everything except beatmap downloads.
+    pub general: reqwest::Client,
+    /// Client for `/d/` downloads, which has no overall request timeout.
+    pub download: reqwest::Client,
+}
+
+impl UpstreamClients {
+    pub fn new(config: &ProxyConfig) -> Self {
+        Self {
+            timeouts: Self::timeouts_of(config),
+            general: build_upstream_client(config),
+            download: build_download_client(config),
+        }
+    }
+
+    /// Whether these clients were built with the timeouts in `config`.
+    pub fn matches(&self, config: &ProxyConfig) -> bool {
+        self.timeouts == Self::timeouts_of(config)
+    }
+
+    fn timeouts_of(config: &ProxyConfig) -> (u64, u64) {
+        (
+            config.upstream_request_timeout_secs,
+            config.upstream_connect_timeout_secs,
+        )
+    }
+}
+
+/// Settings and shared handles needed by every request on the proxy.
+struct ProxyContext {
+    config: ProxyConfig,
+    /// Live supporter mode, which may change while the proxy runs.
+    supporter: Arc<RwLock<SupporterMode>>,
+    state: Arc<RwLock<AppState>>,
+    clients: Arc<UpstreamClients>,
+    meters: Arc<TrafficMeters>,
+    /// On-disk cache for `/d/` downloads, if enabled.
+    cache: Option<Arc<BeatmapCache>>,
+    /// When the proxy started listening, for the health endpoint's uptime.
+    started_at: Instant,
+    /// Limits on downloads and searches sent to the mirror.
+    rate_limiter: MirrorRateLimiter,
+    /// Recent osu!direct search results.
+    search_cache: SearchCache,
+    /// Terminates TLS inside CONNECT tunnels to osu! hosts. Without it every
+    /// CONNECT is refused.
+    tls_acceptor: Option<TlsAcceptor>,
+    /// Recent Bancho packets, recorded when `config.capture_packets` is on.
+    capture: Arc<PacketCapture>,
+}
+
+/// Marks a request that arrived inside an intercepted CONNECT tunnel, where
+/// the official osu! host names are expected in the `Host` header.
+#[derive(Clone, Copy)]
+struct Tunneled;
+
+/// Marks a request on a connection that didn't arrive over loopback, which
+/// happens only when `bind_host` isn't a loopback address. It can't open
+/// CONNECT tunnels or reach the local endpoints.
+#[derive(Clone, Copy)]
+struct Remote;
+
+/// Packet editor that injects supporter privileges into a Bancho response.
+#[derive(Clone, Copy)]
+struct Injection<'a> {
+    /// Report UserPrivileges packets with an unexpected layout.
+    strict: bool,
+    /// Where strict mode records layout warnings.
+    state: &'a RwLock<AppState>,
+}
+
+impl PacketEditor for Injection<'_> {
+    fn edit(&self, packet: &mut Packet) -> EditOutcome {
+        match inject_supporter_privileges(packet) {
+            InjectionOutcome::NotApplicable => EditOutcome::Unchanged,
+            InjectionOutcome::Injected => {
+                tracing::debug!("Injected supporter privileges into UserPrivileges packet");
+                EditOutcome::Modified
+            }
+            InjectionOutcome::UnexpectedLayout { payload_len } => {
+                if self.strict {
+                    report_unexpected_privileges_layout(payload_len, self.state);
+                }
+                EditOutcome::Modified
+            }
+        }
+    }
+}
+
+/// Packet editor that records every packet without changing it.
+struct Capture<'a>(&'a PacketCapture);
+
+impl PacketEditor for Capture<'_> {
+    fn edit(&self, packet: &mut Packet) -> EditOutcome {
+        self.0.record(packet);
+        EditOutcome::Unchanged
+    }
+}
+
+/// Builds the editors run over Bancho responses, in the order they apply.
+/// Empty when nothing needs rewriting, so responses are streamed through.
+fn bancho_editors(
+    mode: SupporterMode,
+    state: &RwLock<AppState>,
+) -> Vec<Box<dyn PacketEditor + '_>> {
+    let mut editors: Vec<Box<dyn PacketEditor + '_>> = Vec::new();
+    if mode.inject {
+        editors.push(Box::new(Injection {
+            strict: mode.strict,
+            state,
+        }));
+    }
+    editors
+}
+
 /// Runs the HTTPS proxy server with TLS.
 ///
 /// Listens on the specified port and handles incoming HTTPS requests from the
 /// osu! client using a self-signed certificate. This is required because osu!
 /// with `-devserver localhost` still uses HTTPS.
 ///
+/// The port is bound on `config.bind_host` (`127.0.0.1` by default) and,
+/// when that's an IPv4 address, on `::1` too, since `localhost` may resolve
+/// to either. Only the first listener is required; if IPv6 isn't available
+/// the proxy runs without it.
+///
 /// # Arguments
 ///
-/// * `port` - The local port to listen on (typically 443)
-/// * `direct_base_url` - Base URL for the rai.moe direct API
-/// * `inject_supporter` - If true, modifies Bancho responses to include supporter privileges
-/// * `upstream_server` - The upstream server domain (e.g., "ppy.sh" or "ripple.moe")
+/// * `config` - Proxy settings (port, mirror URL, upstream server, supporter
+///   injection, idle timeout)
 /// * `state` - Shared application state for tracking statistics
+/// * `supporter` - Supporter injection mode, read on every Bancho response so
+///   it can be changed while the proxy runs
+/// * `clients` - Pooled HTTP clients for upstream requests
+/// * `capture` - Where Bancho packets are recorded if `config.capture_packets`
+///   is on
+/// * `tls_acceptor` - Acceptor holding the proxy's certificate
 /// * `shutdown` - Receiver for graceful shutdown signal
 /// * `ready_tx` - Optional channel to signal when the server is ready
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` when the server shuts down gracefully, or an error if
-/// binding fails or TLS setup fails.
+/// Returns `Ok(())` when the server shuts down gracefully, or the
+/// [`io::Error`] if binding `config.bind_host` fails.
+///
+/// # Shutdown
+///
+/// On shutdown the listeners are closed first, then every open connection is
+/// asked to finish its current request and close. Connections still open
+/// after [`CONNECTION_DRAIN_TIMEOUT`] are aborted.
+///
+/// Connections with no traffic in either direction for
+/// `config.idle_timeout_secs` are closed as well.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_https_proxy(
-    port: u16,
-    direct_base_url: &str,
-    inject_supporter: bool,
-    upstream_server: &str,
+    config: &ProxyConfig,
     state: Arc<RwLock<AppState>>,
-    mut shutdown: oneshot::Receiver<()>,
+    supporter: Arc<RwLock<SupporterMode>>,
+    clients: Arc<UpstreamClients>,
+    capture: Arc<PacketCapture>,
+    tls_acceptor: TlsAcceptor,
+    shutdown: oneshot::Receiver<()>,
     ready_tx: Option<oneshot::Sender<()>>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let tls_acceptor = create_tls_acceptor()?;
+    // The io::Error itself is returned so the caller can tell why
+    let listeners = bind_listeners(config).await?;
+
+    // Shared by every connection: settings, state, pooled HTTP clients and meters
+    let ctx = ProxyContext {
+        config: config.clone(),
+        supporter,
+        state,
+        clients,
+        meters: Arc::new(TrafficMeters::default()),
+        cache: open_beatmap_cache(config),
+        started_at: Instant::now(),
+        rate_limiter: MirrorRateLimiter::new(config),
+        search_cache: SearchCache::default(),
+        tls_acceptor: Some(tls_acceptor.clone()),
+        capture,
+    };
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    let listener = TcpListener::bind(addr).await.map_err(|e| {
-        let msg = if e.kind() == std::io::ErrorKind::AddrInUse {
-            format!(
-                "Port {} is already in use. Please close any application using this port.",
-                port
-            )
-        } else if e.kind() == std::io::ErrorKind::PermissionDenied {
+    serve_https(listeners, tls_acceptor, ctx, shutdown, ready_tx).await
+}
+
+/// Binds the proxy's listeners as described on [`run_https_proxy`].
+async fn bind_listeners(config: &ProxyConfig) -> io::Result<Vec<TcpListener>> {
+    let port = config.https_port;
+    let ip: IpAddr = config.bind_host.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
             format!(
-                "Permission denied binding to port {}. Try running as Administrator.",
-                port
-            )
-        } else {
-            format!("Failed to bind to port {}: {}", port, e)
-        };
-        tracing::error!("{}", msg);
-        msg
+                "bind_host must be an IP address, got {:?}",
+                config.bind_host
+            ),
+        )
+    })?;
+
+    let addr = SocketAddr::new(ip, port);
+    let listener = TcpListener::bind(addr).await.inspect_err(|e| {
+        tracing::error!("{}", bind_error_message(port, e));
     })?;
 
     tracing::info!("HTTPS proxy listening on {}", addr);
+    if !ip.is_loopback() {
+        tracing::warn!(
+            "Listening on {}, which other machines can reach. CONNECT tunnels and the \
+             local health and metrics endpoints stay limited to this machine. The proxy's \
+             certificate only covers localhost, so osu! on another machine will reject it \
+             until this machine's LAN IP is added to the certificate's subject alternative names.",
+            addr
+        );
+    }
+    let mut listeners = vec![listener];
+
+    // Some resolvers hand out ::1 for localhost first, so listen there too.
+    // IPv6 may be disabled entirely, in which case IPv4 alone has to do.
+    if ip.is_ipv4() {
+        let addr_v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, port));
+        match TcpListener::bind(addr_v6).await {
+            Ok(listener) => {
+                tracing::info!("HTTPS proxy listening on {}", addr_v6);
+                listeners.push(listener);
+            }
+            Err(e) => tracing::warn!(
+                "Not listening on {}: {}",
+                addr_v6,
+                bind_error_message(port, &e)
+            ),
+        }
+    }
+
+    Ok(listeners)
+}
+
+/// Explains why binding `port` failed, naming the process in the way if the
+/// port is taken.
+fn bind_error_message(port: u16, e: &io::Error) -> String {
+    if e.kind() == io::ErrorKind::AddrInUse {
+        match port_owner(port) {
+            Some(process) => format!(
+                "Port {} is already in use by {}. Please close it and try again.",
+                port, process
+            ),
+            None => format!(
+                "Port {} is already in use. Please close any application using this port.",
+                port
+            ),
+        }
+    } else if e.kind() == io::ErrorKind::PermissionDenied {
+        format!(
+            "Permission denied binding to port {}. Try running as Administrator.",
+            port
+        )
+    } else {
+        format!("Failed to bind to port {}: {}", port, e)
+    }
+}
+
+/// Accepts connections on already bound `listeners` and serves each one
+/// until `shutdown` fires, then drains them.
+///
+/// A connection opening with a TLS handshake is served as HTTPS; anything
+/// else is read as a plain-text CONNECT. `ready_tx` is signalled once the
+/// loop starts. Returns the error if accepting fails for good, after the
+/// open connections have drained.
+async fn serve_https<L: Listener>(
+    listeners: Vec<L>,
+    tls_acceptor: TlsAcceptor,
+    ctx: ProxyContext,
+    mut shutdown: oneshot::Receiver<()>,
+    ready_tx: Option<oneshot::Sender<()>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let idle_timeout = Duration::from_secs(ctx.config.idle_timeout_secs);
+    let state = Arc::clone(&ctx.state);
+    let ctx = Arc::new(ctx);
 
     // Signal that we're ready (port is bound)
     if let Some(tx) = ready_tx {
         let _ = tx.send(());
     }
 
-    let direct_base_url = direct_base_url.to_string();
-    let upstream_server = upstream_server.to_string();
+    let mut rate_ticker = tokio::time::interval(Duration::from_secs(1));
 
-    // Create a shared HTTP client with connection pooling and timeouts
-    let client = Arc::new(
-        reqwest::Client::builder()
-            .pool_max_idle_per_host(10)
-            .pool_idle_timeout(std::time::Duration::from_secs(30))
-            .timeout(std::time::Duration::from_secs(30))
-            .connect_timeout(std::time::Duration::from_secs(10))
-            .build()
-            .unwrap_or_default(),
-    );
+    // Flipped to true on shutdown so open connections can close gracefully
+    let (drain_tx, drain_rx) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    let mut next_connection_id: u64 = 0;
 
-    loop {
+    // Kept across iterations, so the other branches firing doesn't reset the
+    // retry backoff of a failing accept
+    let next_accept = || accept_next(|| accept_any(&listeners));
+    let mut accept = Box::pin(next_accept());
+
+    let fatal = loop {
         tokio::select! {
-            result = listener.accept() => {
-                let (stream, client_addr) = result?;
+            result = &mut accept => {
+                accept.set(next_accept());
+                let (stream, client_addr) = match result {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::error!("HTTPS proxy stopped accepting connections: {}", e);
+                        break Some(e);
+                    }
+                };
 
-                let tls_acceptor = tls_acceptor.clone();
-                let state = Arc::clone(&state);
-                let direct_base_url = direct_base_url.clone();
-                let upstream_server = upstream_server.clone();
-                let client = Arc::clone(&client);
+                // Everything logged for this connection, its requests included,
+                // carries the id and client address
+                next_connection_id += 1;
+                let span = tracing::info_span!(
+                    "connection",
+                    conn_id = next_connection_id,
+                    client = %client_addr
+                );
 
-                tokio::spawn(async move {
-                    let tls_stream = match tls_acceptor.accept(stream).await {
-                        Ok(s) => s,
-                        Err(e) => {
-                            tracing::debug!("TLS handshake failed from {}: {}", client_addr, e);
+                let tls_acceptor = tls_acceptor.clone();
+                let ctx = Arc::clone(&ctx);
+                let drain_rx = drain_rx.clone();
+                let guard = ConnectionGuard::new(Arc::clone(&state));
+
+                connections.spawn(async move {
+                    let _guard = guard;
+                    let activity = Activity::new();
+                    let remote = !is_loopback_connection(&stream);
+
+                    // HTTP proxy clients send CONNECT in plain text, before any TLS
+                    let mut first = [0u8; 1];
+                    let peeked = tokio::select! {
+                        result = stream.peek(&mut first) => Some(result),
+                        _ = activity.idle_for(idle_timeout), if !idle_timeout.is_zero() => None,
+                    };
+                    let stream = ActivityStream::new(stream, activity.clone());
+
+                    match peeked {
+                        Some(Ok(1)) if first[0] != TLS_HANDSHAKE_RECORD => {
+                            let service = service_fn(move |mut req: Request<Incoming>| {
+                                if remote {
+                                    req.extensions_mut().insert(Remote);
+                                }
+                                handle_plain_request(req, Arc::clone(&ctx))
+                            });
+                            serve_connection(stream, &activity, service, idle_timeout, drain_rx, client_addr).await;
                             return;
                         }
-                    };
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => {
+                            tracing::debug!("Failed to read from {}: {}", client_addr, e);
+                            return;
+                        }
+                        None => {
+                            tracing::info!(
+                                "Closing connection from {}: nothing received within {}s",
+                                client_addr,
+                                idle_timeout.as_secs()
+                            );
+                            return;
+                        }
+                    }
 
-                    let io = TokioIo::new(tls_stream);
+                    // A client that never completes the handshake counts as idle too
+                    let handshake = tokio::select! {
+                        result = tls_acceptor.accept(stream) => Some(result),
+                        _ = activity.idle_for(idle_timeout), if !idle_timeout.is_zero() => None,
+                    };
 
-                    let service = service_fn(move |req| {
-                        handle_request(req, direct_base_url.clone(), inject_supporter, upstream_server.clone(), Arc::clone(&state), Arc::clone(&client))
-                    });
+                    match handshake {
+                        Some(Ok(tls_stream)) => {
+                            let service = service_fn(move |mut req: Request<Incoming>| {
+                                if remote {
+                                    req.extensions_mut().insert(Remote);
+                                }
+                                handle_request(req, Arc::clone(&ctx))
+                            });
 
-                    if let Err(err) = http1::Builder::new()
-                        .serve_connection(io, service)
-                        .await
-                    {
-                        tracing::debug!("Connection error from {}: {:?}", client_addr, err);
+                            serve_connection(tls_stream, &activity, service, idle_timeout, drain_rx, client_addr).await;
+                        }
+                        Some(Err(e)) if is_certificate_rejection(&e) => {
+                            tracing::warn!(
+                                "TLS handshake from {} failed because the client rejected the proxy's certificate ({}). The certificate is probably not installed or not trusted.",
+                                client_addr,
+                                e
+                            );
+                        }
+                        Some(Err(e)) => {
+                            tracing::debug!("TLS handshake failed from {}: {}", client_addr, e);
+                        }
+                        None => {
+                            tracing::info!(
+                                "Closing connection from {}: no TLS handshake within {}s",
+                                client_addr,
+                                idle_timeout.as_secs()
+                            );
+                        }
                     }
-                });
+                }.instrument(span));
+            }
+            // Reap finished connection tasks so the set doesn't grow unbounded
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = rate_ticker.tick() => {
+                let mut s = state.write();
+                s.upload_bps = ctx.meters.upload.bytes_per_second();
+                s.download_bps = ctx.meters.download.bytes_per_second();
             }
             _ = &mut shutdown => {
                 tracing::info!("HTTPS proxy shutting down");
-                break;
+                break None;
+            }
+        }
+    };
+
+    // It borrows the listeners, which are closed next
+    drop(accept);
+    drain_connections(listeners, drain_tx, connections).await;
+
+    {
+        let mut s = state.write();
+        s.upload_bps = 0;
+        s.download_bps = 0;
+    }
+
+    match fatal {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Opens the download cache in the app data directory, unless disabled.
+fn open_beatmap_cache(config: &ProxyConfig) -> Option<Arc<BeatmapCache>> {
+    if config.beatmap_cache_max_bytes == 0 {
+        return None;
+    }
+
+    let Some(dir) = BeatmapCache::default_dir() else {
+        tracing::warn!("Could not find local app data directory, beatmap cache disabled");
+        return None;
+    };
+
+    Some(Arc::new(BeatmapCache::new(
+        dir,
+        config.beatmap_cache_max_bytes,
+    )))
+}
+
+/// Delay before retrying after the first failed accept; doubles per failure.
+const ACCEPT_RETRY_BASE_DELAY: Duration = Duration::from_millis(10);
+/// Upper bound on the delay between accept retries.
+const ACCEPT_RETRY_MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Accepts the next connection, riding out transient errors.
+///
+/// Errors such as running out of file descriptors (EMFILE) or a connection
+/// aborted before it was accepted are logged and retried after a delay that
+/// grows with consecutive failures, so one bad accept can't stop the proxy.
+/// Only errors meaning the listener itself is unusable are returned.
+async fn accept_next<T, F, Fut>(mut accept: F) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut failures: u32 = 0;
+
+    loop {
+        match accept().await {
+            Ok(conn) => return Ok(conn),
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => return Err(e),
+            Err(e) => {
+                let delay = ACCEPT_RETRY_BASE_DELAY
+                    .saturating_mul(1 << failures.min(16))
+                    .min(ACCEPT_RETRY_MAX_DELAY);
+                failures += 1;
+                tracing::warn!(
+                    "Failed to accept connection: {} (retrying in {}ms)",
+                    e,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Why a served connection ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectionEnd {
+    /// The client closed the connection (or it failed with an error).
+    Closed,
+    /// Shutdown was requested and the connection finished gracefully.
+    Drained,
+    /// No bytes moved in either direction for the idle timeout.
+    IdleTimeout,
+}
+
+/// Serves HTTP/1 on an established connection.
+///
+/// Runs until the client disconnects, shutdown is requested via `drain`, or no
+/// bytes flow in either direction for `idle_timeout` (zero disables the
+/// timeout). Dropping the connection on timeout closes both halves, so a
+/// half-open client can't pin the task forever. The keepalive polls osu!
+/// sends while the user is idle count as traffic, so they keep it open.
+async fn serve_connection<T, S>(
+    io: T,
+    activity: &Activity,
+    service: S,
+    idle_timeout: Duration,
+    mut drain: watch::Receiver<bool>,
+    client_addr: SocketAddr,
+) -> ConnectionEnd
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: HttpService<Incoming>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S::ResBody: 'static,
+    <S::ResBody as hyper::body::Body>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let conn = http1::Builder::new()
+        .serve_connection(TokioIo::new(io), service)
+        .with_upgrades();
+    tokio::pin!(conn);
+
+    let (result, end) = tokio::select! {
+        result = conn.as_mut() => (result, ConnectionEnd::Closed),
+        _ = drain.changed() => {
+            conn.as_mut().graceful_shutdown();
+            (conn.await, ConnectionEnd::Drained)
+        }
+        _ = activity.idle_for(idle_timeout), if !idle_timeout.is_zero() => {
+            tracing::info!(
+                "Closing idle connection from {}: no traffic for {}s",
+                client_addr,
+                idle_timeout.as_secs()
+            );
+            return ConnectionEnd::IdleTimeout;
+        }
+    };
+
+    if let Err(err) = result {
+        tracing::debug!("Connection error from {}: {:?}", client_addr, err);
+    }
+
+    end
+}
+
+/// Whether a failed TLS handshake was the client refusing our certificate,
+/// as opposed to a client that doesn't speak TLS or hung up.
+fn is_certificate_rejection(e: &io::Error) -> bool {
+    use rustls::AlertDescription;
+
+    let Some(rustls::Error::AlertReceived(alert)) = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    else {
+        return false;
+    };
+
+    matches!(
+        alert,
+        AlertDescription::BadCertificate
+            | AlertDescription::UnknownCA
+            | AlertDescription::CertificateUnknown
+            | AlertDescription::CertificateExpired
+            | AlertDescription::UnsupportedCertificate
+    )
+}
+
+/// Whether `stream` was made to a loopback address, and so came from this
+/// machine.
+fn is_loopback_connection(stream: &TcpStream) -> bool {
+    stream
+        .local_addr()
+        .is_ok_and(|addr| addr.ip().to_canonical().is_loopback())
+}
+
+/// Where [`serve_https`] takes connections from: a [`TcpListener`] outside
+/// of tests.
+trait Listener: Send + Sync + 'static {
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>>;
+}
+
+impl Listener for TcpListener {
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+        TcpListener::poll_accept(self, cx)
+    }
+}
+
+/// Accepts the next connection on whichever of `listeners` has one first.
+async fn accept_any<L: Listener>(listeners: &[L]) -> io::Result<(TcpStream, SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for listener in listeners {
+            if let Poll::Ready(result) = listener.poll_accept(cx) {
+                return Poll::Ready(result);
             }
         }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Closes the listeners and waits for open connections to finish.
+///
+/// Connections that are still open after [`CONNECTION_DRAIN_TIMEOUT`] are
+/// aborted so a lingering client can't hold up a restart.
+async fn drain_connections<L>(
+    listeners: Vec<L>,
+    drain_tx: watch::Sender<bool>,
+    mut connections: JoinSet<()>,
+) {
+    drop(listeners);
+    let _ = drain_tx.send(true);
+
+    if connections.is_empty() {
+        return;
+    }
+
+    tracing::info!("Draining {} open connection(s)", connections.len());
+
+    let drained = tokio::time::timeout(CONNECTION_DRAIN_TIMEOUT, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+
+    if drained.is_err() {
+        tracing::warn!(
+            "{} connection(s) still open after {}s, closing them",
+            connections.len(),
+            CONNECTION_DRAIN_TIMEOUT.as_secs()
+        );
+        connections.shutdown().await;
+    }
+}
+
+/// Counts a client connection in `active_connections` for as long as the
+/// guard lives.
+///
+/// The count is released on drop, so it stays accurate whether the
+/// connection ends normally, fails its TLS handshake or has its task aborted.
+struct ConnectionGuard {
+    state: Arc<RwLock<AppState>>,
+}
+
+impl ConnectionGuard {
+    fn new(state: Arc<RwLock<AppState>>) -> Self {
+        state.write().active_connections += 1;
+        Self { state }
     }
+}
 
-    Ok(())
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut s = self.state.write();
+        s.active_connections = s.active_connections.saturating_sub(1);
+    }
 }
 
 /// Handles a single HTTP request from the osu! client.
@@ -167,27 +798,40 @@ pub async fn run_https_proxy(
 /// decision, updates statistics, and forwards the request to the appropriate
 /// upstream server.
 ///
+/// Paths listed in `bypass_paths` are checked before anything else, including
+/// custom routing rules: they go straight to the official servers without
+/// being logged, counted or modified.
+///
+/// The request body is read up front, and a body larger than
+/// `max_request_body_bytes` is answered with 413 without contacting any
+/// server.
+///
 /// # Arguments
 ///
 /// * `req` - The incoming HTTP request
-/// * `direct_base_url` - Base URL for rai.moe direct API
-/// * `inject_supporter` - Whether to inject supporter privileges in Bancho responses
-/// * `upstream_server` - The upstream server domain (e.g., "ppy.sh" or "ripple.moe")
-/// * `state` - Shared application state for statistics
-/// * `client` - Shared HTTP client for upstream requests
+/// * `ctx` - Proxy settings, shared state for statistics, the upstream HTTP
+///   client and throughput meters
 ///
 /// # Returns
 ///
 /// Always returns `Ok` with an HTTP response. Errors from upstream servers
 /// are converted to 502 Bad Gateway responses.
-async fn handle_request(
-    req: Request<Incoming>,
-    direct_base_url: String,
-    inject_supporter: bool,
-    upstream_server: String,
-    state: Arc<RwLock<AppState>>,
-    client: Arc<reqwest::Client>,
-) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible> {
+async fn handle_request<B>(
+    req: Request<B>,
+    ctx: Arc<ProxyContext>,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let remote = req.extensions().get::<Remote>().is_some();
+    if req.method() == Method::CONNECT {
+        if remote {
+            return Ok(local_only_response());
+        }
+        return Ok(handle_connect(req, ctx));
+    }
+
     let host = req
         .headers()
         .get("host")
@@ -195,7 +839,10 @@ async fn handle_request(
         .unwrap_or("localhost")
         .to_string();
 
-    if !is_valid_localhost_host(&host) {
+    let tunneled = req.extensions().get::<Tunneled>().is_some();
+    let allowed = is_valid_localhost_host(&host)
+        || ((ctx.config.intercept_real_hosts || tunneled) && hosts::is_real_host(&host));
+    if !allowed {
         tracing::warn!(
             "Rejected request with invalid host header: {} (expected localhost)",
             host
@@ -206,140 +853,897 @@ async fn handle_request(
         ));
     }
 
+    match req.uri().path() {
+        HEALTH_PATH | METRICS_PATH if remote => return Ok(local_only_response()),
+        HEALTH_PATH => return Ok(health_response(req.method(), &ctx)),
+        METRICS_PATH => return Ok(metrics_response(req.method(), &ctx)),
+        _ => {}
+    }
+
+    let req = match read_limited_body(req, ctx.config.max_request_body_bytes).await {
+        Ok(req) => req,
+        Err(resp) => return Ok(resp),
+    };
+
     let path = req
         .uri()
         .path_and_query()
         .map(|pq| pq.as_str())
         .unwrap_or("/");
 
-    tracing::debug!("Request: {} {} (host: {})", req.method(), path, &host);
+    if is_bypassed(path, &ctx.config.bypass_paths) {
+        return Ok(forward_bypassed(req, &host, &ctx).await);
+    }
+
+    tracing::debug!(
+        "Request: {} {} (host: {})",
+        req.method(),
+        sanitize_for_log(path),
+        &host
+    );
+
+    let tapped = is_tapped(path, ctx.config.debug_tap.as_deref());
+    if tapped {
+        log_tapped_request(req.method(), &host, path, req.headers());
+    }
+
+    let decision = route_request(
+        &host,
+        path,
+        &ctx.config.routes,
+        MirrorEndpoints::from_config(&ctx.config),
+    );
 
-    let decision = route_request(&host, path);
+    // A cached search never reaches the mirror, so it isn't rate limited
+    let search_key = (decision == RouteDecision::HandleLocally && req.method() == Method::GET)
+        .then(|| SearchCache::key_for_path(path))
+        .flatten();
+    let search_hit = search_key
+        .as_deref()
+        .and_then(|key| ctx.search_cache.get(key));
+
+    // Nor is a download served from the beatmap cache
+    let beatmap_cached = decision == RouteDecision::HandleLocally
+        && ctx.cache.as_ref().is_some_and(|cache| {
+            beatmap_cache_key(&req, path).is_some_and(|key| cache.contains(&key))
+        });
+
+    if decision == RouteDecision::HandleLocally && search_hit.is_none() && !beatmap_cached {
+        if let Err((kind, retry_after)) = ctx.rate_limiter.check(path) {
+            tracing::warn!(
+                "Rate limited {:?} request to the mirror: {}",
+                kind,
+                sanitize_for_log(path)
+            );
+            return Ok(too_many_requests_response(retry_after));
+        }
+    }
 
     {
-        let mut s = state.write();
+        let mut s = ctx.state.write();
         s.requests_proxied += 1;
+        s.route_stats
+            .record(RouteCategory::classify(decision, req.uri().path()));
     }
 
-    let response = match decision {
-        RouteDecision::HandleLocally => {
-            if path.starts_with("/d/") {
-                let mut s = state.write();
-                s.beatmaps_downloaded += 1;
+    ctx.meters
+        .upload
+        .record(req.body().size_hint().exact().unwrap_or(0));
+
+    let mut response = match decision {
+        RouteDecision::HandleLocally => match search_hit {
+            Some(hit) => {
+                tracing::debug!("Serving {} from search cache", sanitize_for_log(path));
+                cached_response(hit.data, hit.content_type)
             }
-            forward_to_raimoe(req, &direct_base_url, &client).await
-        }
-        RouteDecision::ForwardToUpstream => {
-            forward_to_upstream(req, &host, inject_supporter, &upstream_server, &client).await
-        }
+            None => {
+                let resp = forward_to_mirror(req, &host, &ctx).await;
+                match search_key {
+                    Some(key) if resp.status() == StatusCode::OK => {
+                        cache_search_response(resp, &ctx.search_cache, key).await
+                    }
+                    _ => resp,
+                }
+            }
+        },
+        RouteDecision::ForwardToUpstream => forward_to_upstream(req, &host, &ctx).await,
         RouteDecision::RedirectToUpstream => {
-            let upstream_host = map_host_to_upstream(&host, &upstream_server);
+            let upstream_host = map_host_to_upstream(&host, &ctx.config.upstream_server);
             let redirect_url = format!("https://{}{}", upstream_host, path);
-            tracing::debug!("Redirecting to: {}", redirect_url);
+            tracing::debug!("Redirecting to: {}", sanitize_for_log(&redirect_url));
             redirect_response(&redirect_url)
         }
     };
 
-    Ok(response)
-}
+    response.headers_mut().insert(
+        ROUTE_HEADER,
+        HeaderValue::from_static(route_header_value(decision)),
+    );
 
-/// Forwards a request to the rai.moe beatmap mirror.
-///
-/// Constructs the target URL by appending the request path to the direct
-/// base URL and forwards the request with all original headers (except
-/// hop-by-hop headers).
-///
-/// # Arguments
-///
-/// * `req` - The incoming HTTP request
-/// * `direct_base_url` - Base URL for rai.moe (e.g., `https://direct.rai.moe`)
-/// * `client` - HTTP client for making the upstream request
-///
-/// # Returns
-///
-/// The response from rai.moe, or a 502 Bad Gateway response on failure.
-async fn forward_to_raimoe(
-    req: Request<Incoming>,
-    direct_base_url: &str,
-    client: &reqwest::Client,
-) -> Response<BoxBody<Bytes, Infallible>> {
-    let path = req
-        .uri()
-        .path_and_query()
-        .map(|pq| pq.as_str())
-        .unwrap_or("/");
-    let url = format!("{}{}", direct_base_url.trim_end_matches('/'), path);
+    if tapped {
+        response = tap_response(response).await;
+    }
 
-    tracing::debug!("Forwarding to rai.moe: {}", url);
+    // Streamed bodies are measured as they're sent rather than up front
+    let response = response.map(|body| MeteredBody::new(body, Arc::clone(&ctx.meters)).boxed());
 
-    match forward_request(req, &url, client).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            tracing::error!("Failed to forward to rai.moe: {}", e);
-            error_response(StatusCode::BAD_GATEWAY, "Failed to reach rai.moe")
-        }
-    }
+    Ok(response)
 }
 
-async fn forward_to_upstream(
-    req: Request<Incoming>,
+/// Sends a request routed to the mirror, falling back to the official
+/// servers if that's enabled and the mirror fails.
+async fn forward_to_mirror(
+    req: Request<Full<Bytes>>,
     host: &str,
-    inject_supporter: bool,
-    upstream_server: &str,
-    client: &reqwest::Client,
+    ctx: &ProxyContext,
 ) -> Response<BoxBody<Bytes, Infallible>> {
-    let upstream_host = map_host_to_upstream(host, upstream_server);
-    let path = req
-        .uri()
-        .path_and_query()
-        .map(|pq| pq.as_str())
-        .unwrap_or("/");
-    let url = format!("https://{}{}", upstream_host, path);
-
-    tracing::debug!("Forwarding to {}: {}", upstream_server, url);
-
-    let is_bancho = upstream_host.starts_with("c.");
+    let is_download = req.uri().path().starts_with("/d/");
+    if is_download {
+        let mut s = ctx.state.write();
+        s.beatmaps_downloaded += 1;
+    }
+    let client = if is_download {
+        &ctx.clients.download
+    } else {
+        &ctx.clients.general
+    };
 
-    match forward_request_with_injection(req, &url, client, inject_supporter && is_bancho).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            tracing::error!("Failed to forward to {}: {}", upstream_server, e);
-            error_response(StatusCode::BAD_GATEWAY, "Failed to reach osu! servers")
+    // Only GETs are safe to send twice
+    let fallback = (ctx.config.mirror_fallback_to_official && req.method() == Method::GET)
+        .then(|| copy_request_head(&req));
+
+    let resp = forward_to_raimoe(
+        req,
+        &ctx.config.direct_base_url,
+        client,
+        ctx.cache.as_ref(),
+        ctx.config.upstream_user_agent.as_deref(),
+    )
+    .await;
+
+    match fallback {
+        Some(fallback) if resp.status().is_server_error() => {
+            tracing::warn!(
+                "Mirror answered {}, falling back to {}",
+                resp.status(),
+                ctx.config.upstream_server
+            );
+            forward_to_upstream(fallback, host, ctx).await
         }
+        _ => resp,
     }
 }
 
-async fn forward_request(
-    req: Request<Incoming>,
+/// Reads a search response in full and stores it in `cache` before passing
+/// it on.
+async fn cache_search_response(
+    resp: Response<BoxBody<Bytes, Infallible>>,
+    cache: &SearchCache,
+    key: String,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let (parts, body) = resp.into_parts();
+    let Ok(collected) = body.collect().await;
+    let data = collected.to_bytes();
+
+    let content_type = parts
+        .headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    cache.insert(key, data.clone(), content_type);
+
+    Response::from_parts(parts, Full::new(data).boxed())
+}
+
+/// Copies the method, URI and headers of a bodiless request so it can be
+/// sent a second time.
+fn copy_request_head(req: &Request<Full<Bytes>>) -> Request<Full<Bytes>> {
+    let mut copy = Request::new(Full::new(Bytes::new()));
+    *copy.method_mut() = req.method().clone();
+    *copy.uri_mut() = req.uri().clone();
+    *copy.headers_mut() = req.headers().clone();
+    copy
+}
+
+/// Handles a request on a connection that didn't open with a TLS handshake.
+///
+/// Only `CONNECT` is accepted there, from clients using the proxy as an HTTP
+/// proxy. Anything else is refused and the connection closed, since osu!
+/// itself always speaks TLS to the proxy.
+async fn handle_plain_request<B>(
+    req: Request<B>,
+    ctx: Arc<ProxyContext>,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    if req.method() != Method::CONNECT {
+        let mut resp = error_response(
+            StatusCode::BAD_REQUEST,
+            "Only CONNECT is accepted without TLS",
+        );
+        resp.headers_mut()
+            .insert(hyper::header::CONNECTION, HeaderValue::from_static("close"));
+        return Ok(resp);
+    }
+    handle_request(req, ctx).await
+}
+
+/// Accepts a `CONNECT` request and sets up the tunnel once the client's
+/// connection is upgraded.
+///
+/// Tunnels to the official osu! hosts are terminated here and served like
+/// any other connection, so their requests go through the usual routing.
+/// Every other target is refused with 403.
+fn handle_connect<B>(
+    mut req: Request<B>,
+    ctx: Arc<ProxyContext>,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let Some(target) = req.uri().authority().map(|a| a.to_string()) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "CONNECT requires a host:port target",
+        );
+    };
+
+    let Some(acceptor) = ctx
+        .tls_acceptor
+        .clone()
+        .filter(|_| hosts::is_real_host(&target))
+    else {
+        tracing::warn!("Refused CONNECT to {}: not an osu! host", target);
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "CONNECT is only allowed to the osu! servers",
+        );
+    };
+    let on_upgrade = hyper::upgrade::on(&mut req);
+
+    let tunnel = async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => TokioIo::new(upgraded),
+            Err(e) => {
+                tracing::debug!("CONNECT to {} was not upgraded: {}", target, e);
+                return;
+            }
+        };
+
+        tracing::debug!("Intercepting CONNECT tunnel to {}", target);
+        serve_tunnel(upgraded, acceptor, ctx).await;
+    };
+    // The tunnel outlives this request, but still belongs to its connection
+    tokio::spawn(tunnel.in_current_span());
+
+    Response::new(Full::new(Bytes::new()).map_err(|_| unreachable!()).boxed())
+}
+
+/// Terminates TLS inside an intercepted tunnel and serves its requests.
+async fn serve_tunnel<T>(io: T, acceptor: TlsAcceptor, ctx: Arc<ProxyContext>)
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let tls_stream = match acceptor.accept(io).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::debug!("TLS handshake inside CONNECT tunnel failed: {}", e);
+            return;
+        }
+    };
+
+    let service = service_fn(move |mut req: Request<Incoming>| {
+        req.extensions_mut().insert(Tunneled);
+        handle_request(req, Arc::clone(&ctx))
+    });
+    if let Err(e) = http1::Builder::new()
+        .serve_connection(TokioIo::new(tls_stream), service)
+        .await
+    {
+        tracing::debug!("CONNECT tunnel connection error: {:?}", e);
+    }
+}
+
+/// Reads a request body into memory, refusing bodies over `max_bytes`.
+///
+/// Every forwarded body is buffered before it's sent upstream, so the limit is
+/// enforced here, while the body is still being read. Returns the error
+/// response to send if the body is too large or couldn't be read.
+async fn read_limited_body<B>(
+    req: Request<B>,
+    max_bytes: u64,
+) -> Result<Request<Full<Bytes>>, Response<BoxBody<Bytes, Infallible>>>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let (parts, body) = req.into_parts();
+    let limit = usize::try_from(max_bytes).unwrap_or(usize::MAX);
+
+    match Limited::new(body, limit).collect().await {
+        Ok(collected) => Ok(Request::from_parts(parts, Full::new(collected.to_bytes()))),
+        Err(e) if e.is::<LengthLimitError>() => {
+            tracing::warn!(
+                "Rejected {} {} with a body over {} bytes",
+                parts.method,
+                sanitize_for_log(parts.uri.path()),
+                max_bytes
+            );
+            Err(error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Request body too large",
+            ))
+        }
+        Err(_) => Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "Failed to read request body",
+        )),
+    }
+}
+
+/// Number of response body bytes the debug tap dumps.
+const TAP_BODY_DUMP_LIMIT: usize = 256;
+
+/// Whether `path` matches the configured debug tap.
+fn is_tapped(path: &str, tap: Option<&str>) -> bool {
+    tap.is_some_and(|tap| !tap.is_empty() && path.contains(tap))
+}
+
+/// Formats headers one per line, redacting credentials.
+fn format_tap_headers(headers: &hyper::HeaderMap) -> String {
+    let lines = headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_KEYS.contains(&name.as_str()) {
+                REDACTED.into()
+            } else {
+                String::from_utf8_lossy(value.as_bytes())
+            };
+            format!("  {}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    sanitize_for_log(&lines)
+}
+
+/// Formats up to `limit` bytes as space-separated hex, noting how many more
+/// were left out.
+fn hex_dump(data: &[u8], limit: usize) -> String {
+    let mut dump = data
+        .iter()
+        .take(limit)
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if data.len() > limit {
+        dump.push_str(&format!(" ... ({} more bytes)", data.len() - limit));
+    }
+    dump
+}
+
+fn log_tapped_request(method: &Method, host: &str, path: &str, headers: &hyper::HeaderMap) {
+    tracing::info!(
+        "Tap: {} https://{}{}\n{}",
+        method,
+        host,
+        sanitize_for_log(path),
+        format_tap_headers(headers)
+    );
+}
+
+/// Logs a tapped response. The body is buffered to dump it, then sent on
+/// unchanged.
+async fn tap_response(
+    response: Response<BoxBody<Bytes, Infallible>>,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let (parts, body) = response.into_parts();
+    let bytes = body
+        .collect()
+        .await
+        .map(|c| c.to_bytes())
+        .unwrap_or_default();
+
+    tracing::info!(
+        "Tap: response {} ({} bytes)\n{}\n  body: {}",
+        parts.status,
+        bytes.len(),
+        format_tap_headers(&parts.headers),
+        hex_dump(&bytes, TAP_BODY_DUMP_LIMIT)
+    );
+
+    Response::from_parts(parts, Full::new(bytes).map_err(|_| unreachable!()).boxed())
+}
+
+/// Response header recording how the proxy routed a request, so the
+/// self-test can tell whether a request really went to the mirror.
+pub const ROUTE_HEADER: &str = "x-rai-connect-route";
+
+/// Value of [`ROUTE_HEADER`] for `decision`, matching its serialized name.
+pub fn route_header_value(decision: RouteDecision) -> &'static str {
+    match decision {
+        RouteDecision::HandleLocally => "handle_locally",
+        RouteDecision::ForwardToUpstream => "forward_to_upstream",
+        RouteDecision::RedirectToUpstream => "redirect_to_upstream",
+    }
+}
+
+/// Path of the proxy's own health endpoint.
+pub const HEALTH_PATH: &str = "/__raiconnect/health";
+
+/// Answers a request to [`HEALTH_PATH`] with the proxy's status, uptime and
+/// counters as JSON.
+fn health_response(method: &Method, ctx: &ProxyContext) -> Response<BoxBody<Bytes, Infallible>> {
+    if method != Method::GET {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+    }
+
+    let body = {
+        let s = ctx.state.read();
+        serde_json::json!({
+            "status": s.status,
+            "uptime_secs": ctx.started_at.elapsed().as_secs(),
+            "requests_proxied": s.requests_proxied,
+            "beatmaps_downloaded": s.beatmaps_downloaded,
+            "active_connections": s.active_connections,
+        })
+    };
+
+    Response::builder()
+        .header("content-type", "application/json")
+        .header("cache-control", "no-store")
+        .body(
+            Full::new(Bytes::from(body.to_string()))
+                .map_err(|_| unreachable!())
+                .boxed(),
+        )
+        .unwrap()
+}
+
+/// Path of the proxy's Prometheus metrics endpoint.
+pub const METRICS_PATH: &str = "/__raiconnect/metrics";
+
+/// Answers a request to [`METRICS_PATH`] with the counters from [`AppState`].
+fn metrics_response(method: &Method, ctx: &ProxyContext) -> Response<BoxBody<Bytes, Infallible>> {
+    if method != Method::GET {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+    }
+
+    let body = metrics::render(&ctx.state.read());
+
+    Response::builder()
+        .header("content-type", metrics::CONTENT_TYPE)
+        .header("cache-control", "no-store")
+        .body(
+            Full::new(Bytes::from(body))
+                .map_err(|_| unreachable!())
+                .boxed(),
+        )
+        .unwrap()
+}
+
+/// Forwards a request to the rai.moe beatmap mirror.
+///
+/// Constructs the target URL by appending the request path to the direct
+/// base URL and forwards the request with all original headers (except
+/// hop-by-hop headers).
+///
+/// Beatmap downloads (`GET /d/{id}`) are served from `cache` when present
+/// there, and successful downloads are added to it.
+///
+/// The client's original `Host` is passed along as `X-Original-Host`, so the
+/// mirror can tell which osu! subdomain (e.g. `b.` for thumbnails) was meant.
+/// Avatar requests are rewritten to the mirror's `/a/` path.
+///
+/// # Arguments
+///
+/// * `req` - The incoming HTTP request
+/// * `direct_base_url` - Base URL for rai.moe (e.g., `https://direct.rai.moe`)
+/// * `client` - HTTP client for making the upstream request
+/// * `cache` - On-disk download cache, or `None` if caching is disabled
+/// * `user_agent` - `User-Agent` to send instead of the client's, if any
+///
+/// # Returns
+///
+/// The response from rai.moe, or a 502 Bad Gateway response on failure.
+async fn forward_to_raimoe<B>(
+    mut req: Request<B>,
+    direct_base_url: &str,
+    client: &reqwest::Client,
+    cache: Option<&Arc<BeatmapCache>>,
+    user_agent: Option<&str>,
+) -> Response<BoxBody<Bytes, Infallible>>
+where
+    B: Body,
+{
+    if let Some(host) = req.headers().get("host").cloned() {
+        req.headers_mut().insert("x-original-host", host);
+    }
+    if let Some(user_agent) = user_agent {
+        override_user_agent(&mut req, user_agent);
+    }
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let is_avatar = req
+        .headers()
+        .get("host")
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(is_avatar_host);
+    let url = if is_avatar {
+        map_avatar_to_raimoe_url(path, direct_base_url)
+    } else {
+        map_to_raimoe_url(path, direct_base_url)
+    };
+
+    let cached = cache.and_then(|cache| Some((Arc::clone(cache), beatmap_cache_key(&req, path)?)));
+
+    if let Some((cache, key)) = cached.clone() {
+        let hit = tokio::task::spawn_blocking(move || cache.get(&key))
+            .await
+            .ok()
+            .flatten();
+        if let Some(hit) = hit {
+            tracing::info!("Serving {} from beatmap cache", sanitize_for_log(path));
+            return cached_beatmap_response(hit);
+        }
+    }
+
+    tracing::debug!("Forwarding to rai.moe: {}", sanitize_for_log(&url));
+
+    let result = match cached {
+        Some((cache, key)) => forward_and_cache(req, &url, client, cache, key).await,
+        None => forward_request(req, &url, client).await,
+    };
+
+    match result {
+        Ok(resp) => resp,
+        Err(e) => {
+            tracing::error!(
+                "Failed to forward to rai.moe: {}",
+                sanitize_for_log(&e.to_string())
+            );
+            error_response(StatusCode::BAD_GATEWAY, "Failed to reach rai.moe")
+        }
+    }
+}
+
+/// The beatmap cache key for `req` to `path`, if it's a download the cache
+/// can serve. Only whole-file downloads are cached, not partial (Range)
+/// requests.
+fn beatmap_cache_key<B>(req: &Request<B>, path: &str) -> Option<String> {
+    if req.method() != Method::GET || req.headers().contains_key("range") {
+        return None;
+    }
+    BeatmapCache::key_for_path(path)
+}
+
+/// Forwards a beatmap download, writing it into the cache as it streams.
+async fn forward_and_cache<B>(
+    req: Request<B>,
+    url: &str,
+    client: &reqwest::Client,
+    cache: Arc<BeatmapCache>,
+    key: String,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, Box<dyn std::error::Error + Send + Sync>>
+where
+    B: Body,
+{
+    let resp = send_upstream(req, url, client, false).await?;
+
+    let tee = if resp.status() == reqwest::StatusCode::OK {
+        let content_type = resp
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        match cache.writer(&key, content_type).await {
+            Ok(writer) => Some(writer),
+            Err(e) => {
+                tracing::warn!("Failed to open beatmap cache entry {}: {}", key, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    Ok(streamed_response(resp, tee))
+}
+
+/// Forwards a bypassed request to the official servers untouched.
+///
+/// Unlike [`forward_to_upstream`] this never injects into Bancho responses
+/// and logs nothing, not even failures.
+async fn forward_bypassed<B>(
+    req: Request<B>,
+    host: &str,
+    ctx: &ProxyContext,
+) -> Response<BoxBody<Bytes, Infallible>>
+where
+    B: Body,
+{
+    let upstream_host = map_host_to_upstream(host, &ctx.config.upstream_server);
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let url = format!("https://{}{}", upstream_host, path);
+
+    match forward_request(req, &url, &ctx.clients.general).await {
+        Ok(resp) => resp,
+        Err(_) => error_response(StatusCode::BAD_GATEWAY, "Failed to reach osu! servers"),
+    }
+}
+
+async fn forward_to_upstream<B>(
+    req: Request<B>,
+    host: &str,
+    ctx: &ProxyContext,
+) -> Response<BoxBody<Bytes, Infallible>>
+where
+    B: Body,
+{
+    let upstream_server = &ctx.config.upstream_server;
+    let upstream_host = map_host_to_upstream(host, upstream_server);
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let url = format!("https://{}{}", upstream_host, path);
+
+    tracing::debug!(
+        "Forwarding to {}: {}",
+        upstream_server,
+        sanitize_for_log(&url)
+    );
+
+    let is_bancho = upstream_host.starts_with("c.");
+
+    let result = if is_bancho {
+        forward_bancho_request(req, &url, ctx).await
+    } else {
+        forward_request_with_injection(
+            req,
+            &url,
+            &ctx.clients.general,
+            &[],
+            ctx.config.upstream_user_agent.as_deref(),
+        )
+        .await
+    };
+
+    match result {
+        Ok(resp) => resp,
+        Err(e) => {
+            tracing::error!(
+                "Failed to forward to {}: {}",
+                upstream_server,
+                sanitize_for_log(&e.to_string())
+            );
+            error_response(StatusCode::BAD_GATEWAY, "Failed to reach osu! servers")
+        }
+    }
+}
+
+async fn forward_request<B>(
+    req: Request<B>,
     url: &str,
     client: &reqwest::Client,
-) -> Result<Response<BoxBody<Bytes, Infallible>>, reqwest::Error> {
-    forward_request_with_injection(req, url, client, false).await
+) -> Result<Response<BoxBody<Bytes, Infallible>>, Box<dyn std::error::Error + Send + Sync>>
+where
+    B: Body,
+{
+    forward_request_with_injection(req, url, client, &[], None).await
+}
+
+/// Forwards a Bancho request and records how many bytes moved in each direction.
+///
+/// Bancho polls are small and frequent, so both counters are updated under a
+/// single write lock once the response body has been sent rather than as
+/// data flows.
+///
+/// A login request, the only one without an `osu-token` header, also
+/// records the username in the state once Bancho accepts it by handing out
+/// a `cho-token`. The rest of the login body, which holds the password
+/// hash, is forwarded but never kept.
+///
+/// With `answer_stalled_keepalives`, a poll carrying only keepalives that
+/// Bancho doesn't answer gets an empty response from the proxy instead.
+async fn forward_bancho_request<B>(
+    req: Request<B>,
+    url: &str,
+    ctx: &ProxyContext,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, Box<dyn std::error::Error + Send + Sync>>
+where
+    B: Body,
+{
+    let (parts, body) = req.into_parts();
+    let body = body
+        .collect()
+        .await
+        .map_err(|_| "Failed to read request body")?
+        .to_bytes();
+    let sent = body.len() as u64;
+
+    let is_login = !parts.headers.contains_key("osu-token");
+    let username = if is_login {
+        parse_login_username(&body)
+    } else {
+        None
+    };
+    let keepalive = !is_login && is_keepalive_only(&body);
+
+    let mode = *ctx.supporter.read();
+    let mut editors = bancho_editors(mode, &ctx.state);
+    if ctx.config.capture_packets {
+        // First, so the packets are recorded as Bancho sent them
+        editors.insert(0, Box::new(Capture(&ctx.capture)));
+    }
+
+    let req = Request::from_parts(parts, Full::new(body));
+    let user_agent = ctx.config.upstream_user_agent.as_deref();
+    let resp =
+        match forward_request_with_injection(req, url, &ctx.clients.general, &editors, user_agent)
+            .await
+        {
+            Ok(resp) => resp,
+            Err(e) if keepalive && ctx.config.answer_stalled_keepalives => {
+                tracing::debug!(
+                    "Bancho didn't answer a keepalive poll ({}), answering it locally",
+                    sanitize_for_log(&e.to_string())
+                );
+                Response::new(Full::new(Bytes::new()).map_err(|_| unreachable!()).boxed())
+            }
+            Err(e) => return Err(e),
+        };
+
+    if let Some(username) = username.filter(|_| resp.headers().contains_key("cho-token")) {
+        tracing::info!("Logged in to Bancho as {}", username);
+        ctx.state.write().current_username = Some(username);
+    }
+
+    let state = Arc::clone(&ctx.state);
+    Ok(resp.map(|body| {
+        CountingBody::new(body, move |received| {
+            let mut s = state.write();
+            s.bancho_bytes_client_to_server += sent;
+            s.bancho_bytes_server_to_client += received;
+        })
+        .boxed()
+    }))
+}
+
+/// Replaces the client's `User-Agent` with `user_agent`.
+fn override_user_agent<B>(req: &mut Request<B>, user_agent: &str) {
+    match HeaderValue::from_str(user_agent) {
+        Ok(value) => {
+            req.headers_mut().insert(USER_AGENT, value);
+        }
+        // Rejected by validate(), so only configs saved before that get here
+        Err(_) => tracing::warn!(
+            "Ignoring upstream_user_agent {:?}, which isn't a valid header value",
+            user_agent
+        ),
+    }
 }
 
-/// Forwards an HTTP request to the specified URL, optionally injecting
-/// supporter privileges into Bancho response packets.
+/// Forwards an HTTP request to the specified URL, optionally rewriting the
+/// Bancho packets in the response.
+///
+/// When `editors` isn't empty, the response body is parsed as Bancho
+/// packets and run through them in order before being returned to the
+/// client. Otherwise the body is streamed
+/// through without being buffered, as are error responses, whose bodies
+/// aren't Bancho packets. Upstream may gzip the body even though `identity`
+/// was requested; such bodies are decompressed before parsing.
 ///
-/// When `inject_supporter` is true, the response body is parsed as Bancho
-/// packets and any UserPrivileges packets are modified to include supporter
-/// status before being returned to the client.
+/// With `user_agent` set, it replaces the client's `User-Agent`.
 ///
 /// # Arguments
 ///
 /// * `req` - The incoming HTTP request
 /// * `url` - The full URL to forward to
 /// * `client` - HTTP client for making the request
-/// * `inject_supporter` - Whether to inject supporter privileges
+/// * `editors` - Editors to run over the response packets; empty to leave
+///   the response untouched
+/// * `user_agent` - `User-Agent` to send instead of the client's, if any
 ///
 /// # Returns
 ///
 /// The upstream response (possibly modified), or a reqwest error.
-async fn forward_request_with_injection(
-    req: Request<Incoming>,
+async fn forward_request_with_injection<B>(
+    mut req: Request<B>,
+    url: &str,
+    client: &reqwest::Client,
+    editors: &[Box<dyn PacketEditor + '_>],
+    user_agent: Option<&str>,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, Box<dyn std::error::Error + Send + Sync>>
+where
+    B: Body,
+{
+    if let Some(user_agent) = user_agent {
+        override_user_agent(&mut req, user_agent);
+    }
+
+    let resp = send_upstream(req, url, client, !editors.is_empty()).await?;
+
+    // Packet rewriting needs the whole body; everything else, including
+    // error pages that only look like packets, is streamed verbatim
+    if editors.is_empty() || !resp.status().is_success() {
+        return Ok(streamed_response(resp, None));
+    }
+
+    let response_builder = response_head(&resp, false);
+    let gzipped = resp
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    let mut body_bytes = resp.bytes().await.unwrap_or_default();
+    let mut decoded = false;
+
+    if !body_bytes.is_empty() {
+        (body_bytes, decoded) = edit_body(body_bytes, gzipped, editors);
+    }
+
+    // Upstream's Content-Length may no longer match, so state the real one
+    let content_length = HeaderValue::from(body_bytes.len());
+    let body = Full::new(body_bytes).map_err(|_| unreachable!()).boxed();
+    let mut response = response_builder.body(body).unwrap();
+    response
+        .headers_mut()
+        .insert(CONTENT_LENGTH, content_length);
+
+    // A rewritten body is sent uncompressed
+    if decoded {
+        response.headers_mut().remove(CONTENT_ENCODING);
+    }
+
+    Ok(response)
+}
+
+/// Runs `editors` over a Bancho response body, decompressing it first if
+/// it's gzipped.
+///
+/// Returns the body to send and whether it was decompressed. A gzipped body
+/// that needed no changes, or couldn't be decompressed, is returned as is.
+fn edit_body(body: Bytes, gzipped: bool, editors: &[Box<dyn PacketEditor + '_>]) -> (Bytes, bool) {
+    if !gzipped {
+        return (edit_bancho_response(body, editors), false);
+    }
+
+    let mut plain = Vec::new();
+    if let Err(e) = GzDecoder::new(&body[..]).read_to_end(&mut plain) {
+        tracing::warn!("Failed to decompress gzipped Bancho response: {}", e);
+        return (body, false);
+    }
+
+    let plain = Bytes::from(plain);
+    let injected = edit_bancho_response(plain.clone(), editors);
+    if injected == plain {
+        (body, false)
+    } else {
+        (injected, true)
+    }
+}
+
+/// Sends a client request upstream, copying its method, headers and body.
+///
+/// When `injecting` is set the client's `Accept-Encoding` is replaced with
+/// `identity`, since a compressed Bancho response can't be parsed for
+/// injection. Otherwise it's forwarded unchanged.
+async fn send_upstream<B>(
+    req: Request<B>,
     url: &str,
     client: &reqwest::Client,
-    inject_supporter: bool,
-) -> Result<Response<BoxBody<Bytes, Infallible>>, reqwest::Error> {
+    injecting: bool,
+) -> Result<reqwest::Response, Box<dyn std::error::Error + Send + Sync>>
+where
+    B: Body,
+{
     let method = match *req.method() {
         Method::GET => reqwest::Method::GET,
         Method::POST => reqwest::Method::POST,
@@ -355,6 +1759,9 @@ async fn forward_request_with_injection(
 
     for (name, value) in req.headers() {
         let name_str = name.as_str();
+        if injecting && name == hyper::header::ACCEPT_ENCODING {
+            continue;
+        }
         if !matches!(
             name_str.to_lowercase().as_str(),
             "host" | "connection" | "keep-alive" | "transfer-encoding" | "te" | "trailer"
@@ -365,70 +1772,139 @@ async fn forward_request_with_injection(
         }
     }
 
-    let body_bytes = req.collect().await.ok().map(|b| b.to_bytes());
-    if let Some(bytes) = body_bytes {
-        if !bytes.is_empty() {
-            builder = builder.body(bytes.to_vec());
-        }
+    if injecting {
+        builder = builder.header("accept-encoding", "identity");
     }
 
-    let resp = builder.send().await?;
+    let needs_length = matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH)
+        && !req.headers().contains_key(hyper::header::CONTENT_LENGTH);
 
+    // A body that can't be read in full must not be forwarded truncated
+    let bytes = req
+        .collect()
+        .await
+        .map_err(|_| "Failed to read request body")?
+        .to_bytes();
+
+    // Upstream may reject a bodiless POST without a length (411), so an
+    // empty body is sent as an explicit `Content-Length: 0`
+    if needs_length && bytes.is_empty() {
+        builder = builder.header(hyper::header::CONTENT_LENGTH, "0");
+    }
+    if !bytes.is_empty() {
+        builder = builder.body(bytes);
+    }
+
+    Ok(builder.send().await?)
+}
+
+/// Starts a client response with the upstream status and headers.
+///
+/// `Content-Length` is only kept when the body is passed through unchanged.
+fn response_head(
+    resp: &reqwest::Response,
+    keep_content_length: bool,
+) -> hyper::http::response::Builder {
     let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::OK);
     let mut response_builder = Response::builder().status(status);
 
     for (name, value) in resp.headers() {
         let name_str = name.as_str();
-        if !matches!(
-            name_str.to_lowercase().as_str(),
-            "transfer-encoding" | "connection" | "content-length"
-        ) {
+        let skip = match name_str.to_lowercase().as_str() {
+            "transfer-encoding" | "connection" => true,
+            "content-length" => !keep_content_length,
+            _ => false,
+        };
+        if !skip {
             if let Ok(v) = value.to_str() {
                 response_builder = response_builder.header(name_str, v);
             }
         }
     }
 
-    let mut body_bytes = resp.bytes().await.unwrap_or_default();
+    response_builder
+}
 
-    // If supporter injection is enabled, parse and modify Bancho packets
-    if inject_supporter && !body_bytes.is_empty() {
-        body_bytes = inject_supporter_into_bancho_response(body_bytes);
-    }
+/// Upstream chunks buffered between the upstream reader and the client.
+const STREAM_BUFFER_CHUNKS: usize = 16;
 
-    let body = Full::new(body_bytes).map_err(|_| unreachable!()).boxed();
+/// Builds a response whose body is forwarded from upstream as it arrives.
+///
+/// When `tee` is given the body is also written into the beatmap cache, and
+/// committed once the download completes. If the upstream connection fails
+/// partway through, the response ends early; the forwarded `Content-Length`
+/// lets the client see that the download is incomplete.
+fn streamed_response(
+    resp: reqwest::Response,
+    tee: Option<CacheWriter>,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let response_builder = response_head(&resp, true);
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+
+    tokio::spawn(pump_body(resp, tx, tee));
 
-    Ok(response_builder.body(body).unwrap())
+    response_builder.body(ChannelBody::new(rx).boxed()).unwrap()
 }
 
-/// Parses Bancho packets from the response body and injects supporter
-/// privileges into any UserPrivileges packets.
-///
-/// This function:
-/// 1. Parses the binary response as a stream of Bancho packets
-/// 2. For each UserPrivileges packet (ID 71), modifies the privileges to
-///    include supporter status (bit 2)
-/// 3. Reassembles the packets into a new response body
-///
-/// If parsing fails or there are incomplete packets, they are preserved
-/// as-is to avoid breaking the client connection.
-fn inject_supporter_into_bancho_response(body: Bytes) -> Bytes {
-    let (mut packets, remaining) = Packet::parse_stream(&body);
+/// Copies an upstream body into `tx` chunk by chunk, mirroring it into `tee`.
+async fn pump_body(
+    mut resp: reqwest::Response,
+    tx: mpsc::Sender<Bytes>,
+    mut tee: Option<CacheWriter>,
+) {
+    loop {
+        match resp.chunk().await {
+            Ok(Some(chunk)) => {
+                if let Some(writer) = tee.as_mut() {
+                    if let Err(e) = writer.write(&chunk).await {
+                        tracing::debug!("Not caching download: {}", e);
+                        tee = None;
+                    }
+                }
+                // The client went away; dropping the writer discards the partial file
+                if tx.send(chunk).await.is_err() {
+                    return;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                tracing::warn!("Upstream response ended early: {}", e);
+                return;
+            }
+        }
+    }
+
+    // Let the client response finish before committing to the cache
+    drop(tx);
+
+    if let Some(writer) = tee {
+        if let Err(e) = writer.finish().await {
+            tracing::warn!("Failed to cache download: {}", e);
+        }
+    }
+}
+
+/// Parses Bancho packets from the response body and runs them through
+/// `editors`.
+///
+/// This function:
+/// 1. Parses the binary response as a stream of Bancho packets
+/// 2. Passes each packet through the editors in order, e.g. adding
+///    supporter status to UserPrivileges packets (ID 71)
+/// 3. Reassembles the packets, plus any the editors inserted, into a new
+///    response body
+///
+/// If parsing fails or there are incomplete packets, they are preserved
+/// as-is to avoid breaking the client connection.
+fn edit_bancho_response(body: Bytes, editors: &[Box<dyn PacketEditor + '_>]) -> Bytes {
+    let (packets, remaining) = Packet::parse_stream(&body);
 
     if packets.is_empty() && remaining.is_empty() {
         // No valid packets found, return original
         return body;
     }
 
-    let mut modified = false;
-
-    for packet in &mut packets {
-        if packet.packet_type() == ServerPacketId::UserPrivileges {
-            tracing::debug!("Injecting supporter privileges into UserPrivileges packet");
-            inject_supporter_privileges(packet);
-            modified = true;
-        }
-    }
+    let (packets, modified) = apply_editors(packets, editors);
 
     if !modified {
         // No modifications needed, return original
@@ -446,6 +1922,59 @@ fn inject_supporter_into_bancho_response(body: Bytes) -> Bytes {
     Bytes::from(output)
 }
 
+/// Warns that a UserPrivileges packet didn't have the expected 4-byte payload.
+///
+/// This usually means the Bancho protocol changed and supporter injection is
+/// no longer working, so the warning is also recorded in [`AppState`] for the UI.
+fn report_unexpected_privileges_layout(payload_len: usize, state: &RwLock<AppState>) {
+    let message = format!(
+        "Supporter injection may not be working: UserPrivileges packet has a {}-byte payload (expected 4)",
+        payload_len
+    );
+    tracing::warn!("{}", message);
+    state.write().injection_warning = Some(message);
+}
+
+/// Builds a response for a search result served from the search cache.
+fn cached_response(
+    data: Bytes,
+    content_type: Option<String>,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut builder = Response::builder().status(StatusCode::OK);
+    if let Some(content_type) = content_type {
+        builder = builder.header("content-type", content_type);
+    }
+    builder
+        .body(Full::new(data).map_err(|_| unreachable!()).boxed())
+        .unwrap()
+}
+
+/// Streams a beatmapset from the download cache.
+///
+/// A read error partway through ends the body early; with the length
+/// already sent, the client sees a truncated download rather than a
+/// corrupt one.
+fn cached_beatmap_response(hit: CachedBeatmap) -> Response<BoxBody<Bytes, Infallible>> {
+    let file = ReaderStream::new(tokio::fs::File::from_std(hit.file));
+    let frames = futures_util::StreamExt::scan(file, (), |_, chunk| {
+        std::future::ready(match chunk {
+            Ok(data) => Some(Ok::<_, Infallible>(Frame::data(data))),
+            Err(e) => {
+                tracing::warn!("Failed to read cached beatmap: {}", e);
+                None
+            }
+        })
+    });
+
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_LENGTH, hit.len);
+    if let Some(content_type) = hit.content_type {
+        builder = builder.header("content-type", content_type);
+    }
+    builder.body(StreamBody::new(frames).boxed()).unwrap()
+}
+
 /// Creates an error response with the given status code and message.
 ///
 /// Used for returning error responses when upstream requests fail.
@@ -470,6 +1999,27 @@ fn error_response(status: StatusCode, message: &str) -> Response<BoxBody<Bytes,
         .unwrap()
 }
 
+/// Refuses something only offered to clients on this machine.
+fn local_only_response() -> Response<BoxBody<Bytes, Infallible>> {
+    error_response(
+        StatusCode::FORBIDDEN,
+        "Only available to clients on this machine",
+    )
+}
+
+/// Creates a 429 response asking the client to retry after `retry_after`.
+fn too_many_requests_response(retry_after: Duration) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut resp = error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too many requests to the beatmap mirror, try again shortly",
+    );
+    // Round up so the client doesn't retry a moment too early
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    resp.headers_mut()
+        .insert("retry-after", HeaderValue::from(secs));
+    resp
+}
+
 /// Creates a redirect response to the given URL.
 ///
 /// Returns a 302 Found response that redirects the browser to the target URL.
@@ -485,6 +2035,1809 @@ fn redirect_response(url: &str) -> Response<BoxBody<Bytes, Infallible>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{ClientPacketId, PacketHeader, Privileges, RouteStats, ServerPacketId};
+    use crate::infrastructure::tls::CertOptions;
+
+    fn test_context(config: ProxyConfig) -> ProxyContext {
+        ProxyContext {
+            supporter: Arc::new(RwLock::new(config.supporter_mode())),
+            rate_limiter: MirrorRateLimiter::new(&config),
+            clients: Arc::new(UpstreamClients::new(&config)),
+            config,
+            state: Arc::new(RwLock::new(AppState::default())),
+            meters: Arc::new(TrafficMeters::default()),
+            cache: None,
+            started_at: Instant::now(),
+            search_cache: SearchCache::default(),
+            tls_acceptor: None,
+            capture: Arc::new(PacketCapture::default()),
+        }
+    }
+
+    fn empty_service(
+    ) -> impl HttpService<Incoming, ResBody = Full<Bytes>, Error = Infallible, Future = impl Send>
+    {
+        service_fn(|_req| async { Ok::<_, Infallible>(Response::new(Full::new(Bytes::new()))) })
+    }
+
+    #[tokio::test]
+    async fn test_accept_survives_transient_errors() {
+        let mut results = std::collections::VecDeque::from([
+            Err(io::Error::from_raw_os_error(24)), // EMFILE
+            Err(io::Error::from(io::ErrorKind::ConnectionAborted)),
+            Ok(42),
+        ]);
+
+        let accepted = accept_next(|| {
+            let next = results.pop_front().unwrap();
+            async move { next }
+        })
+        .await;
+
+        assert_eq!(accepted.unwrap(), 42);
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_accept_returns_fatal_errors() {
+        let accepted: io::Result<()> =
+            accept_next(|| async { Err(io::Error::from(io::ErrorKind::InvalidInput)) }).await;
+
+        assert_eq!(accepted.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    /// Fails every accept with EMFILE, counting the attempts.
+    struct ExhaustedListener(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl Listener for ExhaustedListener {
+        fn poll_accept(&self, _cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Poll::Ready(Err(io::Error::from_raw_os_error(24)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accept_backoff_outlives_other_events() {
+        use crate::infrastructure::tls;
+
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs, key).unwrap();
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(serve_https(
+            vec![ExhaustedListener(Arc::clone(&attempts))],
+            acceptor,
+            test_context(ProxyConfig::default()),
+            shutdown_rx,
+            None,
+        ));
+
+        // Retries at 0, 10, 30, 70, 150, 310, 630, 1270 and 2270ms. If the
+        // once-a-second rate tick reset the backoff, it'd be twice as many.
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        let attempts = attempts.load(std::sync::atomic::Ordering::SeqCst);
+        assert!((5..=10).contains(&attempts), "{} accept attempts", attempts);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    /// A listener whose next accept fails with a fatal error once `fail` is set.
+    struct BreakableListener {
+        inner: TcpListener,
+        fail: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl Listener for BreakableListener {
+        fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+            match self.inner.poll_accept(cx) {
+                Poll::Ready(Ok(_)) if self.fail.load(std::sync::atomic::Ordering::SeqCst) => {
+                    Poll::Ready(Err(io::ErrorKind::InvalidInput.into()))
+                }
+                other => other,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fatal_accept_error_drains_connections() {
+        use crate::infrastructure::tls;
+
+        // A mirror that holds its response until the test releases it
+        let received = Arc::new(tokio::sync::Notify::new());
+        let release = Arc::new(tokio::sync::Notify::new());
+        let mirror_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror = mirror_listener.local_addr().unwrap();
+        let (mirror_received, mirror_release) = (Arc::clone(&received), Arc::clone(&release));
+        tokio::spawn(async move {
+            let (stream, _) = mirror_listener.accept().await.unwrap();
+            let service = service_fn(move |_req: Request<Incoming>| {
+                let (received, release) =
+                    (Arc::clone(&mirror_received), Arc::clone(&mirror_release));
+                async move {
+                    received.notify_one();
+                    release.notified().await;
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from_static(b"osz"))))
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let config = ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            ..ProxyConfig::default()
+        };
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs.clone(), key).unwrap();
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let listener = BreakableListener {
+            inner: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            fail: Arc::clone(&fail),
+        };
+        let addr = listener.inner.local_addr().unwrap();
+
+        let (_shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(serve_https(
+            vec![listener],
+            acceptor,
+            test_context(config),
+            shutdown_rx,
+            None,
+        ));
+
+        let client = reqwest::Client::builder()
+            .resolve("osu.localhost", addr)
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_der(&certs[0]).unwrap())
+            .build()
+            .unwrap();
+        let download = tokio::spawn(
+            client
+                .get(format!("https://osu.localhost:{}/d/1", addr.port()))
+                .send(),
+        );
+        received.notified().await;
+
+        // The next connection hits the fatal error, with the download in flight
+        fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        let _trigger = TcpStream::connect(addr).await.unwrap();
+        release.notify_one();
+
+        let resp = download.await.unwrap().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.bytes().await.unwrap(), Bytes::from_static(b"osz"));
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_silent_connection_hits_idle_timeout() {
+        // The client half is kept open but never sends anything
+        let (_client, server) = tokio::io::duplex(1024);
+        let activity = Activity::new();
+        let (_drain_tx, drain_rx) = watch::channel(false);
+
+        let end = tokio::time::timeout(
+            Duration::from_secs(2),
+            serve_connection(
+                ActivityStream::new(server, activity.clone()),
+                &activity,
+                empty_service(),
+                Duration::from_millis(50),
+                drain_rx,
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+            ),
+        )
+        .await
+        .expect("connection should close after the idle timeout");
+
+        assert_eq!(end, ConnectionEnd::IdleTimeout);
+    }
+
+    #[tokio::test]
+    async fn test_client_close_is_not_idle_timeout() {
+        let (client, server) = tokio::io::duplex(1024);
+        let activity = Activity::new();
+        let (_drain_tx, drain_rx) = watch::channel(false);
+        drop(client);
+
+        let end = serve_connection(
+            ActivityStream::new(server, activity.clone()),
+            &activity,
+            empty_service(),
+            Duration::from_secs(60),
+            drain_rx,
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+        )
+        .await;
+
+        assert_eq!(end, ConnectionEnd::Closed);
+    }
+
+    fn pong() -> Vec<u8> {
+        PacketHeader {
+            packet_id: ClientPacketId::Pong as u16,
+            compression: 0,
+            length: 0,
+        }
+        .to_bytes()
+        .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_polls_keep_connection_open() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (_drain_tx, drain_rx) = watch::channel(false);
+        let serve = tokio::spawn(async move {
+            let activity = Activity::new();
+            serve_connection(
+                ActivityStream::new(server, activity.clone()),
+                &activity,
+                empty_service(),
+                Duration::from_millis(100),
+                drain_rx,
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+            )
+            .await
+        });
+
+        // Polls carrying only a pong, for several times the idle timeout
+        let mut poll = format!(
+            "POST / HTTP/1.1\r\nhost: c.localhost\r\nosu-token: token\r\ncontent-length: {}\r\n\r\n",
+            pong().len()
+        )
+        .into_bytes();
+        poll.extend(pong());
+        for _ in 0..8 {
+            client.write_all(&poll).await.unwrap();
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                let mut buf = [0; 256];
+                let n = client.read(&mut buf).await.unwrap();
+                response.extend_from_slice(&buf[..n]);
+            }
+            tokio::time::sleep(Duration::from_millis(40)).await;
+        }
+        assert!(!serve.is_finished());
+
+        drop(client);
+        assert_eq!(serve.await.unwrap(), ConnectionEnd::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_keepalive_answered_locally() {
+        // Bind then drop so nothing is listening there
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}/", port);
+        let poll = |body: Vec<u8>| {
+            Request::builder()
+                .method(Method::POST)
+                .header("osu-token", "token")
+                .body(Full::new(Bytes::from(body)))
+                .unwrap()
+        };
+
+        let ctx = test_context(ProxyConfig {
+            answer_stalled_keepalives: true,
+            ..ProxyConfig::default()
+        });
+        let resp = forward_bancho_request(poll(pong()), &url, &ctx)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .is_empty());
+
+        // Polls with anything else in them still fail
+        let message = Packet::new(ServerPacketId::Unknown, Vec::new()).to_bytes();
+        let mut body = pong();
+        body.extend(message);
+        assert!(forward_bancho_request(poll(body), &url, &ctx)
+            .await
+            .is_err());
+
+        // As do keepalives with the option off
+        let ctx = test_context(ProxyConfig::default());
+        assert!(forward_bancho_request(poll(pong()), &url, &ctx)
+            .await
+            .is_err());
+    }
+
+    /// Serves an HTTP server on loopback that echoes request bodies back.
+    async fn spawn_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<Incoming>| async move {
+                        let body = req.collect().await.unwrap().to_bytes();
+                        Ok::<_, Infallible>(Response::new(Full::new(body)))
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_bancho_traffic_is_counted() {
+        let addr = spawn_echo_server().await;
+        let url = format!("http://{}/", addr);
+        let ctx = test_context(ProxyConfig::default());
+
+        for payload in [&b"hello"[..], &b"bancho!"[..]] {
+            let req = Request::builder()
+                .method(Method::POST)
+                .body(Full::new(Bytes::from_static(payload)))
+                .unwrap();
+            let resp = forward_bancho_request(req, &url, &ctx).await.unwrap();
+            let echoed = resp.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(echoed, payload);
+        }
+
+        let s = ctx.state.read();
+        assert_eq!(s.bancho_bytes_client_to_server, 12);
+        assert_eq!(s.bancho_bytes_server_to_client, 12);
+    }
+
+    #[tokio::test]
+    async fn test_login_records_username_only() {
+        // Plays Bancho accepting every login
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<Incoming>| async {
+                        let resp = Response::builder()
+                            .header("cho-token", "token")
+                            .body(Full::new(Bytes::new()))
+                            .unwrap();
+                        Ok::<_, Infallible>(resp)
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        let url = format!("http://{}/", addr);
+        let ctx = test_context(ProxyConfig::default());
+
+        let login = Request::builder()
+            .method(Method::POST)
+            .body(Full::new(Bytes::from_static(
+                b"peppy\n5f4dcc3b5aa765d61d8327deb882cf99\nb20240123|2|1|abc:def|0\n",
+            )))
+            .unwrap();
+        forward_bancho_request(login, &url, &ctx).await.unwrap();
+
+        let state = ctx.state.read().clone();
+        assert_eq!(state.current_username.as_deref(), Some("peppy"));
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(!json.contains("5f4dcc3b"));
+
+        // Polls carry the token and aren't parsed as logins
+        let poll = Request::builder()
+            .method(Method::POST)
+            .header("osu-token", "token")
+            .body(Full::new(Bytes::from_static(b"someone-else\n")))
+            .unwrap();
+        forward_bancho_request(poll, &url, &ctx).await.unwrap();
+        assert_eq!(ctx.state.read().current_username.as_deref(), Some("peppy"));
+    }
+
+    #[tokio::test]
+    async fn test_self_test_through_live_listener() {
+        use crate::infrastructure::self_test::run_self_test;
+        use crate::infrastructure::tls;
+
+        let mirror = spawn_echo_server().await;
+        let config = ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            ..ProxyConfig::default()
+        };
+
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs.clone(), key).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let state = Arc::new(RwLock::new(AppState::default()));
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn({
+            let state = Arc::clone(&state);
+            async move {
+                let ctx = ProxyContext {
+                    state,
+                    ..test_context(config)
+                };
+                serve_https(vec![listener], acceptor, ctx, shutdown_rx, None)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let report = run_self_test([127, 0, 0, 1].into(), port, &certs[0]).await;
+
+        assert!(report.routed_locally, "{:?}", report);
+        assert_eq!(report.status, Some(200));
+        assert_eq!(report.error, None);
+        assert_eq!(state.read().requests_proxied, 1);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_accepts_connections_over_ipv6_loopback() {
+        use crate::infrastructure::self_test::run_self_test_at;
+        use crate::infrastructure::tls;
+
+        // Nothing to test on a machine without IPv6
+        let Ok(listener_v6) = TcpListener::bind("[::1]:0").await else {
+            return;
+        };
+        let addr_v6 = listener_v6.local_addr().unwrap();
+        let listener_v4 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let mirror = spawn_echo_server().await;
+        let config = ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            ..ProxyConfig::default()
+        };
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs.clone(), key).unwrap();
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            serve_https(
+                vec![listener_v4, listener_v6],
+                acceptor,
+                test_context(config),
+                shutdown_rx,
+                None,
+            )
+            .await
+            .unwrap();
+        });
+
+        let report = run_self_test_at(addr_v6, &certs[0]).await;
+
+        assert!(report.routed_locally, "{:?}", report);
+        assert_eq!(report.status, Some(200));
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_garbage_handshake_does_not_stop_listener() {
+        use crate::infrastructure::self_test::run_self_test;
+        use crate::infrastructure::tls;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mirror = spawn_echo_server().await;
+        let config = ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            ..ProxyConfig::default()
+        };
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs.clone(), key).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            serve_https(
+                vec![listener],
+                acceptor,
+                test_context(config),
+                shutdown_rx,
+                None,
+            )
+            .await
+            .unwrap();
+        });
+
+        // Plain HTTP where a TLS ClientHello is expected
+        let mut garbage = TcpStream::connect(addr).await.unwrap();
+        garbage
+            .write_all(b"GET / HTTP/1.1\r\nHost: osu.localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut rest = Vec::new();
+        let _ = garbage.read_to_end(&mut rest).await;
+
+        let report = run_self_test(addr.ip(), addr.port(), &certs[0]).await;
+        assert_eq!(report.status, Some(200), "{:?}", report);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_closed_connections_leave_count_at_zero() {
+        use crate::infrastructure::tls;
+
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs, key).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ctx = test_context(ProxyConfig::default());
+        let state = Arc::clone(&ctx.state);
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(serve_https(
+            vec![listener],
+            acceptor,
+            ctx,
+            shutdown_rx,
+            None,
+        ));
+
+        let wait_for_count = |expected: u64| {
+            let state = Arc::clone(&state);
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    while state.read().active_connections != expected {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .unwrap_or_else(|_| {
+                    panic!(
+                        "expected {} open connections, found {}",
+                        expected,
+                        state.read().active_connections
+                    )
+                });
+            }
+        };
+
+        let mut clients = Vec::new();
+        for _ in 0..5 {
+            clients.push(TcpStream::connect(addr).await.unwrap());
+        }
+        wait_for_count(5).await;
+
+        // Closing before the TLS handshake fails it on the server side
+        drop(clients);
+        wait_for_count(0).await;
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_logs_carry_connection_id() {
+        use crate::infrastructure::logging::{LogBuffer, LogCaptureLayer};
+        use crate::infrastructure::tls;
+        use tokio::io::AsyncWriteExt;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = LogBuffer::new();
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer::new(buffer.clone()));
+        // The test runtime is single-threaded, so the proxy's tasks log here too
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let acceptor = tls::tls_acceptor_for(certs, key).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(serve_https(
+            vec![listener],
+            acceptor,
+            test_context(ProxyConfig::default()),
+            shutdown_rx,
+            None,
+        ));
+
+        // Two connections that fail the handshake, each logging once. The
+        // leading handshake record byte keeps them off the plain HTTP path.
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(b"\x16 not a TLS handshake").await.unwrap();
+            clients.push(client);
+        }
+
+        let failures = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let failures: Vec<_> = buffer
+                    .get_all()
+                    .into_iter()
+                    .filter(|e| e.message.starts_with("TLS handshake failed"))
+                    .collect();
+                if failures.len() == 2 {
+                    return failures;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("both handshakes should fail");
+
+        let mut ids: Vec<_> = failures
+            .iter()
+            .map(|e| e.fields["conn_id"].clone())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["1", "2"]);
+        for entry in &failures {
+            let logged = &entry.fields["client"];
+            assert!(entry.message.contains(logged.as_str()));
+            assert!(clients
+                .iter()
+                .any(|c| c.local_addr().unwrap().to_string() == *logged));
+        }
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listens_on_configured_bind_host() {
+        use crate::infrastructure::logging::{LogBuffer, LogCaptureLayer};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = LogBuffer::new();
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer::new(buffer.clone()));
+        let _default = tracing::subscriber::set_default(subscriber);
+        let exposure_warnings = || {
+            buffer
+                .get_all()
+                .into_iter()
+                .filter(|e| e.level == "WARN" && e.message.contains("other machines can reach"))
+                .count()
+        };
+
+        let loopback = bind_listeners(&ProxyConfig {
+            https_port: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            loopback[0].local_addr().unwrap().ip(),
+            IpAddr::from([127, 0, 0, 1])
+        );
+        assert_eq!(exposure_warnings(), 0);
+
+        let exposed = bind_listeners(&ProxyConfig {
+            https_port: 0,
+            bind_host: "0.0.0.0".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(exposed[0].local_addr().unwrap().ip().is_unspecified());
+        assert_eq!(exposure_warnings(), 1);
+    }
+
+    #[test]
+    fn test_certificate_rejection_is_recognised() {
+        let rejected = io::Error::new(
+            io::ErrorKind::InvalidData,
+            rustls::Error::AlertReceived(rustls::AlertDescription::UnknownCA),
+        );
+        let garbage = io::Error::new(
+            io::ErrorKind::InvalidData,
+            rustls::Error::InvalidMessage(rustls::InvalidMessage::InvalidContentType),
+        );
+
+        assert!(is_certificate_rejection(&rejected));
+        assert!(!is_certificate_rejection(&garbage));
+        assert!(!is_certificate_rejection(&io::Error::from(
+            io::ErrorKind::UnexpectedEof
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_gzipped_bancho_response_is_injected() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        // Bancho server that gzips whatever it's sent, regardless of Accept-Encoding
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<Incoming>| async move {
+                        let body = req.collect().await.unwrap().to_bytes();
+                        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+                        gz.write_all(&body).unwrap();
+                        let resp = Response::builder()
+                            .header("content-encoding", "gzip")
+                            .body(Full::new(Bytes::from(gz.finish().unwrap())))
+                            .unwrap();
+                        Ok::<_, Infallible>(resp)
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let privileges = Packet {
+            header: PacketHeader {
+                packet_id: ServerPacketId::UserPrivileges as u16,
+                compression: 0,
+                length: 4,
+            },
+            payload: Privileges::NORMAL.to_le_bytes().to_vec(),
+        };
+        let state = RwLock::new(AppState::default());
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: true,
+            },
+            &state,
+        );
+        let url = format!("http://{}/", addr);
+        let client = reqwest::Client::new();
+        let send = |body: Vec<u8>| {
+            let req = Request::builder()
+                .method(Method::POST)
+                .body(Full::new(Bytes::from(body)))
+                .unwrap();
+            forward_request_with_injection(req, &url, &client, &editors, None)
+        };
+
+        let resp = send(privileges.to_bytes()).await.unwrap();
+        assert!(resp.headers().get("content-encoding").is_none());
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let (packets, _) = Packet::parse_stream(&body);
+        let payload: [u8; 4] = packets[0].payload[..].try_into().unwrap();
+        assert!(Privileges(u32::from_le_bytes(payload)).has_supporter());
+
+        // A body without privileges stays compressed
+        let resp = send(b"not bancho".to_vec()).await.unwrap();
+        assert_eq!(resp.headers()["content-encoding"], "gzip");
+    }
+
+    #[tokio::test]
+    async fn test_injected_response_has_accurate_content_length() {
+        let addr = spawn_echo_server().await;
+        let url = format!("http://{}/", addr);
+        let client = reqwest::Client::new();
+        let state = RwLock::new(AppState::default());
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: false,
+            },
+            &state,
+        );
+
+        let privileges = Packet {
+            header: PacketHeader {
+                packet_id: ServerPacketId::UserPrivileges as u16,
+                compression: 0,
+                length: 4,
+            },
+            payload: Privileges::NORMAL.to_le_bytes().to_vec(),
+        };
+
+        // Modified, unmodified, and a trailing partial packet left as is
+        let mut truncated = privileges.to_bytes();
+        truncated.extend_from_slice(&[71, 0, 0]);
+        for body in [
+            privileges.to_bytes(),
+            b"no packets here".to_vec(),
+            truncated,
+        ] {
+            let req = Request::builder()
+                .method(Method::POST)
+                .body(Full::new(Bytes::from(body)))
+                .unwrap();
+            let resp = forward_request_with_injection(req, &url, &client, &editors, None)
+                .await
+                .unwrap();
+
+            let declared: usize = resp.headers()[CONTENT_LENGTH]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(declared, body.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_responses_pass_through_injection_untouched() {
+        let privileges = Packet {
+            header: PacketHeader {
+                packet_id: ServerPacketId::UserPrivileges as u16,
+                compression: 0,
+                length: 4,
+            },
+            payload: Privileges::NORMAL.to_le_bytes().to_vec(),
+        };
+
+        // An error page that happens to parse as a UserPrivileges packet
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let error_body = Bytes::from(privileges.to_bytes());
+        let served = error_body.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(move |_req: Request<Incoming>| {
+                let body = served.clone();
+                async move {
+                    let mut resp = Response::new(Full::new(body));
+                    *resp.status_mut() = StatusCode::BAD_GATEWAY;
+                    Ok::<_, Infallible>(resp)
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let state = RwLock::new(AppState::default());
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: true,
+            },
+            &state,
+        );
+        let req = Request::builder()
+            .method(Method::POST)
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let resp =
+            forward_request_with_injection(req, &url, &reqwest::Client::new(), &editors, None)
+                .await
+                .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, error_body);
+    }
+
+    #[test]
+    fn test_non_packet_body_is_returned_verbatim() {
+        let state = RwLock::new(AppState::default());
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: true,
+            },
+            &state,
+        );
+        let page = Bytes::from_static(b"<html><body>502 Bad Gateway</body></html>");
+
+        assert_eq!(edit_bancho_response(page.clone(), &editors), page);
+        assert!(state.read().injection_warning.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_captured_packets_are_the_ones_bancho_sent() {
+        let addr = spawn_echo_server().await;
+        let url = format!("http://{}/", addr);
+        let ctx = test_context(ProxyConfig {
+            inject_supporter: true,
+            capture_packets: true,
+            ..ProxyConfig::default()
+        });
+
+        let mut body = Vec::new();
+        for (id, payload) in [
+            (ServerPacketId::LoginReply, vec![7, 0, 0, 0]),
+            (
+                ServerPacketId::UserPrivileges,
+                Privileges::NORMAL.to_le_bytes().to_vec(),
+            ),
+            (ServerPacketId::ChannelInfo, Vec::new()),
+        ] {
+            body.extend(Packet::new(id, payload).to_bytes());
+        }
+        let req = Request::builder()
+            .method(Method::POST)
+            .header("osu-token", "token")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap();
+        forward_bancho_request(req, &url, &ctx)
+            .await
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap();
+
+        let captured = ctx.capture.get_all();
+        let types: Vec<_> = captured.iter().map(|p| p.packet_type.as_str()).collect();
+        assert_eq!(types, ["LoginReply", "UserPrivileges", "ChannelInfo"]);
+        // Recorded before injection added the supporter bit
+        assert_eq!(captured[1].hex, "47 00 00 04 00 00 00 01 00 00 00");
+    }
+
+    #[tokio::test]
+    async fn test_supporter_mode_applies_to_next_poll() {
+        let addr = spawn_echo_server().await;
+        let url = format!("http://{}/", addr);
+        let ctx = test_context(ProxyConfig::default());
+
+        // The echo server plays Bancho, answering with the privileges it was sent
+        let privileges = Packet {
+            header: PacketHeader {
+                packet_id: ServerPacketId::UserPrivileges as u16,
+                compression: 0,
+                length: 4,
+            },
+            payload: Privileges::NORMAL.to_le_bytes().to_vec(),
+        };
+        let poll = || async {
+            let req = Request::builder()
+                .method(Method::POST)
+                .body(Full::new(Bytes::from(privileges.to_bytes())))
+                .unwrap();
+            let resp = forward_bancho_request(req, &url, &ctx).await.unwrap();
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            let (packets, _) = Packet::parse_stream(&body);
+            let payload: [u8; 4] = packets[0].payload[..].try_into().unwrap();
+            Privileges(u32::from_le_bytes(payload))
+        };
+
+        assert!(!poll().await.has_supporter());
+
+        ctx.supporter.write().inject = true;
+        assert!(poll().await.has_supporter());
+
+        ctx.supporter.write().inject = false;
+        assert!(!poll().await.has_supporter());
+    }
+
+    #[tokio::test]
+    async fn test_download_served_from_cache_on_repeat() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_hits = Arc::clone(&hits);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let hits = Arc::clone(&server_hits);
+                tokio::spawn(async move {
+                    let service = service_fn(move |_req: Request<Incoming>| {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        async {
+                            Ok::<_, Infallible>(
+                                Response::builder()
+                                    .header("content-type", "application/octet-stream")
+                                    .body(Full::new(Bytes::from_static(b"osz")))
+                                    .unwrap(),
+                            )
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(BeatmapCache::new(dir.path().to_path_buf(), 1024));
+        let client = reqwest::Client::new();
+        let base = format!("http://{}", addr);
+        let download = || {
+            Request::builder()
+                .uri("/d/42")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let first = forward_to_raimoe(download(), &base, &client, Some(&cache), None).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        first.into_body().collect().await.unwrap();
+
+        // The cache write happens in the background
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while cache.get("42").is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("download should be cached");
+
+        let second = forward_to_raimoe(download(), &base, &client, Some(&cache), None).await;
+        assert_eq!(
+            second.headers().get("content-type").unwrap(),
+            "application/octet-stream"
+        );
+        assert_eq!(second.headers()[CONTENT_LENGTH], "3");
+        let body = second.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(body, Bytes::from_static(b"osz"));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_response_streams_before_upstream_finishes() {
+        let gate = Arc::new(tokio::sync::Notify::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_gate = Arc::clone(&gate);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(move |_req: Request<Incoming>| {
+                let gate = Arc::clone(&server_gate);
+                async move {
+                    // Send one chunk, then hold the rest back until the test says so
+                    let (tx, rx) = mpsc::channel(4);
+                    tokio::spawn(async move {
+                        tx.send(Bytes::from_static(b"chunk-1 ")).await.unwrap();
+                        gate.notified().await;
+                        tx.send(Bytes::from_static(b"chunk-2 ")).await.unwrap();
+                        tx.send(Bytes::from_static(b"chunk-3")).await.unwrap();
+                    });
+                    Ok::<_, Infallible>(Response::new(ChannelBody::new(rx)))
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let req = Request::builder().body(Full::new(Bytes::new())).unwrap();
+        let resp = forward_request(
+            req,
+            &format!("http://{}/d/1", addr),
+            &reqwest::Client::new(),
+        )
+        .await
+        .unwrap();
+        let mut body = resp.into_body();
+
+        let first = tokio::time::timeout(Duration::from_secs(2), body.frame())
+            .await
+            .expect("first chunk should arrive while upstream is still sending")
+            .unwrap()
+            .unwrap()
+            .into_data()
+            .unwrap();
+        assert_eq!(first, Bytes::from_static(b"chunk-1 "));
+
+        gate.notify_one();
+        let rest = body.collect().await.unwrap().to_bytes();
+        assert_eq!(rest, Bytes::from_static(b"chunk-2 chunk-3"));
+    }
+
+    /// Serves an HTTP server on loopback that reports the value of request
+    /// header `name` back in an `x-seen` response header.
+    async fn spawn_header_reporter(name: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<Incoming>| async move {
+                        let seen = req
+                            .headers()
+                            .get(name)
+                            .and_then(|v| v.to_str().ok())
+                            .unwrap_or("")
+                            .to_string();
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .header("x-seen", seen)
+                                .body(Full::new(Bytes::new()))
+                                .unwrap(),
+                        )
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    fn seen(resp: &Response<BoxBody<Bytes, Infallible>>) -> String {
+        resp.headers()["x-seen"].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_accept_encoding_neutralized_only_when_injecting() {
+        let addr = spawn_header_reporter("accept-encoding").await;
+        let url = format!("http://{}/", addr);
+        let client = reqwest::Client::new();
+        let state = RwLock::new(AppState::default());
+        let request = || {
+            Request::builder()
+                .header("accept-encoding", "gzip, deflate")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: false,
+            },
+            &state,
+        );
+        let injected = forward_request_with_injection(request(), &url, &client, &editors, None)
+            .await
+            .unwrap();
+        assert_eq!(seen(&injected), "identity");
+
+        let passthrough = forward_request(request(), &url, &client).await.unwrap();
+        assert_eq!(seen(&passthrough), "gzip, deflate");
+    }
+
+    #[tokio::test]
+    async fn test_user_agent_override() {
+        let addr = spawn_header_reporter("user-agent").await;
+        let url = format!("http://{}/", addr);
+        let client = reqwest::Client::new();
+        let request = || {
+            Request::builder()
+                .header("user-agent", "osu!")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let overridden =
+            forward_request_with_injection(request(), &url, &client, &[], Some("rai!connect/1.0"))
+                .await
+                .unwrap();
+        assert_eq!(seen(&overridden), "rai!connect/1.0");
+
+        let passthrough = forward_request_with_injection(request(), &url, &client, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(seen(&passthrough), "osu!");
+
+        // Mirror traffic gets it too
+        let base = format!("http://{}", addr);
+        let mirrored =
+            forward_to_raimoe(request(), &base, &client, None, Some("rai!connect/1.0")).await;
+        assert_eq!(seen(&mirrored), "rai!connect/1.0");
+    }
+
+    #[tokio::test]
+    async fn test_original_host_sent_only_to_mirror() {
+        let addr = spawn_header_reporter("x-original-host").await;
+        let base = format!("http://{}", addr);
+        let client = reqwest::Client::new();
+        let request = || {
+            Request::builder()
+                .uri("/thumb/1l.jpg")
+                .header("host", "b.localhost")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let mirrored = forward_to_raimoe(request(), &base, &client, None, None).await;
+        assert_eq!(seen(&mirrored), "b.localhost");
+
+        let upstream = forward_request(request(), &format!("{}/thumb/1l.jpg", base), &client)
+            .await
+            .unwrap();
+        assert_eq!(seen(&upstream), "");
+    }
+
+    #[tokio::test]
+    async fn test_getbeatmapinfo_body_reaches_mirror_intact() {
+        let addr = spawn_echo_server().await;
+        let base = format!("http://{}", addr);
+        let body = Bytes::from_static(
+            b"{\"Filenames\":[\"Artist - Title (Mapper) [Hard].osu\"],\"Ids\":[]}",
+        );
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/web/osu-getbeatmapinfo.php?u=user&h=hash")
+            .header("host", "osu.localhost")
+            .header("content-type", "application/json")
+            .body(Full::new(body.clone()))
+            .unwrap();
+        let resp = forward_to_raimoe(req, &base, &reqwest::Client::new(), None, None).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.into_body().collect().await.unwrap().to_bytes(), body);
+    }
+
+    #[tokio::test]
+    async fn test_empty_post_is_forwarded_with_zero_length() {
+        let addr = spawn_header_reporter("content-length").await;
+        let base = format!("http://{}", addr);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/web/osu-getbeatmapinfo.php")
+            .header("host", "osu.localhost")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let resp = forward_to_raimoe(req, &base, &reqwest::Client::new(), None, None).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(seen(&resp), "0");
+    }
+
+    #[tokio::test]
+    async fn test_bypassed_requests_are_not_counted() {
+        let ctx = Arc::new(test_context(ProxyConfig {
+            // Unresolvable, so bypassed requests fail fast without network access
+            upstream_server: "invalid".to_string(),
+            bypass_paths: vec!["/web/osu-submit-modular-selector.php".to_string()],
+            ..ProxyConfig::default()
+        }));
+        let request = |path: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(path)
+                .header("host", "osu.localhost")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let bypassed = handle_request(
+            request("/web/osu-submit-modular-selector.php"),
+            Arc::clone(&ctx),
+        )
+        .await
+        .unwrap();
+        assert_eq!(bypassed.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(ctx.state.read().requests_proxied, 0);
+
+        let redirected = handle_request(request("/home"), Arc::clone(&ctx))
+            .await
+            .unwrap();
+        assert_eq!(redirected.status(), StatusCode::FOUND);
+        assert_eq!(ctx.state.read().requests_proxied, 1);
+    }
+
+    #[tokio::test]
+    async fn test_route_stats_count_each_category() {
+        let mirror = spawn_echo_server().await;
+        let ctx = Arc::new(test_context(ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            // Unresolvable, so passthrough requests fail fast without network access
+            upstream_server: "invalid".to_string(),
+            beatmap_cache_max_bytes: 0,
+            ..ProxyConfig::default()
+        }));
+        let requests = [
+            ("osu.localhost", "/web/osu-search.php?q=test"),
+            ("osu.localhost", "/web/osu-search-set.php?s=1"),
+            ("osu.localhost", "/d/1"),
+            ("b.localhost", "/thumb/1l.jpg"),
+            ("b.localhost", "/preview/1.mp3"),
+            ("b.localhost", "/thumb/2l.jpg"),
+            ("osu.localhost", "/web/osu-getbeatmapinfo.php"),
+            ("osu.localhost", "/web/osu-getfriends.php"),
+            ("c.localhost", "/"),
+            ("osu.localhost", "/home"),
+        ];
+
+        for (host, path) in requests {
+            let request = Request::builder()
+                .uri(path)
+                .header("host", host)
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            handle_request(request, Arc::clone(&ctx)).await.unwrap();
+        }
+
+        let state = ctx.state.read();
+        assert_eq!(
+            state.route_stats,
+            RouteStats {
+                searches: 2,
+                downloads: 1,
+                thumbnails: 3,
+                other_mirror: 1,
+                passthrough: 2,
+                redirects: 1,
+            }
+        );
+        assert_eq!(state.route_stats.total(), state.requests_proxied);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_over_limit_get_429() {
+        let mirror = spawn_echo_server().await;
+        let ctx = Arc::new(test_context(ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            max_downloads_per_minute: 2,
+            ..ProxyConfig::default()
+        }));
+        let download = |id: u32| {
+            Request::builder()
+                .uri(format!("/d/{}", id))
+                .header("host", "osu.localhost")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        for id in 0..2 {
+            let resp = handle_request(download(id), Arc::clone(&ctx))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let resp = handle_request(download(2), Arc::clone(&ctx)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "30");
+        assert_eq!(ctx.state.read().beatmaps_downloaded, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cached_downloads_are_not_rate_limited() {
+        let mirror = spawn_echo_server().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(BeatmapCache::new(dir.path().to_path_buf(), 1024));
+        cache.insert("1", b"osz", None).unwrap();
+        let ctx = Arc::new(ProxyContext {
+            cache: Some(cache),
+            ..test_context(ProxyConfig {
+                direct_base_url: format!("http://{}", mirror),
+                max_downloads_per_minute: 1,
+                ..ProxyConfig::default()
+            })
+        });
+        let download = |id: u32| {
+            Request::builder()
+                .uri(format!("/d/{}", id))
+                .header("host", "osu.localhost")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        for id in [1, 1, 2] {
+            let resp = handle_request(download(id), Arc::clone(&ctx))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "download {}", id);
+        }
+
+        // Only the download that reached the mirror used up the limit
+        let resp = handle_request(download(3), Arc::clone(&ctx)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_plain_connect_tunnels_through_live_listener() {
+        use crate::infrastructure::tls;
+
+        let mirror = spawn_echo_server().await;
+        let config = ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            ..ProxyConfig::default()
+        };
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions {
+            intercept_real_hosts: true,
+            ..CertOptions::default()
+        })
+        .unwrap();
+        let acceptor = tls::tls_acceptor_for(certs.clone(), key).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            let ctx = ProxyContext {
+                tls_acceptor: Some(acceptor.clone()),
+                ..test_context(config)
+            };
+            serve_https(vec![listener], acceptor, ctx, shutdown_rx, None)
+                .await
+                .unwrap();
+        });
+
+        // Configured as an HTTP proxy, so it sends a plain-text CONNECT first
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::https(format!("http://{}", addr)).unwrap())
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_der(&certs[0]).unwrap())
+            .build()
+            .unwrap();
+        let resp = client
+            .get("https://osu.ppy.sh/web/osu-search.php?q=test")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[ROUTE_HEADER],
+            route_header_value(RouteDecision::HandleLocally)
+        );
+
+        // Plain HTTP is only for CONNECT
+        let resp = reqwest::get(format!("http://{}/web/osu-search.php", addr))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_other_targets_is_refused() {
+        use crate::infrastructure::tls;
+
+        // A loopback-only service the proxy must not become a way into
+        let local = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = local.local_addr().unwrap().to_string();
+
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let ctx = Arc::new(ProxyContext {
+            tls_acceptor: Some(tls::tls_acceptor_for(certs, key).unwrap()),
+            ..test_context(ProxyConfig::default())
+        });
+
+        for target in [target.as_str(), "example.com:443"] {
+            let req = Request::builder()
+                .method(Method::CONNECT)
+                .uri(target)
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let resp = handle_request(req, Arc::clone(&ctx)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{}", target);
+        }
+
+        let connected = tokio::time::timeout(Duration::from_millis(200), local.accept()).await;
+        assert!(
+            connected.is_err(),
+            "the proxy connected to the local service"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_download_outlasts_request_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<Incoming>| async {
+                        tokio::time::sleep(Duration::from_millis(1500)).await;
+                        Ok::<_, Infallible>(Response::new(Full::new(Bytes::from_static(b"osz"))))
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        let ctx = Arc::new(test_context(ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            upstream_request_timeout_secs: 1,
+            ..ProxyConfig::default()
+        }));
+        let request = |path: &str| {
+            Request::builder()
+                .uri(path)
+                .header("host", "osu.localhost")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let (download, search) = tokio::join!(
+            handle_request(request("/d/1"), Arc::clone(&ctx)),
+            handle_request(request("/web/osu-search.php?q=x"), Arc::clone(&ctx)),
+        );
+
+        let download = download.unwrap();
+        assert_eq!(download.status(), StatusCode::OK);
+        let body = download.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, Bytes::from_static(b"osz"));
+        assert_eq!(search.unwrap().status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_failure_falls_back_to_official_for_gets() {
+        use crate::infrastructure::logging::{LogBuffer, LogCaptureLayer};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = LogBuffer::new();
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer::new(buffer.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // Bind then drop so nothing is listening where the mirror should be
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror = listener.local_addr().unwrap();
+        drop(listener);
+
+        let ctx = Arc::new(test_context(ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            beatmap_cache_max_bytes: 0,
+            // Unreachable too, so the fallback fails fast and visibly
+            upstream_server: "localhost:1".to_string(),
+            mirror_fallback_to_official: true,
+            ..ProxyConfig::default()
+        }));
+        let request = |method: Method| {
+            Request::builder()
+                .method(method)
+                .uri("/web/osu-search.php?q=test")
+                .header("host", "osu.localhost")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+        let body_of = |resp: Response<BoxBody<Bytes, Infallible>>| async move {
+            resp.into_body().collect().await.unwrap().to_bytes()
+        };
+
+        let get = handle_request(request(Method::GET), Arc::clone(&ctx))
+            .await
+            .unwrap();
+        assert_eq!(get.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(body_of(get).await, "Failed to reach osu! servers");
+        assert!(buffer
+            .get_all()
+            .iter()
+            .any(|e| e.message.contains("falling back to localhost:1")));
+
+        let post = handle_request(request(Method::POST), Arc::clone(&ctx))
+            .await
+            .unwrap();
+        assert_eq!(body_of(post).await, "Failed to reach rai.moe");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_gets_413_without_forwarding() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ctx = Arc::new(test_context(ProxyConfig {
+            direct_base_url: format!("http://{}", upstream.local_addr().unwrap()),
+            max_request_body_bytes: 1024,
+            ..ProxyConfig::default()
+        }));
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/web/osu-search.php")
+            .header("host", "osu.localhost")
+            .body(Full::new(Bytes::from(vec![0u8; 1025])))
+            .unwrap();
+        let resp = handle_request(req, Arc::clone(&ctx)).await.unwrap();
+
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(ctx.state.read().requests_proxied, 0);
+        let connected = tokio::time::timeout(Duration::from_millis(100), upstream.accept()).await;
+        assert!(connected.is_err(), "over-limit request reached upstream");
+    }
+
+    #[tokio::test]
+    async fn test_debug_tap_logs_only_matching_paths() {
+        use crate::infrastructure::logging::{LogBuffer, LogCaptureLayer};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = LogBuffer::new();
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer::new(buffer.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mirror = spawn_echo_server().await;
+        let ctx = Arc::new(test_context(ProxyConfig {
+            direct_base_url: format!("http://{}", mirror),
+            debug_tap: Some("osu-search".to_string()),
+            ..ProxyConfig::default()
+        }));
+        let request = |path: &str| {
+            Request::builder()
+                .uri(path)
+                .header("host", "osu.localhost")
+                .header("cho-token", "secret-token")
+                .header("osu-token", "session-token")
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        handle_request(request("/home"), Arc::clone(&ctx))
+            .await
+            .unwrap();
+        let tapped = |buffer: &LogBuffer| {
+            buffer
+                .get_all()
+                .into_iter()
+                .filter(|e| e.message.starts_with("Tap:"))
+                .map(|e| e.message)
+                .collect::<Vec<_>>()
+        };
+        assert!(tapped(&buffer).is_empty());
+
+        let resp = handle_request(request("/web/osu-search.php?q=test"), Arc::clone(&ctx))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let messages = tapped(&buffer);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("GET https://osu.localhost/web/osu-search.php?q=test"));
+        assert!(messages[0].contains("cho-token: <redacted>"));
+        assert!(messages[0].contains("osu-token: <redacted>"));
+        assert!(!messages[0].contains("secret-token"));
+        assert!(!messages[0].contains("session-token"));
+        assert!(messages[1].starts_with("Tap: response 200 OK"));
+    }
+
+    #[test]
+    fn test_hex_dump_truncates() {
+        assert_eq!(hex_dump(&[0x00, 0xab, 0x10], 8), "00 ab 10");
+        assert_eq!(hex_dump(&[1, 2, 3, 4], 2), "01 02 ... (2 more bytes)");
+    }
+
+    #[tokio::test]
+    async fn test_remote_clients_cannot_tunnel_or_reach_local_endpoints() {
+        use crate::infrastructure::tls;
+
+        let (certs, key) = tls::generate_ephemeral_cert(CertOptions::default()).unwrap();
+        let ctx = Arc::new(ProxyContext {
+            tls_acceptor: Some(tls::tls_acceptor_for(certs, key).unwrap()),
+            ..test_context(ProxyConfig::default())
+        });
+
+        let requests = [
+            Request::builder()
+                .method(Method::CONNECT)
+                .uri("c.ppy.sh:443"),
+            Request::builder().uri(HEALTH_PATH),
+            Request::builder().uri(METRICS_PATH),
+        ];
+        for builder in requests {
+            let req = builder
+                .header("host", "osu.localhost")
+                .extension(Remote)
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let resp = handle_request(req, Arc::clone(&ctx)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        assert!(is_loopback_connection(&server));
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint_is_served_locally() {
+        let ctx = Arc::new(test_context(ProxyConfig {
+            // Unresolvable, so anything forwarded by mistake fails the test
+            upstream_server: "invalid".to_string(),
+            ..ProxyConfig::default()
+        }));
+        ctx.state.write().beatmaps_downloaded = 3;
+
+        let req = Request::builder()
+            .uri(HEALTH_PATH)
+            .header("host", "osu.localhost")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let resp = handle_request(req, Arc::clone(&ctx)).await.unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["status"], "disconnected");
+        assert!(health["uptime_secs"].is_u64());
+        assert_eq!(health["requests_proxied"], 0);
+        assert_eq!(health["beatmaps_downloaded"], 3);
+        assert_eq!(health["active_connections"], 0);
+        assert_eq!(ctx.state.read().requests_proxied, 0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_is_served_locally() {
+        let ctx = Arc::new(test_context(ProxyConfig {
+            upstream_server: "invalid".to_string(),
+            ..ProxyConfig::default()
+        }));
+
+        let req = Request::builder()
+            .uri(METRICS_PATH)
+            .header("host", "osu.localhost")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let resp = handle_request(req, Arc::clone(&ctx)).await.unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], metrics::CONTENT_TYPE);
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("raiconnect_requests_proxied_total 0"));
+        assert_eq!(ctx.state.read().requests_proxied, 0);
+    }
+
+    fn short_privileges_packet() -> Bytes {
+        let packet = Packet {
+            header: PacketHeader {
+                packet_id: ServerPacketId::UserPrivileges as u16,
+                compression: 0,
+                length: 2,
+            },
+            payload: vec![1, 0],
+        };
+        Bytes::from(packet.to_bytes())
+    }
+
+    #[test]
+    fn test_strict_injection_warns_on_short_privileges() {
+        let state = RwLock::new(AppState::default());
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: true,
+            },
+            &state,
+        );
+
+        let body = short_privileges_packet();
+        let out = edit_bancho_response(body.clone(), &editors);
+
+        assert_eq!(out, body);
+        assert!(state.read().injection_warning.is_some());
+    }
+
+    #[test]
+    fn test_lenient_injection_ignores_short_privileges() {
+        let state = RwLock::new(AppState::default());
+        let editors = bancho_editors(
+            SupporterMode {
+                inject: true,
+                strict: false,
+            },
+            &state,
+        );
+
+        edit_bancho_response(short_privileges_packet(), &editors);
+
+        assert!(state.read().injection_warning.is_none());
+    }
 
     #[test]
     fn test_localhost_valid() {
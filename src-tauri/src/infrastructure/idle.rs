@@ -0,0 +1,151 @@
+//! Idle detection for proxied connections.
+//!
+//! A client that crashes without closing its socket leaves a half-open
+//! connection that would otherwise keep its task alive forever. Wrapping the
+//! socket in an [`ActivityStream`] records when bytes last moved in either
+//! direction, and [`Activity::idle_for`] resolves once the connection has been
+//! silent for the given duration.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Instant;
+
+/// Shared record of when a connection last transferred data.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    started: Instant,
+    /// Milliseconds since `started` at which bytes last moved.
+    last_ms: Arc<AtomicU64>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Records that bytes were just transferred.
+    pub fn touch(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Returns the instant at which bytes last moved.
+    pub fn last_activity(&self) -> Instant {
+        self.started + Duration::from_millis(self.last_ms.load(Ordering::Relaxed))
+    }
+
+    /// Resolves once no bytes have moved for `timeout`.
+    ///
+    /// Activity recorded while waiting pushes the deadline back.
+    pub async fn idle_for(&self, timeout: Duration) {
+        loop {
+            let deadline = self.last_activity() + timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+/// Stream wrapper that records reads and writes into an [`Activity`].
+#[derive(Debug)]
+pub struct ActivityStream<S> {
+    inner: S,
+    activity: Activity,
+}
+
+impl<S> ActivityStream<S> {
+    pub fn new(inner: S, activity: Activity) -> Self {
+        Self { inner, activity }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if buf.filled().len() > before {
+            self.activity.touch();
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_idle_for_resolves_without_traffic() {
+        let activity = Activity::new();
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            activity.idle_for(Duration::from_millis(20)),
+        )
+        .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stream_traffic_records_activity() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let activity = Activity::new();
+        let mut stream = ActivityStream::new(server, activity.clone());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let before = activity.last_activity();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        assert!(activity.last_activity() > before);
+    }
+}
@@ -1,17 +1,23 @@
 //! Tracing layer for capturing logs and exposing them to the frontend.
 
-use std::collections::VecDeque;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
 use tracing::{Event, Level, Subscriber};
 use tracing_subscriber::layer::Context;
-use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
 
-const MAX_LOG_ENTRIES: usize = 500;
+use crate::domain::DEFAULT_LOG_BUFFER_SIZE;
+
+/// How many entries may wait for the frontend before new ones are dropped.
+pub const LOG_CHANNEL_CAPACITY: usize = 256;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
@@ -20,6 +26,20 @@ pub struct LogEntry {
     pub level: String,
     pub target: String,
     pub message: String,
+    /// Structured fields recorded on the event other than the message,
+    /// e.g. `port` for `info!(port = 443, "bound")`.
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+}
+
+/// Entries added after a given ID, as returned by
+/// [`LogBuffer::get_logs_since`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogTail {
+    pub entries: Vec<LogEntry>,
+    /// Entries after the given ID that were dropped from the buffer before
+    /// they could be fetched, so the caller knows its view has a gap.
+    pub missed: u64,
 }
 
 /// Thread-safe log buffer with atomic ID generation for differential updates
@@ -28,6 +48,8 @@ pub struct LogBuffer {
     entries: Arc<RwLock<VecDeque<LogEntry>>>,
     /// Atomic counter for generating unique, monotonically increasing log IDs
     next_id: Arc<AtomicU64>,
+    /// Most entries kept, which can change while logging
+    capacity: Arc<AtomicUsize>,
 }
 
 impl Default for LogBuffer {
@@ -38,26 +60,50 @@ impl Default for LogBuffer {
 
 impl LogBuffer {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_LOG_BUFFER_SIZE)
+    }
+
+    /// Creates a buffer keeping at most `capacity` entries (at least one).
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            entries: Arc::new(RwLock::new(VecDeque::with_capacity(MAX_LOG_ENTRIES))),
+            entries: Arc::new(RwLock::new(VecDeque::new())),
             next_id: Arc::new(AtomicU64::new(1)),
+            capacity: Arc::new(AtomicUsize::new(capacity.max(1))),
         }
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Changes how many entries are kept (at least one), dropping the
+    /// oldest straight away if there are now too many.
+    pub fn set_capacity(&self, capacity: usize) {
+        let capacity = capacity.max(1);
+        let mut entries = self.entries.write();
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let excess = entries.len().saturating_sub(capacity);
+        entries.drain(..excess);
+    }
+
     /// Generate the next unique log ID atomically
     fn next_id(&self) -> u64 {
         self.next_id.fetch_add(1, Ordering::Relaxed)
     }
 
     /// Add a new log entry, removing old entries if buffer is full.
-    /// The entry's ID will be set automatically.
-    pub fn push(&self, mut entry: LogEntry) {
-        entry.id = self.next_id();
+    /// The entry's ID will be set automatically and is returned.
+    pub fn push(&self, mut entry: LogEntry) -> u64 {
+        // Assigned under the lock so the buffer stays in ID order
         let mut entries = self.entries.write();
-        if entries.len() >= MAX_LOG_ENTRIES {
+        let id = self.next_id();
+        entry.id = id;
+        let capacity = self.capacity();
+        while entries.len() >= capacity {
             entries.pop_front();
         }
         entries.push_back(entry);
+        id
     }
 
     /// Get all log entries as a vector
@@ -73,12 +119,38 @@ impl LogBuffer {
         entries.iter().skip(skip).cloned().collect()
     }
 
+    /// Get the most recent `count` entries (all if `None`) at or above
+    /// `min_level` in severity, oldest first.
+    pub fn get_filtered(&self, min_level: Level, count: Option<usize>) -> Vec<LogEntry> {
+        let entries = self.entries.read();
+        let mut matching: Vec<LogEntry> = entries
+            .iter()
+            .rev()
+            // `Level` orders more verbose levels as greater, so ERROR is the smallest
+            .filter(|e| e.level.parse::<Level>().is_ok_and(|l| l <= min_level))
+            .take(count.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        matching.reverse();
+        matching
+    }
+
     /// Get all log entries with ID greater than `last_id`.
     /// This enables differential updates - the frontend can track the last
     /// received ID and only fetch new logs.
-    pub fn get_logs_since(&self, last_id: u64) -> Vec<LogEntry> {
+    ///
+    /// If entries after `last_id` were already evicted (or cleared), their
+    /// number is reported in [`LogTail::missed`].
+    pub fn get_logs_since(&self, last_id: u64) -> LogTail {
         let entries = self.entries.read();
-        entries.iter().filter(|e| e.id > last_id).cloned().collect()
+        let oldest = entries
+            .front()
+            .map_or_else(|| self.next_id.load(Ordering::Relaxed), |e| e.id);
+
+        LogTail {
+            entries: entries.iter().filter(|e| e.id > last_id).cloned().collect(),
+            missed: oldest.saturating_sub(last_id + 1),
+        }
     }
 
     /// Get the ID of the most recent log entry, or 0 if buffer is empty.
@@ -103,15 +175,69 @@ impl LogBuffer {
     }
 }
 
+/// Filter directives used when debug logging is enabled.
+pub const DEBUG_LOG_DIRECTIVES: &str = "rai_connect=debug,info";
+
+/// Filter directives used when debug logging is disabled.
+pub const DEFAULT_LOG_DIRECTIVES: &str = "info";
+
+/// The log filter installed on the tracing subscriber, which can be changed
+/// while the app is running.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// Whether the filter came from `RUST_LOG`, which takes precedence over
+    /// the debug logging setting.
+    from_env: bool,
+}
+
+impl LogFilter {
+    /// Wraps `filter` in a reloadable layer, returning the handle and the
+    /// layer to install on the subscriber.
+    pub fn new(filter: EnvFilter, from_env: bool) -> (Self, reload::Layer<EnvFilter, Registry>) {
+        let (layer, handle) = reload::Layer::new(filter);
+        (Self { handle, from_env }, layer)
+    }
+
+    /// Returns the filter directives in effect, e.g. `rai_connect=debug,info`.
+    pub fn directives(&self) -> String {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Switches between debug and regular logging.
+    ///
+    /// Does nothing when the filter was set through `RUST_LOG`.
+    pub fn set_debug(&self, enabled: bool) -> Result<(), String> {
+        if self.from_env {
+            tracing::debug!("Log filter set by RUST_LOG, ignoring debug logging setting");
+            return Ok(());
+        }
+
+        let directives = if enabled {
+            DEBUG_LOG_DIRECTIVES
+        } else {
+            DEFAULT_LOG_DIRECTIVES
+        };
+        self.handle
+            .reload(EnvFilter::new(directives))
+            .map_err(|e| format!("Failed to update log filter: {}", e))
+    }
+}
+
 /// Visitor to extract the message from a tracing event
-struct MessageVisitor {
-    message: String,
+pub(crate) struct MessageVisitor {
+    pub(crate) message: String,
+    /// Every field other than `message`, formatted as strings.
+    pub(crate) fields: HashMap<String, String>,
 }
 
 impl MessageVisitor {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             message: String::new(),
+            fields: HashMap::new(),
         }
     }
 }
@@ -124,9 +250,13 @@ impl Visit for MessageVisitor {
             if self.message.starts_with('"') && self.message.ends_with('"') {
                 self.message = self.message[1..self.message.len() - 1].to_string();
             }
-        } else if self.message.is_empty() {
-            // Fallback: use the first field as the message
-            self.message = format!("{:?}", value);
+        } else {
+            let value = format!("{:?}", value);
+            if self.message.is_empty() {
+                // Fallback: use the first field as the message
+                self.message = value.clone();
+            }
+            self.fields.insert(field.name().to_string(), value);
         }
     }
 
@@ -134,25 +264,58 @@ impl Visit for MessageVisitor {
         if field.name() == "message" || self.message.is_empty() {
             self.message = value.to_string();
         }
+        if field.name() != "message" {
+            self.fields
+                .insert(field.name().to_string(), value.to_string());
+        }
     }
 }
 
-/// A tracing layer that captures log events to a buffer
+/// Fields recorded on a span when it was created, kept in the span's
+/// extensions so events inside it can carry them.
+struct SpanFields(HashMap<String, String>);
+
+/// A tracing layer that captures log events to a buffer.
+///
+/// Fields of the spans an event happened in, such as the `conn_id` of a
+/// proxy connection, are added to the event's own, which win on conflict.
 pub struct LogCaptureLayer {
     buffer: LogBuffer,
+    /// Receives a copy of every new entry for live streaming to the frontend.
+    sender: Option<mpsc::Sender<LogEntry>>,
 }
 
 impl LogCaptureLayer {
     pub fn new(buffer: LogBuffer) -> Self {
-        Self { buffer }
+        Self {
+            buffer,
+            sender: None,
+        }
+    }
+
+    /// Also sends each new entry through `sender`.
+    ///
+    /// Entries are dropped rather than waited on when the channel is full, so
+    /// a slow consumer never stalls logging. The buffer still has them.
+    pub fn with_sender(mut self, sender: mpsc::Sender<LogEntry>) -> Self {
+        self.sender = Some(sender);
+        self
     }
 }
 
 impl<S> Layer<S> for LogCaptureLayer
 where
-    S: Subscriber,
+    S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::new();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
         let metadata = event.metadata();
         let level = *metadata.level();
 
@@ -163,15 +326,38 @@ where
         let mut visitor = MessageVisitor::new();
         event.record(&mut visitor);
 
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    for (name, value) in fields {
+                        visitor
+                            .fields
+                            .entry(name.clone())
+                            .or_insert_with(|| value.clone());
+                    }
+                }
+            }
+        }
+
         let entry = LogEntry {
             id: 0, // Will be assigned by LogBuffer::push()
             timestamp,
             level: level_to_string(level),
             target: metadata.target().to_string(),
             message: visitor.message,
+            fields: visitor.fields,
         };
 
-        self.buffer.push(entry);
+        match &self.sender {
+            Some(sender) => {
+                let mut live = entry.clone();
+                live.id = self.buffer.push(entry);
+                let _ = sender.try_send(live);
+            }
+            None => {
+                self.buffer.push(entry);
+            }
+        }
     }
 }
 
@@ -184,3 +370,305 @@ fn level_to_string(level: Level) -> String {
         Level::ERROR => "ERROR".to_string(),
     }
 }
+
+/// Query parameters and headers whose values are credentials. `h` is the
+/// password hash osu! appends to `/web/` requests.
+pub const SENSITIVE_KEYS: &[&str] = &[
+    "cho-token",
+    "osu-token",
+    "set-cookie",
+    "cookie",
+    "authorization",
+    "h",
+];
+
+/// Placeholder written in place of a masked value.
+pub const REDACTED: &str = "<redacted>";
+
+/// Masks credentials in text about to be logged, such as request paths, URLs
+/// and header lists, so logs can be shared in bug reports.
+///
+/// Query parameters (`?h=...`) are masked up to the next `&`, headers
+/// (`cho-token: ...`) up to the end of the line.
+pub fn sanitize_for_log(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        let at_boundary = text[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|prev| matches!(prev, '?' | '&' | ';' | '"') || prev.is_whitespace());
+
+        if at_boundary {
+            if let Some((prefix_len, value_len)) = sensitive_value_at(&text[i..]) {
+                out.push_str(&text[i..i + prefix_len]);
+                out.push_str(REDACTED);
+                i += prefix_len + value_len;
+                continue;
+            }
+        }
+
+        out.push(c);
+        i += c.len_utf8();
+    }
+
+    out
+}
+
+/// If `text` starts with a sensitive key and a non-empty value, returns the
+/// length of the key with its separator and the length of the value.
+fn sensitive_value_at(text: &str) -> Option<(usize, usize)> {
+    SENSITIVE_KEYS.iter().find_map(|key| {
+        let name = text.get(..key.len())?;
+        if !name.eq_ignore_ascii_case(key) {
+            return None;
+        }
+
+        let rest = &text[key.len()..];
+        let (separator, value_end): (usize, fn(char) -> bool) = if rest.starts_with('=') {
+            (1, |c| {
+                matches!(c, '&' | '#' | ';' | '"') || c.is_whitespace()
+            })
+        } else if let Some(after) = rest.strip_prefix(':') {
+            let spaces = after.len() - after.trim_start_matches(' ').len();
+            (1 + spaces, |c| c == '\n' || c == '\r')
+        } else {
+            return None;
+        };
+
+        let value = &rest[separator..];
+        let value_len = value.find(value_end).unwrap_or(value.len());
+        (value_len > 0).then_some((key.len() + separator, value_len))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn entry(level: &str, message: &str) -> LogEntry {
+        LogEntry {
+            id: 0,
+            timestamp: String::new(),
+            level: level.to_string(),
+            target: "rai_connect".to_string(),
+            message: message.to_string(),
+            fields: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_get_filtered_by_min_level() {
+        let buffer = LogBuffer::new();
+        buffer.push(entry("ERROR", "error"));
+        buffer.push(entry("INFO", "info"));
+        buffer.push(entry("WARN", "warn"));
+        buffer.push(entry("DEBUG", "debug"));
+
+        let messages = |entries: Vec<LogEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.message).collect()
+        };
+
+        // WARN includes ERROR but excludes INFO, keeping chronological order
+        assert_eq!(
+            messages(buffer.get_filtered(Level::WARN, None)),
+            ["error", "warn"]
+        );
+        assert_eq!(
+            messages(buffer.get_filtered(Level::INFO, Some(2))),
+            ["info", "warn"]
+        );
+        assert_eq!(buffer.get_filtered(Level::TRACE, None).len(), 4);
+    }
+
+    #[test]
+    fn test_get_logs_since_returns_only_new_entries() {
+        let buffer = LogBuffer::new();
+        buffer.push(entry("INFO", "first"));
+        let last_id = buffer.push(entry("INFO", "second"));
+
+        let tail = buffer.get_logs_since(last_id);
+        assert!(tail.entries.is_empty());
+        assert_eq!(tail.missed, 0);
+
+        buffer.push(entry("INFO", "third"));
+        buffer.push(entry("INFO", "fourth"));
+        let tail = buffer.get_logs_since(last_id);
+        let messages: Vec<_> = tail.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["third", "fourth"]);
+        assert_eq!(tail.missed, 0);
+    }
+
+    #[test]
+    fn test_get_logs_since_reports_evicted_entries() {
+        let buffer = LogBuffer::with_capacity(5);
+        let last_id = buffer.push(entry("INFO", "seen"));
+
+        // Push enough to evict "seen" and the 10 entries after it
+        for i in 0..15 {
+            buffer.push(entry("INFO", &i.to_string()));
+        }
+
+        let tail = buffer.get_logs_since(last_id);
+        assert_eq!(tail.missed, 10);
+        assert_eq!(tail.entries.len(), 5);
+        assert_eq!(tail.entries[0].id, last_id + 11);
+
+        // Entries cleared before they were fetched count as missed too
+        let last_id = buffer.get_latest_id();
+        buffer.push(entry("INFO", "cleared"));
+        buffer.clear();
+        let tail = buffer.get_logs_since(last_id);
+        assert!(tail.entries.is_empty());
+        assert_eq!(tail.missed, 1);
+    }
+
+    #[test]
+    fn test_capacity_can_change_at_runtime() {
+        let buffer = LogBuffer::with_capacity(3);
+        for i in 0..5 {
+            buffer.push(entry("INFO", &i.to_string()));
+        }
+        let messages = |buffer: &LogBuffer| -> Vec<String> {
+            buffer.get_all().into_iter().map(|e| e.message).collect()
+        };
+        assert_eq!(messages(&buffer), ["2", "3", "4"]);
+
+        // Lowering it drops the oldest right away
+        buffer.set_capacity(2);
+        assert_eq!(messages(&buffer), ["3", "4"]);
+
+        // Raising it keeps more from then on
+        buffer.set_capacity(4);
+        for i in 5..8 {
+            buffer.push(entry("INFO", &i.to_string()));
+        }
+        assert_eq!(messages(&buffer), ["4", "5", "6", "7"]);
+        assert_eq!(buffer.capacity(), 4);
+    }
+
+    #[test]
+    fn test_debug_toggle_changes_captured_messages() {
+        let buffer = LogBuffer::new();
+        let (log_filter, filter_layer) =
+            LogFilter::new(EnvFilter::new(DEFAULT_LOG_DIRECTIVES), false);
+        let subscriber = tracing_subscriber::registry()
+            .with(filter_layer)
+            .with(LogCaptureLayer::new(buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "rai_connect", "hidden");
+
+            log_filter.set_debug(true).unwrap();
+            assert_eq!(log_filter.directives(), DEBUG_LOG_DIRECTIVES);
+            tracing::debug!(target: "rai_connect", "shown");
+
+            log_filter.set_debug(false).unwrap();
+            tracing::debug!(target: "rai_connect", "hidden again");
+        });
+
+        let messages: Vec<String> = buffer.get_all().into_iter().map(|e| e.message).collect();
+        assert_eq!(messages, ["shown"]);
+    }
+
+    #[test]
+    fn test_structured_fields_are_recorded() {
+        let buffer = LogBuffer::new();
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer::new(buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(port = 443, host = "c.localhost", "bound");
+        });
+
+        let entry = &buffer.get_all()[0];
+        assert_eq!(entry.message, "bound");
+        assert_eq!(entry.fields["port"], "443");
+        assert_eq!(entry.fields["host"], "c.localhost");
+        assert!(!entry.fields.contains_key("message"));
+    }
+
+    #[test]
+    fn test_span_fields_are_added_to_events() {
+        let buffer = LogBuffer::new();
+        let subscriber = tracing_subscriber::registry().with(LogCaptureLayer::new(buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _outer = tracing::info_span!("connection", conn_id = 7, port = 443).entered();
+            let _inner = tracing::info_span!("request", path = "/web/osu-search.php").entered();
+            tracing::info!(port = 8443, "handled");
+        });
+
+        let entry = &buffer.get_all()[0];
+        assert_eq!(entry.message, "handled");
+        assert_eq!(entry.fields["conn_id"], "7");
+        assert_eq!(entry.fields["path"], "/web/osu-search.php");
+        // The event's own field wins over the span's
+        assert_eq!(entry.fields["port"], "8443");
+    }
+
+    #[test]
+    fn test_entry_is_buffered_and_streamed() {
+        let buffer = LogBuffer::new();
+        let (tx, mut rx) = mpsc::channel(LOG_CHANNEL_CAPACITY);
+        let subscriber = tracing_subscriber::registry()
+            .with(LogCaptureLayer::new(buffer.clone()).with_sender(tx));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("proxy started");
+        });
+
+        let stored = buffer.get_all();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].message, "proxy started");
+
+        let streamed = rx.try_recv().unwrap();
+        assert_eq!(streamed.id, stored[0].id);
+        assert_eq!(streamed.message, "proxy started");
+    }
+
+    #[test]
+    fn test_sanitize_masks_tokens_in_paths() {
+        assert_eq!(
+            sanitize_for_log("/web/osu-search.php?u=peppy&h=5f4dcc3b&q=camellia"),
+            "/web/osu-search.php?u=peppy&h=<redacted>&q=camellia"
+        );
+        assert_eq!(
+            sanitize_for_log("https://c.localhost/?cho-token=abc123"),
+            "https://c.localhost/?cho-token=<redacted>"
+        );
+        // Keys that merely start like a sensitive one are left alone
+        assert_eq!(
+            sanitize_for_log("/thumb/1l.jpg?height=80"),
+            "/thumb/1l.jpg?height=80"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_masks_header_values() {
+        let headers = "  cho-token: abc123\n  Cookie: session=x; other=y\n  user-agent: osu!";
+        assert_eq!(
+            sanitize_for_log(headers),
+            "  cho-token: <redacted>\n  Cookie: <redacted>\n  user-agent: osu!"
+        );
+        assert_eq!(sanitize_for_log("OSU-TOKEN:xyz"), "OSU-TOKEN:<redacted>");
+    }
+
+    #[test]
+    fn test_full_channel_drops_instead_of_blocking() {
+        let buffer = LogBuffer::new();
+        let (tx, mut rx) = mpsc::channel(1);
+        let subscriber = tracing_subscriber::registry()
+            .with(LogCaptureLayer::new(buffer.clone()).with_sender(tx));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(rx.try_recv().unwrap().message, "first");
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Proxy metrics in the Prometheus text exposition format.
+//!
+//! Served on [`METRICS_PATH`](crate::infrastructure::http_proxy::METRICS_PATH)
+//! for users who scrape the machine running osu!. Metric names are part of
+//! that interface, so existing ones must not be renamed:
+//!
+//! | Metric | Type | Meaning |
+//! |---|---|---|
+//! | `raiconnect_requests_proxied_total` | counter | Requests routed by the proxy |
+//! | `raiconnect_beatmaps_downloaded_total` | counter | Beatmap downloads served |
+//! | `raiconnect_active_connections` | gauge | Client connections currently open |
+//! | `raiconnect_bancho_sent_bytes_total` | counter | Bancho bytes sent from osu! to the server |
+//! | `raiconnect_bancho_received_bytes_total` | counter | Bancho bytes sent from the server to osu! |
+//! | `raiconnect_upload_bytes_per_second` | gauge | Upstream send rate over the last few seconds |
+//! | `raiconnect_download_bytes_per_second` | gauge | Upstream receive rate over the last few seconds |
+
+use std::fmt::Write;
+
+use crate::domain::AppState;
+
+/// Content type of the exposition format produced by [`render`].
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Renders the proxy's counters from `state` as Prometheus text.
+pub fn render(state: &AppState) -> String {
+    let metrics: [(&str, &str, &str, u64); 7] = [
+        (
+            "raiconnect_requests_proxied_total",
+            "counter",
+            "Requests routed by the proxy.",
+            state.requests_proxied,
+        ),
+        (
+            "raiconnect_beatmaps_downloaded_total",
+            "counter",
+            "Beatmap downloads served.",
+            state.beatmaps_downloaded,
+        ),
+        (
+            "raiconnect_active_connections",
+            "gauge",
+            "Client connections currently open.",
+            state.active_connections,
+        ),
+        (
+            "raiconnect_bancho_sent_bytes_total",
+            "counter",
+            "Bancho bytes sent from osu! to the server.",
+            state.bancho_bytes_client_to_server,
+        ),
+        (
+            "raiconnect_bancho_received_bytes_total",
+            "counter",
+            "Bancho bytes sent from the server to osu!.",
+            state.bancho_bytes_server_to_client,
+        ),
+        (
+            "raiconnect_upload_bytes_per_second",
+            "gauge",
+            "Upstream send rate over the last few seconds.",
+            state.upload_bps,
+        ),
+        (
+            "raiconnect_download_bytes_per_second",
+            "gauge",
+            "Upstream receive rate over the last few seconds.",
+            state.download_bps,
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Parses exposition text, checking that every sample follows a `# TYPE`
+    /// line for the same metric. Returns the samples by name.
+    fn parse(text: &str) -> HashMap<String, u64> {
+        let mut typed = None;
+        let mut samples = HashMap::new();
+
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(matches!(kind, "counter" | "gauge"), "bad type: {}", line);
+                typed = Some(name.to_string());
+            } else if line.starts_with("# HELP ") {
+                continue;
+            } else {
+                let (name, value) = line.split_once(' ').unwrap();
+                assert_eq!(typed.as_deref(), Some(name), "untyped sample: {}", line);
+                assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+                samples.insert(name.to_string(), value.parse().unwrap());
+            }
+        }
+
+        samples
+    }
+
+    #[test]
+    fn test_render_is_valid_exposition_text() {
+        let state = AppState {
+            requests_proxied: 42,
+            beatmaps_downloaded: 3,
+            active_connections: 2,
+            bancho_bytes_client_to_server: 100,
+            bancho_bytes_server_to_client: 2048,
+            ..Default::default()
+        };
+
+        let samples = parse(&render(&state));
+
+        assert_eq!(samples["raiconnect_requests_proxied_total"], 42);
+        assert_eq!(samples["raiconnect_beatmaps_downloaded_total"], 3);
+        assert_eq!(samples["raiconnect_active_connections"], 2);
+        assert_eq!(samples["raiconnect_bancho_sent_bytes_total"], 100);
+        assert_eq!(samples["raiconnect_bancho_received_bytes_total"], 2048);
+        assert_eq!(samples["raiconnect_upload_bytes_per_second"], 0);
+        assert_eq!(samples["raiconnect_download_bytes_per_second"], 0);
+    }
+}
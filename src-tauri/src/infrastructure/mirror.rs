@@ -0,0 +1,275 @@
+//! Direct queries against the rai.moe beatmap mirror.
+//!
+//! These run outside of the proxy request path so the UI can check the mirror
+//! without osu! being involved.
+
+use std::time::{Duration, Instant};
+
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{join_base_url, map_to_raimoe_url};
+
+/// Upper bound on how long an availability check may take.
+pub const AVAILABILITY_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Upper bound on how long a reachability check may take. Kept short since
+/// the UI waits on it.
+pub const MIRROR_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Result of checking that the mirror answers at all.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum MirrorStatus {
+    /// The mirror answered, with whatever status.
+    Reachable {
+        /// HTTP status of the answer.
+        status: u16,
+        /// Time until the response headers arrived, in milliseconds.
+        latency_ms: u64,
+    },
+    /// The mirror couldn't be reached or didn't answer in time.
+    Unreachable { message: String },
+}
+
+/// Checks that the mirror at `direct_base_url` answers, with a `HEAD` on the
+/// base URL itself.
+///
+/// Any HTTP response counts as reachable; the status is reported so a wrong
+/// base URL (404) or a mirror in trouble (5xx) can still be told apart.
+pub async fn test_mirror(client: &reqwest::Client, direct_base_url: &str) -> MirrorStatus {
+    let url = join_base_url(direct_base_url, "/");
+
+    tracing::debug!("Testing mirror: {}", url);
+
+    let started = Instant::now();
+    match client.head(&url).timeout(MIRROR_TEST_TIMEOUT).send().await {
+        Ok(resp) => MirrorStatus::Reachable {
+            status: resp.status().as_u16(),
+            latency_ms: started.elapsed().as_millis() as u64,
+        },
+        Err(e) => MirrorStatus::Unreachable {
+            message: unreachable_message(&e, MIRROR_TEST_TIMEOUT),
+        },
+    }
+}
+
+/// Describes why a request to the mirror got no response.
+fn unreachable_message(e: &reqwest::Error, timeout: Duration) -> String {
+    if e.is_timeout() {
+        format!("Mirror did not respond within {}s", timeout.as_secs())
+    } else {
+        format!("Failed to reach mirror: {}", e)
+    }
+}
+
+/// Result of checking whether a beatmapset can be downloaded from the mirror.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum AvailabilityResult {
+    /// The mirror will serve the download.
+    Available {
+        /// Size of the `.osz` in bytes, if the mirror reported it.
+        size: Option<u64>,
+        /// Final URL if the mirror redirected the download elsewhere.
+        redirected_to: Option<String>,
+    },
+    /// The mirror doesn't have this beatmapset.
+    NotFound,
+    /// The mirror refused the request (401/403).
+    AuthRequired,
+    /// The mirror answered with some other status.
+    Unexpected { status: u16 },
+    /// The mirror couldn't be reached or didn't answer in time.
+    Unreachable { message: String },
+}
+
+/// Checks whether a beatmapset is downloadable from the mirror without downloading it.
+///
+/// Issues a `HEAD` request for the same `/d/{id}` path osu! uses for downloads.
+pub async fn check_beatmap_available(
+    client: &reqwest::Client,
+    direct_base_url: &str,
+    beatmapset_id: u64,
+) -> AvailabilityResult {
+    let url = map_to_raimoe_url(&format!("/d/{}", beatmapset_id), direct_base_url);
+
+    tracing::debug!("Checking beatmap availability: {}", url);
+
+    let resp = match client
+        .head(&url)
+        .timeout(AVAILABILITY_CHECK_TIMEOUT)
+        .send()
+        .await
+    {
+        Ok(resp) => resp,
+        Err(e) => {
+            return AvailabilityResult::Unreachable {
+                message: unreachable_message(&e, AVAILABILITY_CHECK_TIMEOUT),
+            };
+        }
+    };
+
+    let redirected_to = (resp.url().as_str() != url).then(|| resp.url().to_string());
+
+    match resp.status() {
+        status if status.is_success() => {
+            // HEAD responses have no body, so read the header rather than the body size
+            let size = resp
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok());
+            AvailabilityResult::Available {
+                size,
+                redirected_to,
+            }
+        }
+        StatusCode::NOT_FOUND => AvailabilityResult::NotFound,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AvailabilityResult::AuthRequired,
+        status => AvailabilityResult::Unexpected {
+            status: status.as_u16(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use bytes::Bytes;
+    use http_body_util::Full;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Request, Response};
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpListener;
+
+    /// Serves a tiny fake mirror on an ephemeral port and returns its base URL.
+    async fn spawn_mock_mirror() -> String {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        let resp = match req.uri().path() {
+                            "/" => Response::builder().body(Full::new(Bytes::new())),
+                            "/d/1" => Response::builder()
+                                .header("content-length", "12345")
+                                .body(Full::new(Bytes::new())),
+                            "/d/2" => Response::builder()
+                                .status(302)
+                                .header("location", "/files/2.osz")
+                                .body(Full::new(Bytes::new())),
+                            "/files/2.osz" => Response::builder().body(Full::new(Bytes::new())),
+                            "/d/3" => Response::builder()
+                                .status(401)
+                                .body(Full::new(Bytes::new())),
+                            _ => Response::builder()
+                                .status(404)
+                                .body(Full::new(Bytes::new())),
+                        };
+                        Ok::<_, Infallible>(resp.unwrap())
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_available_reports_size() {
+        let base = spawn_mock_mirror().await;
+        let result = check_beatmap_available(&reqwest::Client::new(), &base, 1).await;
+
+        assert_eq!(
+            result,
+            AvailabilityResult::Available {
+                size: Some(12345),
+                redirected_to: None
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect_is_reported() {
+        let base = spawn_mock_mirror().await;
+        let result = check_beatmap_available(&reqwest::Client::new(), &base, 2).await;
+
+        match result {
+            AvailabilityResult::Available { redirected_to, .. } => {
+                assert_eq!(redirected_to, Some(format!("{}/files/2.osz", base)));
+            }
+            other => panic!("expected Available, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_required_distinct_from_not_found() {
+        let base = spawn_mock_mirror().await;
+        let client = reqwest::Client::new();
+
+        assert_eq!(
+            check_beatmap_available(&client, &base, 3).await,
+            AvailabilityResult::AuthRequired
+        );
+        assert_eq!(
+            check_beatmap_available(&client, &base, 4).await,
+            AvailabilityResult::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_mirror() {
+        // Bind then drop to get a port nothing is listening on
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let result =
+            check_beatmap_available(&reqwest::Client::new(), &format!("http://{}", addr), 1).await;
+
+        assert!(matches!(result, AvailabilityResult::Unreachable { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_mirror_reachable_reports_status() {
+        let base = spawn_mock_mirror().await;
+        let client = reqwest::Client::new();
+
+        match test_mirror(&client, &base).await {
+            MirrorStatus::Reachable { status, .. } => assert_eq!(status, 200),
+            other => panic!("expected Reachable, got {:?}", other),
+        }
+
+        // A base URL pointing at the wrong place still answers, just not with 200
+        match test_mirror(&client, &format!("{}/wrong", base)).await {
+            MirrorStatus::Reachable { status, .. } => assert_eq!(status, 404),
+            other => panic!("expected Reachable, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirror_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let result = test_mirror(&reqwest::Client::new(), &format!("http://{}", addr)).await;
+
+        assert!(matches!(result, MirrorStatus::Unreachable { .. }));
+    }
+}
@@ -1,5 +1,20 @@
+pub mod atomic_file;
+pub mod beatmap_cache;
+pub mod body;
+pub mod elevation;
+pub mod event_log;
 pub mod hosts;
 pub mod http_proxy;
+pub mod idle;
 pub mod logging;
+pub mod metrics;
+pub mod mirror;
+pub mod packet_capture;
+pub mod port;
+pub mod process;
+pub mod rate_limit;
+pub mod search_cache;
+pub mod self_test;
 pub mod storage;
+pub mod throughput;
 pub mod tls;
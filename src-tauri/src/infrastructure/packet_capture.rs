@@ -0,0 +1,134 @@
+//! Ring buffer of raw Bancho packets for debugging.
+//!
+//! With [`ProxyConfig::capture_packets`](crate::domain::ProxyConfig) on, the
+//! proxy records every packet Bancho sends, as received and before any
+//! rewriting, keeping only the last [`PACKET_CAPTURE_CAPACITY`]. Tokens
+//! travel in HTTP headers rather than packets, so nothing is redacted.
+
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::Packet;
+
+/// Most packets kept at once; older ones are dropped first.
+pub const PACKET_CAPTURE_CAPACITY: usize = 256;
+
+/// One captured server packet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedPacket {
+    pub packet_id: u16,
+    /// Name of the [`ServerPacketId`](crate::domain::ServerPacketId), or
+    /// `Unknown`.
+    pub packet_type: String,
+    /// Header and payload as space-separated hex bytes.
+    pub hex: String,
+}
+
+impl CapturedPacket {
+    fn new(packet: &Packet) -> Self {
+        let hex = packet
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            packet_id: packet.header.packet_id,
+            packet_type: format!("{:?}", packet.packet_type()),
+            hex,
+        }
+    }
+}
+
+/// Bounded buffer of the most recent server packets.
+pub struct PacketCapture {
+    capacity: usize,
+    packets: Mutex<VecDeque<CapturedPacket>>,
+}
+
+impl Default for PacketCapture {
+    fn default() -> Self {
+        Self::new(PACKET_CAPTURE_CAPACITY)
+    }
+}
+
+impl PacketCapture {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            packets: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Records `packet`, dropping the oldest one if the buffer is full.
+    pub fn record(&self, packet: &Packet) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut packets = self.packets.lock();
+        if packets.len() >= self.capacity {
+            packets.pop_front();
+        }
+        packets.push_back(CapturedPacket::new(packet));
+    }
+
+    /// The captured packets, oldest first.
+    pub fn get_all(&self) -> Vec<CapturedPacket> {
+        self.packets.lock().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.packets.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ServerPacketId;
+
+    #[test]
+    fn test_captures_packets_in_order_with_types() {
+        let capture = PacketCapture::default();
+
+        capture.record(&Packet::new(
+            ServerPacketId::LoginReply,
+            vec![0x2a, 0, 0, 0],
+        ));
+        capture.record(&Packet::new(
+            ServerPacketId::UserPrivileges,
+            vec![1, 0, 0, 0],
+        ));
+        capture.record(&Packet::new(ServerPacketId::Notification, Vec::new()));
+
+        let packets = capture.get_all();
+        let types: Vec<_> = packets.iter().map(|p| p.packet_type.as_str()).collect();
+        assert_eq!(types, ["LoginReply", "UserPrivileges", "Notification"]);
+        assert_eq!(packets[1].packet_id, 71);
+        assert_eq!(packets[1].hex, "47 00 00 04 00 00 00 01 00 00 00");
+        assert_eq!(packets[2].hex, "18 00 00 00 00 00 00");
+    }
+
+    #[test]
+    fn test_oldest_packets_dropped_at_capacity() {
+        let capture = PacketCapture::new(2);
+
+        for id in [
+            ServerPacketId::LoginReply,
+            ServerPacketId::UserStats,
+            ServerPacketId::ChannelInfo,
+        ] {
+            capture.record(&Packet::new(id, Vec::new()));
+        }
+
+        let ids: Vec<_> = capture.get_all().iter().map(|p| p.packet_id).collect();
+        assert_eq!(ids, [11, 64]);
+
+        capture.clear();
+        assert!(capture.get_all().is_empty());
+    }
+}
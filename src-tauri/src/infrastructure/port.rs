@@ -0,0 +1,226 @@
+//! Checks for whether the proxy's listening port can be bound.
+//!
+//! Port 443 is often held by something else on Windows (IIS, Skype, VMware).
+//! Rather than just reporting that binding failed, the owning process is
+//! looked up so the UI can tell the user exactly what to close.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Whether a port can be bound, and if not, why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PortStatus {
+    Free,
+    /// Another program listens on the port.
+    InUse {
+        /// Name of that program, if it could be determined.
+        process: Option<String>,
+    },
+    /// Binding the port needs privileges the process lacks.
+    PermissionDenied,
+    /// Binding failed for some other reason.
+    Unavailable {
+        message: String,
+    },
+}
+
+/// Tries to bind `port` on `ip`, the address the proxy listens on, then
+/// releases it straight away.
+///
+/// Fails with [`io::ErrorKind::AddrInUse`] if another program holds the port,
+/// or [`io::ErrorKind::PermissionDenied`] if binding it needs privileges the
+/// process lacks.
+pub fn probe_port(ip: IpAddr, port: u16) -> io::Result<()> {
+    TcpListener::bind(SocketAddr::new(ip, port)).map(drop)
+}
+
+/// How often [`wait_for_port_release`] retries binding.
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Waits until `port` can be bound on `ip` again, giving up after `timeout`.
+///
+/// The OS can take a moment to release a port after its listener is closed,
+/// so rebinding straight after stopping the proxy may still fail with
+/// `AddrInUse`. Returns whether the port was released in time.
+pub async fn wait_for_port_release(ip: IpAddr, port: u16, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        match probe_port(ip, port) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {}
+            _ => return true,
+        }
+
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(RELEASE_POLL_INTERVAL).await;
+    }
+}
+
+/// Checks whether the proxy could bind `port` on `ip`, naming the process
+/// that holds it if it's taken.
+pub fn check_port_available(ip: IpAddr, port: u16) -> PortStatus {
+    match probe_port(ip, port) {
+        Ok(()) => PortStatus::Free,
+        Err(e) => match e.kind() {
+            io::ErrorKind::AddrInUse => PortStatus::InUse {
+                process: port_owner(port),
+            },
+            io::ErrorKind::PermissionDenied => PortStatus::PermissionDenied,
+            _ => PortStatus::Unavailable {
+                message: e.to_string(),
+            },
+        },
+    }
+}
+
+/// Returns the name of the process listening on `port`, if it can be found.
+#[cfg(target_os = "windows")]
+pub fn port_owner(port: u16) -> Option<String> {
+    use std::time::Duration;
+
+    use crate::infrastructure::process::{background_command, run_with_timeout};
+
+    const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+    let netstat = run_with_timeout(
+        background_command("netstat").args(["-ano", "-p", "TCP"]),
+        LOOKUP_TIMEOUT,
+    )
+    .ok()?;
+    let pid = listening_pid(&String::from_utf8_lossy(&netstat.stdout), port)?;
+
+    let tasklist = run_with_timeout(
+        background_command("tasklist").args([
+            "/FI",
+            &format!("PID eq {}", pid),
+            "/FO",
+            "CSV",
+            "/NH",
+        ]),
+        LOOKUP_TIMEOUT,
+    )
+    .ok()?;
+
+    Some(
+        tasklist_image_name(&String::from_utf8_lossy(&tasklist.stdout))
+            .map(|name| format!("{} (PID {})", name, pid))
+            .unwrap_or_else(|| format!("PID {}", pid)),
+    )
+}
+
+/// Returns the name of the process listening on `port`, if it can be found.
+#[cfg(not(target_os = "windows"))]
+pub fn port_owner(_port: u16) -> Option<String> {
+    None
+}
+
+/// Finds the PID listening on `port` in `netstat -ano` output.
+///
+/// Listening sockets are recognized by their `:0` foreign address rather than
+/// the state column, which Windows localizes.
+#[cfg(any(target_os = "windows", test))]
+fn listening_pid(netstat: &str, port: u16) -> Option<u32> {
+    let suffix = format!(":{}", port);
+    netstat.lines().find_map(|line| {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["TCP", local, foreign, _state, pid]
+                if local.ends_with(&suffix) && foreign.ends_with(":0") =>
+            {
+                pid.parse().ok()
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Extracts the image name from `tasklist /FO CSV /NH` output.
+#[cfg(any(target_os = "windows", test))]
+fn tasklist_image_name(tasklist: &str) -> Option<String> {
+    let line = tasklist.lines().next()?;
+    let name = line.strip_prefix('"')?.split('"').next()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_free_port() {
+        // Bind then drop to get a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        assert_eq!(check_port_available(LOCALHOST, port), PortStatus::Free);
+    }
+
+    #[test]
+    fn test_port_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(matches!(
+            check_port_available(LOCALHOST, port),
+            PortStatus::InUse { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_port_release() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(!wait_for_port_release(LOCALHOST, port, Duration::from_millis(100)).await);
+
+        // Close the listener a little later, as a stopping proxy would
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(listener);
+        });
+
+        assert!(wait_for_port_release(LOCALHOST, port, Duration::from_secs(5)).await);
+        tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_listening_pid_from_netstat() {
+        let netstat = "
+Active Connections
+
+  Proto  Local Address          Foreign Address        State           PID
+  TCP    0.0.0.0:135            0.0.0.0:0              LISTENING       1044
+  TCP    127.0.0.1:4430         0.0.0.0:0              LISTENING       77
+  TCP    127.0.0.1:50123        127.0.0.1:443          ESTABLISHED     9000
+  TCP    [::]:443               [::]:0                 LISTENING       4
+";
+
+        assert_eq!(listening_pid(netstat, 443), Some(4));
+        assert_eq!(listening_pid(netstat, 135), Some(1044));
+        assert_eq!(listening_pid(netstat, 80), None);
+    }
+
+    #[test]
+    fn test_tasklist_image_name() {
+        let tasklist = "\"Skype.exe\",\"4321\",\"Console\",\"1\",\"120,000 K\"\r\n";
+        assert_eq!(tasklist_image_name(tasklist), Some("Skype.exe".to_string()));
+        assert_eq!(
+            tasklist_image_name("INFO: No tasks are running which match the specified criteria."),
+            None
+        );
+    }
+}
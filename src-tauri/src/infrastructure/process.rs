@@ -0,0 +1,122 @@
+//! Helpers for running external processes.
+
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::process::{Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often to check whether the child has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Process creation flag that starts a console program without a console
+/// window.
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// A [`Command`] for a console tool run in the background, such as
+/// `certutil` or `tasklist`.
+///
+/// On Windows it's started without a console window; otherwise each run
+/// flashes one over the app, which is jarring from the tray.
+pub fn background_command(program: impl AsRef<OsStr>) -> Command {
+    #[allow(unused_mut)]
+    let mut cmd = Command::new(program);
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+    cmd
+}
+
+/// Runs a command to completion, killing it if it takes longer than `timeout`.
+///
+/// Behaves like [`Command::output`], except that a child still running at the
+/// deadline is killed and an error of kind [`io::ErrorKind::TimedOut`] is
+/// returned. Stdin is closed so the child can't block waiting for input.
+pub fn run_with_timeout(cmd: &mut Command, timeout: Duration) -> io::Result<Output> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drain the pipes while waiting so a chatty child can't fill them and stall
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("process did not exit within {:?}", timeout),
+            ));
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: join_reader(stdout),
+        stderr: join_reader(stderr),
+    })
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+fn join_reader(handle: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    handle.and_then(|h| h.join().ok()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background_command_keeps_program_and_args() {
+        let mut cmd = background_command("certutil");
+        cmd.args(["-store", "-user", "Root"]);
+
+        assert_eq!(cmd.get_program(), "certutil");
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            ["-store", "-user", "Root"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_captures_output() {
+        let output =
+            run_with_timeout(Command::new("echo").arg("hello"), Duration::from_secs(5)).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kills_child_on_timeout() {
+        let started = Instant::now();
+        let err = run_with_timeout(Command::new("sleep").arg("10"), Duration::from_millis(100))
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,177 @@
+//! Rate limiting for requests sent to the beatmap mirror.
+//!
+//! Each kind of mirror request gets a token bucket holding up to a minute's
+//! worth of requests, refilled continuously. Occasional downloads during play
+//! never come close to the limit; only sustained bursts are turned away.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::domain::ProxyConfig;
+
+/// A token bucket allowing `capacity` requests at once, refilled at a steady
+/// rate.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    /// Tokens left, and when they were last topped up.
+    tokens: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// A bucket allowing `per_minute` requests per minute, starting full.
+    /// Returns `None` for 0, meaning unlimited.
+    pub fn per_minute(per_minute: u32) -> Option<Self> {
+        (per_minute > 0).then(|| {
+            let capacity = f64::from(per_minute);
+            Self {
+                capacity,
+                refill_per_sec: capacity / 60.0,
+                tokens: Mutex::new((capacity, Instant::now())),
+            }
+        })
+    }
+
+    /// Takes a token if one is available. Otherwise returns how long until
+    /// the next one is.
+    pub fn try_take(&self) -> Result<(), Duration> {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut guard = self.tokens.lock();
+        let (tokens, last) = &mut *guard;
+
+        let refilled = now.saturating_duration_since(*last).as_secs_f64() * self.refill_per_sec;
+        *tokens = (*tokens + refilled).min(self.capacity);
+        *last = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - *tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
+
+/// Kinds of mirror request limited separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorRequestKind {
+    Download,
+    Search,
+}
+
+impl MirrorRequestKind {
+    /// Classifies a request path, or `None` if it isn't limited.
+    pub fn of_path(path: &str) -> Option<Self> {
+        if path.starts_with("/d/") {
+            Some(Self::Download)
+        } else if path.starts_with("/web/osu-search.php") {
+            Some(Self::Search)
+        } else {
+            None
+        }
+    }
+}
+
+/// Per-kind rate limits for requests the proxy sends to the mirror.
+#[derive(Debug)]
+pub struct MirrorRateLimiter {
+    downloads: Option<TokenBucket>,
+    searches: Option<TokenBucket>,
+}
+
+impl MirrorRateLimiter {
+    pub fn new(config: &ProxyConfig) -> Self {
+        Self {
+            downloads: TokenBucket::per_minute(config.max_downloads_per_minute),
+            searches: TokenBucket::per_minute(config.max_searches_per_minute),
+        }
+    }
+
+    /// Counts a request to `path` against its limit. Returns how long to wait
+    /// if it's over the limit.
+    pub fn check(&self, path: &str) -> Result<(), (MirrorRequestKind, Duration)> {
+        let Some(kind) = MirrorRequestKind::of_path(path) else {
+            return Ok(());
+        };
+
+        let bucket = match kind {
+            MirrorRequestKind::Download => &self.downloads,
+            MirrorRequestKind::Search => &self.searches,
+        };
+
+        match bucket {
+            Some(bucket) => bucket.try_take().map_err(|wait| (kind, wait)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_beyond_capacity_is_refused() {
+        let bucket = TokenBucket::per_minute(3).unwrap();
+        let t0 = bucket.tokens.lock().1;
+
+        for _ in 0..3 {
+            assert!(bucket.try_take_at(t0).is_ok());
+        }
+        let wait = bucket.try_take_at(t0).unwrap_err();
+
+        // One token every 20 seconds at 3 per minute
+        assert_eq!(wait, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let bucket = TokenBucket::per_minute(60).unwrap();
+        let t0 = bucket.tokens.lock().1;
+
+        for _ in 0..60 {
+            assert!(bucket.try_take_at(t0).is_ok());
+        }
+        assert!(bucket.try_take_at(t0).is_err());
+
+        // One token per second comes back
+        let t1 = t0 + Duration::from_secs(2);
+        assert!(bucket.try_take_at(t1).is_ok());
+        assert!(bucket.try_take_at(t1).is_ok());
+        assert!(bucket.try_take_at(t1).is_err());
+
+        // A long pause refills the bucket, but never past its capacity
+        let t2 = t1 + Duration::from_secs(3600);
+        for _ in 0..60 {
+            assert!(bucket.try_take_at(t2).is_ok());
+        }
+        assert!(bucket.try_take_at(t2).is_err());
+    }
+
+    #[test]
+    fn test_limits_apply_per_kind() {
+        let limiter = MirrorRateLimiter::new(&ProxyConfig {
+            max_downloads_per_minute: 1,
+            max_searches_per_minute: 0,
+            ..ProxyConfig::default()
+        });
+
+        assert!(limiter.check("/d/1").is_ok());
+        assert!(matches!(
+            limiter.check("/d/2"),
+            Err((MirrorRequestKind::Download, _))
+        ));
+        // Searches are unlimited and thumbnails aren't limited at all
+        for _ in 0..100 {
+            assert!(limiter.check("/web/osu-search.php?q=test").is_ok());
+            assert!(limiter.check("/thumb/1l.jpg").is_ok());
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Short-lived in-memory cache for osu!direct search results.
+//!
+//! Paging back and forth through search results repeats the same queries,
+//! and their results barely change from one minute to the next. Responses
+//! are kept for [`SEARCH_CACHE_TTL`], keyed by the full path and query, with
+//! at most [`SEARCH_CACHE_CAPACITY`] held at once.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+/// How long a search result is served from memory.
+pub const SEARCH_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Most search results held at once.
+pub const SEARCH_CACHE_CAPACITY: usize = 128;
+
+/// A search response read back from the cache.
+#[derive(Debug, Clone)]
+pub struct CachedSearch {
+    pub data: Bytes,
+    pub content_type: Option<String>,
+}
+
+/// Time-limited cache of search responses, bounded by entry count.
+pub struct SearchCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<String, (Instant, CachedSearch)>>,
+}
+
+impl Default for SearchCache {
+    fn default() -> Self {
+        Self::new(SEARCH_CACHE_TTL, SEARCH_CACHE_CAPACITY)
+    }
+}
+
+impl SearchCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cache key for a search path such as
+    /// `/web/osu-search.php?q=...`, or `None` for anything else.
+    pub fn key_for_path(path: &str) -> Option<String> {
+        path.starts_with("/web/osu-search.php")
+            .then(|| path.to_string())
+    }
+
+    /// Looks up a search result stored less than the TTL ago.
+    pub fn get(&self, key: &str) -> Option<CachedSearch> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Stores a search result, making room if the cache is full.
+    pub fn insert(&self, key: String, data: Bytes, content_type: Option<String>) {
+        self.insert_at(key, data, content_type, Instant::now());
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<CachedSearch> {
+        let mut entries = self.entries.lock();
+        match entries.get(key) {
+            Some((stored, hit)) if now.saturating_duration_since(*stored) < self.ttl => {
+                Some(hit.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert_at(&self, key: String, data: Bytes, content_type: Option<String>, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock();
+
+        if !entries.contains_key(&key) && entries.len() >= self.capacity {
+            // Expired entries go first, then the oldest if that wasn't enough
+            entries.retain(|_, (stored, _)| now.saturating_duration_since(*stored) < self.ttl);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (stored, _))| *stored)
+                    .map(|(k, _)| k.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.insert(key, (now, CachedSearch { data, content_type }));
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEARCH: &str = "/web/osu-search.php?r=0&q=test&m=-1&p=0";
+
+    #[test]
+    fn test_key_for_path() {
+        assert_eq!(SearchCache::key_for_path(SEARCH), Some(SEARCH.to_string()));
+        assert_eq!(SearchCache::key_for_path("/d/123"), None);
+        assert_eq!(
+            SearchCache::key_for_path("/web/osu-search-set.php?s=1"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_hit_within_ttl() {
+        let cache = SearchCache::new(Duration::from_secs(60), 4);
+        let t0 = Instant::now();
+
+        cache.insert_at(
+            SEARCH.to_string(),
+            Bytes::from_static(b"results"),
+            Some("text/plain".to_string()),
+            t0,
+        );
+        let hit = cache.get_at(SEARCH, t0 + Duration::from_secs(59)).unwrap();
+
+        assert_eq!(hit.data, Bytes::from_static(b"results"));
+        assert_eq!(hit.content_type.as_deref(), Some("text/plain"));
+        assert!(cache.get_at("/web/osu-search.php?q=other", t0).is_none());
+    }
+
+    #[test]
+    fn test_miss_after_expiry() {
+        let cache = SearchCache::new(Duration::from_secs(60), 4);
+        let t0 = Instant::now();
+
+        cache.insert_at(SEARCH.to_string(), Bytes::from_static(b"results"), None, t0);
+
+        assert!(cache.get_at(SEARCH, t0 + Duration::from_secs(60)).is_none());
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_evicts_oldest_at_capacity() {
+        let cache = SearchCache::new(Duration::from_secs(60), 2);
+        let t0 = Instant::now();
+
+        for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+            cache.insert_at(
+                key.to_string(),
+                Bytes::new(),
+                None,
+                t0 + Duration::from_secs(i as u64),
+            );
+        }
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at("a", t0).is_none());
+        assert!(cache.get_at("b", t0).is_some());
+        assert!(cache.get_at("c", t0).is_some());
+    }
+}
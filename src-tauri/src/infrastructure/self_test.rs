@@ -0,0 +1,113 @@
+//! End-to-end check that the running proxy intercepts and routes requests.
+//!
+//! Sends an osu!direct search through the local listener the same way osu!
+//! would, trusting only the proxy's own certificate, and reports how the
+//! proxy routed it. osu! doesn't need to be running.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use rustls::pki_types::CertificateDer;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::RouteDecision;
+use crate::infrastructure::http_proxy::{route_header_value, ROUTE_HEADER};
+
+/// Request sent through the proxy: an empty osu!direct search, which should
+/// be routed to the mirror.
+pub const SELF_TEST_PATH: &str = "/web/osu-search.php?r=0&q=&m=-1&p=0";
+
+/// Upper bound on the whole round trip, including the mirror's response.
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of [`run_self_test`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    /// The proxy accepted the request and sent it to the mirror.
+    pub routed_locally: bool,
+    /// Status of the response that came back, if one did.
+    pub status: Option<u16>,
+    /// Round trip time in milliseconds.
+    pub elapsed_ms: u64,
+    /// Why the request failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Sends [`SELF_TEST_PATH`] through the proxy listening on `bind_ip` and
+/// `port`. A proxy listening on every address is reached over loopback.
+///
+/// `cert` is the certificate the proxy serves. It's the only one trusted, so
+/// a response proves the request reached this proxy and not something else
+/// on the port.
+pub async fn run_self_test(
+    bind_ip: IpAddr,
+    port: u16,
+    cert: &CertificateDer<'_>,
+) -> SelfTestReport {
+    let ip = match bind_ip {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    run_self_test_at(SocketAddr::new(ip, port), cert).await
+}
+
+/// Like [`run_self_test`], but connects to the listener at `addr`, for
+/// example the IPv6 loopback one.
+pub async fn run_self_test_at(addr: SocketAddr, cert: &CertificateDer<'_>) -> SelfTestReport {
+    let started = Instant::now();
+    let result = send_test_request(addr, cert).await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(resp) => {
+            let routed_locally = resp
+                .headers()
+                .get(ROUTE_HEADER)
+                .is_some_and(|v| v == route_header_value(RouteDecision::HandleLocally));
+            SelfTestReport {
+                routed_locally,
+                status: Some(resp.status().as_u16()),
+                elapsed_ms,
+                error: None,
+            }
+        }
+        Err(e) => SelfTestReport {
+            routed_locally: false,
+            status: None,
+            elapsed_ms,
+            error: Some(e),
+        },
+    }
+}
+
+async fn send_test_request(
+    addr: SocketAddr,
+    cert: &CertificateDer<'_>,
+) -> Result<reqwest::Response, String> {
+    let cert = reqwest::Certificate::from_der(cert)
+        .map_err(|e| format!("Invalid proxy certificate: {}", e))?;
+
+    // Resolve the osu! host to the listener directly, so this works even
+    // before the hosts file has been edited
+    let client = reqwest::Client::builder()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(cert)
+        .resolve("osu.localhost", addr)
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(SELF_TEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let url = format!("https://osu.localhost:{}{}", addr.port(), SELF_TEST_PATH);
+    client.get(&url).send().await.map_err(|e| {
+        if e.is_timeout() {
+            format!(
+                "The proxy did not respond within {}s",
+                SELF_TEST_TIMEOUT.as_secs()
+            )
+        } else {
+            format!("Request through the proxy failed: {}", e)
+        }
+    })
+}
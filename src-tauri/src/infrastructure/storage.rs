@@ -1,33 +1,235 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use serde_json::json;
+use serde_json::{json, Map, Value};
 use tauri::Manager;
-use tauri_plugin_store::StoreExt;
 
-use crate::domain::AppConfig;
+use crate::domain::{normalize_base_url, AppConfig, ConfigError, ProxyConfig, CONFIG_VERSION};
+use crate::infrastructure::atomic_file::write_atomically;
 
 const STORE_FILE: &str = "settings.json";
 const CONFIG_KEY: &str = "config";
 
 pub fn load_config(app_handle: &tauri::AppHandle) -> AppConfig {
-    match app_handle.store(STORE_FILE) {
-        Ok(store) => match store.get(CONFIG_KEY) {
-            Some(value) => serde_json::from_value(value.clone()).unwrap_or_default(),
+    match get_store_path(app_handle) {
+        Some(path) => load_config_from(&path),
+        None => {
+            tracing::warn!("Failed to resolve the app data directory");
+            AppConfig::default()
+        }
+    }
+}
+
+/// Reads the config stored in the settings file at `path`, falling back to
+/// the defaults if it is missing or unreadable.
+pub fn load_config_from(path: &Path) -> AppConfig {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to read {}: {}", path.display(), e);
+            }
+            return AppConfig::default();
+        }
+    };
+
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(mut store) => match store.get_mut(CONFIG_KEY) {
+            Some(value) => {
+                let mut config = migrate(value.take());
+                replace_invalid_urls(&mut config.proxy);
+                disable_looping_interception(&mut config.proxy);
+                config
+            }
             None => AppConfig::default(),
         },
         Err(e) => {
-            tracing::warn!("Failed to load store: {}", e);
+            tracing::warn!("Failed to parse {}: {}", path.display(), e);
             AppConfig::default()
         }
     }
 }
 
+/// Upgrades a stored config of any version to the current [`AppConfig`].
+///
+/// Configs saved before versioning count as version 0. Rather than resetting
+/// everything when one field doesn't fit, each stored field is kept if it
+/// still deserializes and replaced by its default otherwise, so the osu! path
+/// and port settings survive schema changes.
+pub fn migrate(value: Value) -> AppConfig {
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > u64::from(CONFIG_VERSION) {
+        tracing::warn!(
+            "Config version {} is newer than supported version {}",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    // Version 0 has the same shape as version 1, it just wasn't tagged. Later
+    // renames go here, each upgrading one version to the next.
+
+    let mut merged = json!(AppConfig::default());
+    merge_valid_fields(&mut merged, &mut Vec::new(), &value);
+
+    let mut config: AppConfig = serde_json::from_value(merged).unwrap_or_default();
+    config.version = CONFIG_VERSION;
+    normalize_urls(&mut config.proxy);
+    config
+}
+
+/// Normalizes the mirror URLs, for example adding a missing scheme, so a
+/// typo doesn't show up later as a 502 from every mirror request.
+fn normalize_urls(proxy: &mut ProxyConfig) {
+    for (field, value) in [
+        ("api_base_url", &mut proxy.api_base_url),
+        ("direct_base_url", &mut proxy.direct_base_url),
+    ] {
+        let normalized = normalize_base_url(value);
+        if normalized != *value {
+            tracing::warn!("Changed {} from {:?} to {:?}", field, value, normalized);
+            *value = normalized;
+        }
+    }
+}
+
+/// Replaces mirror URLs that aren't valid even after normalizing with the
+/// defaults, so a broken settings file still leaves a working proxy.
+fn replace_invalid_urls(proxy: &mut ProxyConfig) {
+    let defaults = ProxyConfig::default();
+    while let Err(ConfigError::InvalidOrigin { field, value }) = proxy.validate() {
+        tracing::warn!("Replacing invalid {} {:?} with the default", field, value);
+        match field {
+            "api_base_url" => proxy.api_base_url = defaults.api_base_url.clone(),
+            _ => proxy.direct_base_url = defaults.direct_base_url.clone(),
+        }
+    }
+}
+
+/// Turns `intercept_real_hosts` off when it would send upstream requests
+/// back to the proxy, which configs saved before it was validated allowed.
+fn disable_looping_interception(proxy: &mut ProxyConfig) {
+    if proxy.intercept_real_hosts && proxy.upstream_is_official() {
+        tracing::warn!(
+            "Turning off intercept_real_hosts: it can't be used with upstream_server {:?}",
+            proxy.upstream_server
+        );
+        proxy.intercept_real_hosts = false;
+    }
+}
+
+/// Copies the fields of `stored` at `path` into `merged`, skipping any that
+/// would stop `merged` from deserializing as an [`AppConfig`]. Nested objects
+/// that exist in the defaults are merged field by field too.
+fn merge_valid_fields(merged: &mut Value, path: &mut Vec<String>, stored: &Value) {
+    let Some(fields) = stored.as_object() else {
+        return;
+    };
+
+    for (key, value) in fields {
+        if key == "version" && path.is_empty() {
+            continue;
+        }
+
+        let nested = value.is_object()
+            && object_at(merged, path)
+                .is_some_and(|obj| obj.get(key).is_some_and(Value::is_object));
+        if nested {
+            path.push(key.clone());
+            merge_valid_fields(merged, path, value);
+            path.pop();
+            continue;
+        }
+
+        let Some(obj) = object_at(merged, path) else {
+            return;
+        };
+        let previous = obj.insert(key.clone(), value.clone());
+
+        if serde_json::from_value::<AppConfig>(merged.clone()).is_err() {
+            tracing::warn!("Dropping invalid config field {}", path_display(path, key));
+            if let Some(obj) = object_at(merged, path) {
+                match previous {
+                    Some(previous) => obj.insert(key.clone(), previous),
+                    None => obj.remove(key),
+                };
+            }
+        }
+    }
+}
+
+fn object_at<'a>(root: &'a mut Value, path: &[String]) -> Option<&'a mut Map<String, Value>> {
+    path.iter()
+        .try_fold(root, |value, key| value.get_mut(key))?
+        .as_object_mut()
+}
+
+fn path_display(path: &[String], key: &str) -> String {
+    path.iter()
+        .map(String::as_str)
+        .chain(std::iter::once(key))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Fields that only make sense on the machine they were set on, left out of
+/// exported configs. Launch profiles carry osu! paths too.
+const MACHINE_SPECIFIC_FIELDS: &[&str] = &["osu_path", "profiles", "active_profile"];
+
+/// Serializes `config` as pretty JSON for sharing, without machine-specific
+/// fields such as the osu! path.
+pub fn export_config_json(config: &AppConfig) -> String {
+    let mut value = json!(config);
+    if let Some(fields) = value.as_object_mut() {
+        for field in MACHINE_SPECIFIC_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    serde_json::to_string_pretty(&value).unwrap_or_default()
+}
+
+/// Reads a config exported with [`export_config_json`], possibly from an
+/// older version, keeping the machine-specific fields of `current`.
+pub fn import_config_json(json: &str, current: &AppConfig) -> Result<AppConfig, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid config file: {}", e))?;
+    if !value.is_object() {
+        return Err("Invalid config file: expected a JSON object".to_string());
+    }
+
+    let mut config = migrate(value);
+    config.osu_path = current.osu_path.clone();
+    config.profiles = current.profiles.clone();
+    config.active_profile = current.active_profile;
+    config.proxy.validate().map_err(|e| e.to_string())?;
+    Ok(config)
+}
+
 pub fn save_config(app_handle: &tauri::AppHandle, config: &AppConfig) -> Result<(), String> {
-    let store = app_handle.store(STORE_FILE).map_err(|e| e.to_string())?;
-    let value = json!(config);
-    store.set(CONFIG_KEY.to_string(), value);
-    store.save().map_err(|e| e.to_string())?;
-    Ok(())
+    let path = get_store_path(app_handle)
+        .ok_or_else(|| "Failed to resolve the app data directory".to_string())?;
+    save_config_to(&path, config)
+}
+
+/// Writes `config` to the settings file at `path`.
+///
+/// The file is replaced atomically, so a crash or power loss mid-save leaves
+/// the previous config in place instead of a truncated file. Other keys
+/// already in the file are kept.
+pub fn save_config_to(path: &Path, config: &AppConfig) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let mut store = fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Value>(&contents).ok())
+        .filter(Value::is_object)
+        .unwrap_or_else(|| json!({}));
+    store[CONFIG_KEY] = json!(config);
+
+    let contents = serde_json::to_string_pretty(&store).map_err(|e| e.to_string())?;
+    write_atomically(path, contents.as_bytes()).map_err(|e| e.to_string())
 }
 
 pub fn get_store_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
@@ -37,3 +239,195 @@ pub fn get_store_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
         .ok()
         .map(|p| p.join(STORE_FILE))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::LaunchProfile;
+
+    #[test]
+    fn test_migrate_v0_preserves_values() {
+        // Saved before versioning, and before most of the newer proxy fields
+        let v0 = json!({
+            "osu_path": "C:\\Games\\osu!",
+            "start_at_boot": true,
+            "minimize_to_tray": false,
+            "start_minimized": false,
+            "debug_logging": true,
+            "proxy": {
+                "https_port": 8443,
+                "inject_supporter": true,
+                "api_base_url": "https://api.example.com",
+                "direct_base_url": "https://direct.example.com"
+            }
+        });
+
+        let config = migrate(v0);
+
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.osu_path, Some(PathBuf::from("C:\\Games\\osu!")));
+        assert!(config.start_at_boot);
+        assert!(!config.minimize_to_tray);
+        assert!(config.debug_logging);
+        assert_eq!(config.proxy.https_port, 8443);
+        assert!(config.proxy.inject_supporter);
+        assert_eq!(config.proxy.api_base_url, "https://api.example.com");
+        assert_eq!(config.proxy.upstream_server, "ppy.sh");
+    }
+
+    #[test]
+    fn test_migrate_normalizes_mirror_urls() {
+        let stored = json!({
+            "version": 1,
+            "proxy": {
+                "api_base_url": "api.example.com/",
+                "direct_base_url": "https://direct.example.com/mirror/"
+            }
+        });
+
+        let config = migrate(stored);
+
+        assert_eq!(config.proxy.api_base_url, "https://api.example.com");
+        assert_eq!(
+            config.proxy.direct_base_url,
+            "https://direct.example.com/mirror"
+        );
+    }
+
+    #[test]
+    fn test_load_replaces_unusable_mirror_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STORE_FILE);
+        let stored = json!({
+            CONFIG_KEY: {
+                "version": 1,
+                "proxy": {
+                    "direct_base_url": "ftp://direct.example.com"
+                }
+            }
+        });
+        fs::write(&path, stored.to_string()).unwrap();
+
+        let config = load_config_from(&path);
+
+        assert_eq!(
+            config.proxy.direct_base_url,
+            ProxyConfig::default().direct_base_url
+        );
+    }
+
+    #[test]
+    fn test_load_turns_off_interception_that_would_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STORE_FILE);
+        let stored = json!({
+            CONFIG_KEY: {
+                "version": 1,
+                "proxy": { "intercept_real_hosts": true }
+            }
+        });
+        fs::write(&path, stored.to_string()).unwrap();
+
+        let config = load_config_from(&path);
+
+        assert!(!config.proxy.intercept_real_hosts);
+        assert_eq!(config.proxy.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_migrate_drops_only_invalid_fields() {
+        let stored = json!({
+            "version": 1,
+            "osu_path": "/games/osu",
+            "proxy": {
+                "https_port": "not a port",
+                "inject_supporter": true,
+                "idle_timeout_secs": 60
+            }
+        });
+
+        let config = migrate(stored);
+
+        assert_eq!(config.osu_path, Some(PathBuf::from("/games/osu")));
+        assert_eq!(config.proxy.https_port, 443);
+        assert!(config.proxy.inject_supporter);
+        assert_eq!(config.proxy.idle_timeout_secs, 60);
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let mut config = AppConfig {
+            osu_path: Some(PathBuf::from("/games/osu")),
+            debug_logging: true,
+            launch_args: vec!["-windowed".to_string()],
+            profiles: vec![LaunchProfile {
+                name: "Lazer".to_string(),
+                osu_path: Some(PathBuf::from("/games/lazer")),
+                ..Default::default()
+            }],
+            active_profile: Some(0),
+            ..Default::default()
+        };
+        config.proxy.https_port = 8443;
+        config.proxy.inject_supporter = true;
+
+        let exported = export_config_json(&config);
+        assert!(!exported.contains("osu_path"));
+        assert!(!exported.contains("/games/lazer"));
+
+        let other_machine = AppConfig {
+            osu_path: Some(PathBuf::from("/other/osu")),
+            ..Default::default()
+        };
+        let imported = import_config_json(&exported, &other_machine).unwrap();
+
+        assert_eq!(imported.osu_path, other_machine.osu_path);
+        assert!(imported.profiles.is_empty());
+        assert_eq!(imported.active_profile, None);
+        assert!(imported.debug_logging);
+        assert_eq!(imported.launch_args, config.launch_args);
+        assert_eq!(imported.proxy.https_port, 8443);
+        assert!(imported.proxy.inject_supporter);
+    }
+
+    #[test]
+    fn test_import_rejects_malformed_json() {
+        let current = AppConfig::default();
+
+        assert!(import_config_json("{ not json", &current).is_err());
+        assert!(import_config_json("[1, 2, 3]", &current).is_err());
+
+        let invalid = r#"{"proxy": {"direct_base_url": "ftp://example.com"}}"#;
+        assert!(import_config_json(invalid, &current)
+            .unwrap_err()
+            .contains("direct_base_url"));
+    }
+
+    #[test]
+    fn test_interrupted_save_keeps_previous_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STORE_FILE);
+
+        let good = AppConfig {
+            osu_path: Some(PathBuf::from("/games/osu")),
+            ..Default::default()
+        };
+        save_config_to(&path, &good).unwrap();
+
+        // A crash between writing the temp file and renaming it over the
+        // settings file leaves a partial temp file behind
+        let mut tmp_name = path.file_name().unwrap().to_os_string();
+        tmp_name.push(".rai-connect.tmp");
+        fs::write(path.with_file_name(tmp_name), "{\"config\": {\"osu_pa").unwrap();
+
+        assert_eq!(load_config_from(&path).osu_path, good.osu_path);
+    }
+
+    #[test]
+    fn test_migrate_non_object_falls_back_to_default() {
+        let config = migrate(json!("garbage"));
+
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.proxy.https_port, 443);
+    }
+}
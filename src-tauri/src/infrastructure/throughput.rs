@@ -0,0 +1,137 @@
+//! Rolling throughput measurement for proxied traffic.
+//!
+//! Cumulative counters say how much has moved in total, but not how fast data
+//! is moving right now. A [`ThroughputMeter`] keeps a small ring of per-second
+//! buckets and reports the average rate over the last [`WINDOW_SECS`] seconds.
+//! Memory use is fixed regardless of how much traffic is recorded.
+
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+/// Length of the rolling window, in seconds.
+pub const WINDOW_SECS: u64 = 5;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Bucket {
+    /// Second (relative to the meter's origin) this bucket holds bytes for.
+    second: u64,
+    bytes: u64,
+}
+
+/// Measures bytes per second over a rolling [`WINDOW_SECS`]-second window.
+///
+/// Recording only holds a mutex long enough to bump a single bucket, so it is
+/// cheap to call from the request path.
+#[derive(Debug)]
+pub struct ThroughputMeter {
+    origin: Instant,
+    buckets: Mutex<[Bucket; WINDOW_SECS as usize]>,
+}
+
+impl Default for ThroughputMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThroughputMeter {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            buckets: Mutex::new([Bucket::default(); WINDOW_SECS as usize]),
+        }
+    }
+
+    /// Records that `bytes` were just transferred.
+    pub fn record(&self, bytes: u64) {
+        self.record_at(bytes, Instant::now());
+    }
+
+    /// Returns the average rate over the rolling window, in bytes per second.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.rate_at(Instant::now())
+    }
+
+    fn second_of(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.origin).as_secs()
+    }
+
+    fn record_at(&self, bytes: u64, at: Instant) {
+        if bytes == 0 {
+            return;
+        }
+
+        let second = self.second_of(at);
+        let mut buckets = self.buckets.lock();
+        let bucket = &mut buckets[(second % WINDOW_SECS) as usize];
+
+        // The slot last held a second that has since left the window
+        if bucket.second != second {
+            *bucket = Bucket { second, bytes: 0 };
+        }
+        bucket.bytes += bytes;
+    }
+
+    fn rate_at(&self, at: Instant) -> u64 {
+        let now = self.second_of(at);
+        let buckets = self.buckets.lock();
+
+        let total: u64 = buckets
+            .iter()
+            .filter(|b| b.second <= now && b.second + WINDOW_SECS > now)
+            .map(|b| b.bytes)
+            .sum();
+
+        total / WINDOW_SECS
+    }
+}
+
+/// Upload and download meters for the proxy.
+#[derive(Debug, Default)]
+pub struct TrafficMeters {
+    /// Bytes sent from osu! towards upstream servers.
+    pub upload: ThroughputMeter,
+    /// Bytes sent from upstream servers back to osu!.
+    pub download: ThroughputMeter,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_over_window() {
+        let meter = ThroughputMeter::new();
+        let t0 = meter.origin;
+
+        // 1000 bytes in each of the first five seconds
+        for s in 0..5 {
+            meter.record_at(1000, t0 + Duration::from_secs(s));
+        }
+        assert_eq!(meter.rate_at(t0 + Duration::from_millis(4500)), 1000);
+
+        // A burst in the sixth second evicts the first second's bucket
+        meter.record_at(6000, t0 + Duration::from_secs(5));
+        assert_eq!(meter.rate_at(t0 + Duration::from_secs(5)), 2000);
+
+        // Once the window has passed with no traffic the rate drops to zero
+        assert_eq!(meter.rate_at(t0 + Duration::from_secs(10)), 0);
+    }
+
+    #[test]
+    fn test_stale_bucket_is_reset() {
+        let meter = ThroughputMeter::new();
+        let t0 = meter.origin;
+
+        meter.record_at(500, t0);
+        // Same slot in the ring, one full window later
+        meter.record_at(100, t0 + Duration::from_secs(WINDOW_SECS));
+
+        assert_eq!(
+            meter.rate_at(t0 + Duration::from_secs(WINDOW_SECS)),
+            100 / WINDOW_SECS
+        );
+    }
+}
@@ -16,12 +16,20 @@ use rcgen::{CertificateParams, DnType, KeyPair, SanType};
 use rustls::crypto::ring::default_provider;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use rustls::ServerConfig;
+use time::OffsetDateTime;
 use tokio_rustls::TlsAcceptor;
+use x509_parser::extensions::GeneralName;
+use x509_parser::public_key::PublicKey;
+
+use crate::domain::{CertAlgo, ProxyConfig};
+use crate::infrastructure::hosts;
 
 /// Service name for keyring storage.
 const KEYRING_SERVICE: &str = "rai-connect";
 /// Account name for the TLS private key.
 const KEYRING_KEY_ACCOUNT: &str = "localhost-tls-key";
+/// Common name of the generated certificate, used to find it in trust stores.
+const CERT_COMMON_NAME: &str = "rai!connect Local Proxy";
 
 /// Returns the directory where certificate files are stored.
 fn get_cert_dir() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
@@ -130,23 +138,56 @@ fn migrate_legacy_key() -> Result<bool, Box<dyn std::error::Error + Send + Sync>
     Ok(true)
 }
 
-/// Generates a new certificate and key pair, saving both to disk/keychain.
+/// How long a generated certificate stays valid. 397 days is the most the
+/// CA/Browser Forum allows, and some trust stores reject anything longer.
+const CERT_VALIDITY_DAYS: i64 = 397;
+
+/// Certificates expiring within this many days are regenerated on load.
+const CERT_RENEWAL_DAYS: i64 = 14;
+
+/// Returns the `(not_before, not_after)` validity window for a certificate
+/// generated at `now`. It starts a day early to tolerate clock skew.
+fn validity_window(now: OffsetDateTime) -> (OffsetDateTime, OffsetDateTime) {
+    (
+        now - time::Duration::days(1),
+        now + time::Duration::days(CERT_VALIDITY_DAYS),
+    )
+}
+
+/// Settings that determine what certificate gets generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CertOptions {
+    pub algo: CertAlgo,
+    /// Also cover the official `ppy.sh` hosts.
+    pub intercept_real_hosts: bool,
+}
+
+impl From<&ProxyConfig> for CertOptions {
+    fn from(config: &ProxyConfig) -> Self {
+        Self {
+            algo: config.cert_key_algorithm,
+            intercept_real_hosts: config.intercept_real_hosts,
+        }
+    }
+}
+
+/// Builds the parameters for the self-signed localhost certificate.
 ///
 /// The certificate is valid for:
 /// - `localhost`
 /// - `*.localhost` (covers c.localhost, osu.localhost, a.localhost, etc.)
 /// - `127.0.0.1` and `::1`
-fn generate_and_save_cert() -> Result<
-    (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
-    Box<dyn std::error::Error + Send + Sync>,
-> {
-    let cert_path = get_cert_path()?;
-
+/// - with `intercept_real_hosts`, also `ppy.sh` and `*.ppy.sh`
+fn cert_params(
+    intercept_real_hosts: bool,
+) -> Result<CertificateParams, Box<dyn std::error::Error + Send + Sync>> {
     let mut params = CertificateParams::default();
 
+    (params.not_before, params.not_after) = validity_window(OffsetDateTime::now_utc());
+
     params
         .distinguished_name
-        .push(DnType::CommonName, "rai!connect Local Proxy");
+        .push(DnType::CommonName, CERT_COMMON_NAME);
     params
         .distinguished_name
         .push(DnType::OrganizationName, "rai.moe");
@@ -168,23 +209,292 @@ fn generate_and_save_cert() -> Result<
         SanType::IpAddress(std::net::IpAddr::V6(std::net::Ipv6Addr::LOCALHOST)),
     ];
 
-    let key_pair = KeyPair::generate()?;
+    if intercept_real_hosts {
+        for name in ["ppy.sh", "*.ppy.sh"]
+            .into_iter()
+            .chain(hosts::intercepted_hostnames(true).filter(|h| h.ends_with(".ppy.sh")))
+        {
+            params
+                .subject_alt_names
+                .push(SanType::DnsName(name.try_into()?));
+        }
+    }
+
+    Ok(params)
+}
+
+/// Generates a key pair using `algo`.
+fn generate_key_pair(algo: CertAlgo) -> Result<KeyPair, rcgen::Error> {
+    match algo {
+        CertAlgo::Ecdsa => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256),
+        CertAlgo::Rsa2048 => {
+            KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, rcgen::RsaKeySize::_2048)
+        }
+    }
+}
+
+/// Returns the key algorithm of a certificate, read from its public key.
+///
+/// The certificate itself records which algorithm it was generated with, so
+/// regenerating it can keep the same one.
+fn cert_algorithm(cert_der: &[u8]) -> Option<CertAlgo> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert_der).ok()?;
+    match cert.public_key().parsed().ok()? {
+        PublicKey::RSA(_) => Some(CertAlgo::Rsa2048),
+        PublicKey::EC(_) => Some(CertAlgo::Ecdsa),
+        _ => None,
+    }
+}
+
+/// Returns the options a stored certificate was generated with.
+fn cert_options(cert_der: &[u8]) -> CertOptions {
+    CertOptions {
+        algo: cert_algorithm(cert_der).unwrap_or_default(),
+        intercept_real_hosts: missing_sans(cert_der, &["c.ppy.sh".to_string()]).is_empty(),
+    }
+}
+
+/// Generates a new certificate and key pair without storing them.
+fn generate_cert(
+    options: CertOptions,
+) -> Result<
+    (CertificateDer<'static>, PrivatePkcs8KeyDer<'static>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let params = cert_params(options.intercept_real_hosts)?;
+    let key_pair = generate_key_pair(options.algo)?;
     let cert = params.self_signed(&key_pair)?;
 
+    // rcgen serializes both ECDSA and RSA keys in PKCS#8 format
+    let cert_der = CertificateDer::from(cert.der().to_vec());
+    let key_der = PrivatePkcs8KeyDer::from(key_pair.serialize_der());
+    Ok((cert_der, key_der))
+}
+
+/// Generates a new certificate and key pair, saving both to disk/keychain.
+fn generate_and_save_cert(
+    options: CertOptions,
+) -> Result<
+    (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let cert_path = get_cert_path()?;
+    let (cert_der, key_der) = generate_cert(options)?;
+
     // Save certificate in DER format (.cer) - this is public, no encryption needed
-    std::fs::write(&cert_path, cert.der())?;
+    std::fs::write(&cert_path, &cert_der)?;
     tracing::info!("Certificate saved to: {}", cert_path.display());
 
     // Save private key securely in system keychain
-    let key_der_bytes = key_pair.serialize_der();
-    store_key_in_keyring(&key_der_bytes)?;
+    store_key_in_keyring(key_der.secret_pkcs8_der())?;
 
-    // Convert to rustls types
-    // rcgen serializes ECDSA keys in PKCS#8 format
-    let cert_der = CertificateDer::from(cert.der().to_vec());
-    let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_der_bytes));
+    Ok((vec![cert_der], PrivateKeyDer::Pkcs8(key_der)))
+}
 
-    Ok((vec![cert_der], key_der))
+/// Returns the hostnames the certificate must be valid for: `localhost` plus
+/// every subdomain that the hosts file redirects to the proxy.
+fn required_san_names(intercept_real_hosts: bool) -> Vec<String> {
+    std::iter::once("localhost")
+        .chain(hosts::intercepted_hostnames(intercept_real_hosts))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns `true` if the SAN DNS name covers `hostname`.
+///
+/// A wildcard such as `*.localhost` covers exactly one label, so it matches
+/// `c.localhost` but neither `localhost` nor `a.b.localhost`.
+fn san_matches(san: &str, hostname: &str) -> bool {
+    let san = san.to_lowercase();
+    let hostname = hostname.to_lowercase();
+    match san.strip_prefix("*.") {
+        Some(suffix) => hostname
+            .split_once('.')
+            .map(|(label, rest)| !label.is_empty() && rest == suffix)
+            .unwrap_or(false),
+        None => san == hostname,
+    }
+}
+
+/// Returns the names in `required` that the certificate's SAN list does not cover.
+///
+/// A certificate that cannot be parsed covers nothing.
+fn missing_sans(cert_der: &[u8], required: &[String]) -> Vec<String> {
+    let dns_names: Vec<String> = match x509_parser::parse_x509_certificate(cert_der) {
+        Ok((_, cert)) => match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    };
+
+    required
+        .iter()
+        .filter(|name| !dns_names.iter().any(|san| san_matches(san, name)))
+        .cloned()
+        .collect()
+}
+
+/// Returns the certificate's notAfter date, or `None` if it can't be parsed.
+fn cert_not_after(cert_der: &[u8]) -> Option<OffsetDateTime> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert_der).ok()?;
+    Some(cert.validity().not_after.to_datetime())
+}
+
+/// Returns why a stored certificate must be regenerated, or `None` if it can
+/// still be used as of `now`. With `wanted` set, a certificate generated with
+/// other options is regenerated too.
+fn regeneration_reason(
+    cert_der: &[u8],
+    now: OffsetDateTime,
+    wanted: Option<CertOptions>,
+) -> Option<String> {
+    if let Some(wanted) = wanted {
+        let current = cert_algorithm(cert_der);
+        if current != Some(wanted.algo) {
+            return Some(format!(
+                "Certificate uses {:?} keys, but {:?} is configured",
+                current, wanted.algo
+            ));
+        }
+
+        // Don't keep trusting the real hosts once interception is turned off
+        if !wanted.intercept_real_hosts && cert_options(cert_der).intercept_real_hosts {
+            return Some("Certificate still covers the official osu! hosts".to_string());
+        }
+    }
+
+    let intercept_real_hosts = wanted.is_some_and(|w| w.intercept_real_hosts);
+    let missing = missing_sans(cert_der, &required_san_names(intercept_real_hosts));
+    if !missing.is_empty() {
+        return Some(format!(
+            "Certificate is not valid for {}",
+            missing.join(", ")
+        ));
+    }
+
+    match cert_not_after(cert_der) {
+        Some(not_after) if not_after <= now => {
+            Some(format!("Certificate expired on {}", not_after.date()))
+        }
+        Some(not_after) if not_after - now < time::Duration::days(CERT_RENEWAL_DAYS) => {
+            Some(format!("Certificate expires soon, on {}", not_after.date()))
+        }
+        Some(_) => None,
+        None => Some("Certificate validity could not be read".to_string()),
+    }
+}
+
+/// Formats the SHA-256 digest of `cert_der` as uppercase colon-separated hex.
+fn fingerprint(cert_der: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, cert_der)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Returns the SHA-256 fingerprint of the certificate, generating it if needed.
+///
+/// Lets users check they're trusting the right certificate when installing it by hand.
+pub fn cert_fingerprint() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (certs, _) = get_or_create_cert()?;
+    let cert = certs.first().ok_or("No certificate available")?;
+    Ok(fingerprint(cert))
+}
+
+/// Reads the stored certificate without generating one.
+pub fn stored_certificate() -> Option<CertificateDer<'static>> {
+    let cert_der = std::fs::read(get_cert_path().ok()?).ok()?;
+    Some(CertificateDer::from(cert_der))
+}
+
+/// Returns when the stored certificate expires, so the UI can warn ahead of time.
+///
+/// Returns `None` if there is no certificate yet or it can't be read.
+pub fn cert_expiry() -> Option<OffsetDateTime> {
+    let cert_der = std::fs::read(get_cert_path().ok()?).ok()?;
+    cert_not_after(&cert_der)
+}
+
+/// Verifies that the stored certificate is valid for every host osu! will use,
+/// including the official hosts when `intercept_real_hosts` is set.
+///
+/// Returns the missing hostnames if the certificate lacks a SAN for any of
+/// them (e.g. after a subdomain was added to the intercept list). A missing
+/// or unreadable certificate reports every required name as missing.
+pub fn cert_covers_required_sans(intercept_real_hosts: bool) -> Result<(), Vec<String>> {
+    let required = required_san_names(intercept_real_hosts);
+
+    let cert_der = match get_cert_path().ok().and_then(|p| std::fs::read(p).ok()) {
+        Some(bytes) => bytes,
+        None => return Err(required),
+    };
+
+    let missing = missing_sans(&cert_der, &required);
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+/// Discards the stored certificate and key and generates a fresh pair.
+///
+/// The new certificate is also installed to the trust store right away, since
+/// on Windows `is_certificate_installed` matches by name and would still
+/// report the old one.
+fn regenerate_cert(
+    options: CertOptions,
+) -> Result<
+    (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let _ = delete_key_from_keyring();
+    if let Ok(cert_path) = get_cert_path() {
+        let _ = std::fs::remove_file(cert_path);
+    }
+
+    let (certs, key) = generate_and_save_cert(options)?;
+
+    tracing::info!("Installing regenerated certificate to trust store...");
+    if let Err(e) = install_certificate() {
+        tracing::warn!("Failed to auto-install regenerated certificate: {}", e);
+    }
+
+    Ok((certs, key))
+}
+
+/// Replaces the certificate and key with a new pair, for when the stored
+/// one is in a bad state, such as SANs from an old version or a half-finished
+/// install. The old certificate is taken out of the trust store first so it
+/// doesn't stay trusted, then the new one is installed.
+///
+/// Returns the new certificate's fingerprint.
+pub fn regenerate_certificate(
+    options: CertOptions,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Err(e) = remove_from_trust_store() {
+        tracing::warn!(
+            "Failed to remove the old certificate from the trust store: {}",
+            e
+        );
+    }
+
+    let (certs, _) = regenerate_cert(options)?;
+    let cert = certs.first().ok_or("No certificate available")?;
+    let fingerprint = fingerprint(cert);
+    tracing::info!("Regenerated certificate {}", fingerprint);
+    Ok(fingerprint)
 }
 
 /// Loads an existing certificate from disk and key from keychain.
@@ -215,6 +525,18 @@ fn load_cert_from_disk() -> Result<
 pub fn get_or_create_cert() -> Result<
     (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
     Box<dyn std::error::Error + Send + Sync>,
+> {
+    get_or_create_cert_for(None)
+}
+
+/// Like [`get_or_create_cert`], but with `wanted` set the certificate is
+/// regenerated if it was generated with different options. Otherwise a
+/// regenerated certificate keeps the options of the one it replaces.
+fn get_or_create_cert_for(
+    wanted: Option<CertOptions>,
+) -> Result<
+    (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+    Box<dyn std::error::Error + Send + Sync>,
 > {
     // First, check if we need to migrate a legacy plaintext key
     if let Err(e) = migrate_legacy_key() {
@@ -227,8 +549,17 @@ pub fn get_or_create_cert() -> Result<
     if cert_path.exists() {
         match load_cert_from_disk() {
             Ok(result) => {
-                tracing::debug!("Successfully loaded certificate and key from storage");
-                return Ok(result);
+                let cert = &result.0[0];
+                match regeneration_reason(cert, OffsetDateTime::now_utc(), wanted) {
+                    None => {
+                        tracing::debug!("Successfully loaded certificate and key from storage");
+                        return Ok(result);
+                    }
+                    Some(reason) => {
+                        tracing::warn!("{}. Regenerating.", reason);
+                        return regenerate_cert(wanted.unwrap_or_else(|| cert_options(cert)));
+                    }
+                }
             }
             Err(e) => {
                 // Common causes: first run after keyring migration, admin vs normal user context
@@ -245,7 +576,7 @@ pub fn get_or_create_cert() -> Result<
     }
 
     tracing::info!("Generating new TLS certificate and key pair");
-    generate_and_save_cert()
+    generate_and_save_cert(wanted.unwrap_or_default())
 }
 
 /// Creates a TLS acceptor configured with the certificate.
@@ -260,8 +591,10 @@ pub fn get_or_create_cert() -> Result<
 /// # Errors
 ///
 /// Returns an error if certificate generation or TLS configuration fails.
-pub fn create_tls_acceptor() -> Result<TlsAcceptor, Box<dyn std::error::Error + Send + Sync>> {
-    let (certs, key) = get_or_create_cert()?;
+pub fn create_tls_acceptor(
+    options: CertOptions,
+) -> Result<TlsAcceptor, Box<dyn std::error::Error + Send + Sync>> {
+    let (certs, key) = get_or_create_cert_for(Some(options))?;
 
     match try_create_tls_config(certs.clone(), key) {
         Ok(config) => Ok(TlsAcceptor::from(Arc::new(config))),
@@ -272,23 +605,7 @@ pub fn create_tls_acceptor() -> Result<TlsAcceptor, Box<dyn std::error::Error +
                 e
             );
 
-            // Delete old cert and key
-            let _ = delete_key_from_keyring();
-            if let Ok(cert_path) = get_cert_path() {
-                let _ = std::fs::remove_file(cert_path);
-            }
-
-            // Generate fresh cert and key
-            let (new_certs, new_key) = generate_and_save_cert()?;
-
-            // Reinstall the new certificate to Windows trust store
-            #[cfg(target_os = "windows")]
-            {
-                tracing::info!("Installing regenerated certificate to trust store...");
-                if let Err(e) = install_certificate() {
-                    tracing::warn!("Failed to auto-install regenerated certificate: {}", e);
-                }
-            }
+            let (new_certs, new_key) = regenerate_cert(options)?;
 
             let config = try_create_tls_config(new_certs, new_key)?;
             Ok(TlsAcceptor::from(Arc::new(config)))
@@ -296,6 +613,28 @@ pub fn create_tls_acceptor() -> Result<TlsAcceptor, Box<dyn std::error::Error +
     }
 }
 
+/// Creates a TLS acceptor for a certificate that isn't the stored one.
+pub fn tls_acceptor_for(
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<TlsAcceptor, Box<dyn std::error::Error + Send + Sync>> {
+    let config = try_create_tls_config(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Generates a certificate and key pair like [`generate_and_save_cert`], but
+/// keeps them in memory, for tests that need a real TLS listener.
+#[cfg(test)]
+pub fn generate_ephemeral_cert(
+    options: CertOptions,
+) -> Result<
+    (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let (cert_der, key_der) = generate_cert(options)?;
+    Ok((vec![cert_der], PrivateKeyDer::Pkcs8(key_der)))
+}
+
 fn try_create_tls_config(
     certs: Vec<CertificateDer<'static>>,
     key: PrivateKeyDer<'static>,
@@ -312,7 +651,259 @@ fn try_create_tls_config(
     Ok(config)
 }
 
-/// Generates (if needed) and installs the certificate into the Windows trusted root store.
+/// Upper bound on a single trust store command. `certutil` in particular can
+/// hang indefinitely when antivirus software intercepts certificate store access.
+const TRUST_COMMAND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Upper bound for trust store commands that wait for the user to approve
+/// them in a system dialog.
+#[cfg(target_os = "macos")]
+const INTERACTIVE_TRUST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// Nickname the certificate is stored under in NSS databases.
+#[cfg(any(target_os = "linux", test))]
+const NSS_NICKNAME: &str = "rai!connect";
+
+/// Where the certificate is placed for `update-ca-certificates` to pick up.
+#[cfg(target_os = "linux")]
+const LINUX_CA_CERT_PATH: &str = "/usr/local/share/ca-certificates/rai-connect.crt";
+
+/// An external command that queries or modifies a certificate trust store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrustCommand {
+    program: &'static str,
+    args: Vec<String>,
+}
+
+impl TrustCommand {
+    fn new<I, S>(program: &'static str, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            program,
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Runs the command, killing it if it takes longer than `timeout`.
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    fn run(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<std::process::Output, Box<dyn std::error::Error + Send + Sync>> {
+        use crate::infrastructure::process::{background_command, run_with_timeout};
+
+        run_with_timeout(background_command(self.program).args(&self.args), timeout).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::TimedOut {
+                format!("{} timed out after {}s", self.program, timeout.as_secs()).into()
+            } else {
+                e.into()
+            }
+        })
+    }
+}
+
+/// Adds the certificate to the current user's trusted root store.
+#[cfg(any(target_os = "windows", test))]
+fn certutil_install(cert_path: &str) -> TrustCommand {
+    TrustCommand::new("certutil", ["-addstore", "-user", "Root", cert_path])
+}
+
+/// Succeeds if the certificate is in the current user's trusted root store.
+#[cfg(any(target_os = "windows", test))]
+fn certutil_query() -> TrustCommand {
+    TrustCommand::new("certutil", ["-store", "-user", "Root", "rai!connect"])
+}
+
+/// Removes the certificate from the current user's trusted root store.
+#[cfg(any(target_os = "windows", test))]
+fn certutil_uninstall() -> TrustCommand {
+    TrustCommand::new("certutil", ["-delstore", "-user", "Root", CERT_COMMON_NAME])
+}
+
+/// Adds the certificate to `keychain` and trusts it as a root for the user.
+#[cfg(any(target_os = "macos", test))]
+fn security_install(cert_path: &str, keychain: &str) -> TrustCommand {
+    TrustCommand::new(
+        "security",
+        [
+            "add-trusted-cert",
+            "-r",
+            "trustRoot",
+            "-k",
+            keychain,
+            cert_path,
+        ],
+    )
+}
+
+/// Succeeds if the certificate chains to a trusted root, i.e. it is installed.
+#[cfg(any(target_os = "macos", test))]
+fn security_verify(cert_path: &str) -> TrustCommand {
+    TrustCommand::new("security", ["verify-cert", "-c", cert_path])
+}
+
+/// Succeeds if `keychain` holds a certificate with our common name.
+#[cfg(any(target_os = "macos", test))]
+fn security_find(keychain: &str) -> TrustCommand {
+    TrustCommand::new(
+        "security",
+        ["find-certificate", "-c", CERT_COMMON_NAME, keychain],
+    )
+}
+
+/// Deletes the certificate and its trust settings from `keychain`.
+#[cfg(any(target_os = "macos", test))]
+fn security_uninstall(keychain: &str) -> TrustCommand {
+    TrustCommand::new(
+        "security",
+        ["delete-certificate", "-c", CERT_COMMON_NAME, "-t", keychain],
+    )
+}
+
+/// Rebuilds the system CA bundle from `/usr/local/share/ca-certificates`.
+#[cfg(any(target_os = "linux", test))]
+fn update_ca_certificates() -> TrustCommand {
+    TrustCommand::new("update-ca-certificates", Vec::<String>::new())
+}
+
+/// Adds the certificate to an NSS database as a trusted CA for TLS servers.
+#[cfg(any(target_os = "linux", test))]
+fn nss_install(nss_db: &std::path::Path, cert_path: &str) -> TrustCommand {
+    TrustCommand::new(
+        "certutil",
+        [
+            "-d".to_string(),
+            format!("sql:{}", nss_db.display()),
+            "-A".to_string(),
+            "-t".to_string(),
+            "C,,".to_string(),
+            "-n".to_string(),
+            NSS_NICKNAME.to_string(),
+            "-i".to_string(),
+            cert_path.to_string(),
+        ],
+    )
+}
+
+/// Succeeds if the NSS database holds a certificate under our nickname.
+#[cfg(any(target_os = "linux", test))]
+fn nss_query(nss_db: &std::path::Path) -> TrustCommand {
+    TrustCommand::new(
+        "certutil",
+        [
+            "-d".to_string(),
+            format!("sql:{}", nss_db.display()),
+            "-L".to_string(),
+            "-n".to_string(),
+            NSS_NICKNAME.to_string(),
+        ],
+    )
+}
+
+/// Removes the certificate stored under our nickname from an NSS database.
+#[cfg(any(target_os = "linux", test))]
+fn nss_uninstall(nss_db: &std::path::Path) -> TrustCommand {
+    TrustCommand::new(
+        "certutil",
+        [
+            "-d".to_string(),
+            format!("sql:{}", nss_db.display()),
+            "-D".to_string(),
+            "-n".to_string(),
+            NSS_NICKNAME.to_string(),
+        ],
+    )
+}
+
+/// Returns the path of the user's login keychain.
+#[cfg(target_os = "macos")]
+fn login_keychain() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let home = dirs::home_dir().ok_or("Could not find home directory")?;
+    let keychain = home.join("Library/Keychains/login.keychain-db");
+    Ok(keychain
+        .to_str()
+        .ok_or("Keychain path contains invalid UTF-8 characters")?
+        .to_string())
+}
+
+/// Returns the shared NSS database used by Chromium-based browsers, if present.
+#[cfg(target_os = "linux")]
+fn nss_db_dir() -> Option<PathBuf> {
+    dirs::home_dir()
+        .map(|home| home.join(".pki").join("nssdb"))
+        .filter(|dir| dir.is_dir())
+}
+
+/// Encodes the on-disk DER certificate as PEM, which `update-ca-certificates` requires.
+#[cfg(target_os = "linux")]
+fn cert_pem(
+    cert_path: &std::path::Path,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let der = std::fs::read(cert_path)?;
+    Ok(pem::encode(&pem::Pem::new("CERTIFICATE", der)))
+}
+
+/// Installs the certificate into the system CA bundle and the NSS database.
+///
+/// Writing the system bundle needs root, so this commonly fails for a regular
+/// user; the NSS database is then the only store updated. Succeeds if either
+/// store accepted the certificate.
+#[cfg(target_os = "linux")]
+fn install_certificate_linux(
+    cert_path: &std::path::Path,
+    cert_path_str: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut installed = false;
+    let mut errors = Vec::new();
+
+    let system = cert_pem(cert_path)
+        .and_then(|pem| Ok(std::fs::write(LINUX_CA_CERT_PATH, pem)?))
+        .and_then(|_| {
+            let output = update_ca_certificates().run(TRUST_COMMAND_TIMEOUT)?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(String::from_utf8_lossy(&output.stderr).into_owned().into())
+            }
+        });
+    match system {
+        Ok(()) => {
+            tracing::info!("Certificate installed to system CA store");
+            installed = true;
+        }
+        Err(e) => errors.push(format!("system CA store: {}", e)),
+    }
+
+    if let Some(nss_db) = nss_db_dir() {
+        match nss_install(&nss_db, cert_path_str).run(TRUST_COMMAND_TIMEOUT) {
+            Ok(output) if output.status.success() => {
+                tracing::info!("Certificate installed to NSS database {}", nss_db.display());
+                installed = true;
+            }
+            Ok(output) => errors.push(format!(
+                "NSS database: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )),
+            Err(e) => errors.push(format!("NSS database: {}", e)),
+        }
+    }
+
+    if installed {
+        return Ok(true);
+    }
+
+    Err(format!("Failed to install certificate: {}", errors.join("; ")).into())
+}
+
+/// Generates (if needed) and installs the certificate into the user's trust store.
+///
+/// - Windows: the current user's trusted root store, via `certutil`
+/// - macOS: the login keychain, via `security add-trusted-cert`
+/// - Linux: the system CA bundle via `update-ca-certificates` (needs root),
+///   and the NSS database used by Chromium-based browsers if it exists
 ///
 /// This only needs to be done once. The certificate is saved to:
 /// `%LOCALAPPDATA%/rai-connect/localhost.cer`
@@ -325,16 +916,14 @@ pub fn install_certificate() -> Result<bool, Box<dyn std::error::Error + Send +
     let _ = get_or_create_cert()?;
     let cert_path = get_cert_path()?;
 
-    // Install certificate using certutil (Windows)
+    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+    let cert_path_str = cert_path
+        .to_str()
+        .ok_or("Certificate path contains invalid UTF-8 characters")?;
+
     #[cfg(target_os = "windows")]
     {
-        let cert_path_str = cert_path
-            .to_str()
-            .ok_or("Certificate path contains invalid UTF-8 characters")?;
-
-        let output = std::process::Command::new("certutil")
-            .args(["-addstore", "-user", "Root", cert_path_str])
-            .output()?;
+        let output = certutil_install(cert_path_str).run(TRUST_COMMAND_TIMEOUT)?;
 
         if output.status.success() {
             tracing::info!("Certificate installed to Windows trusted root store");
@@ -354,7 +943,39 @@ pub fn install_certificate() -> Result<bool, Box<dyn std::error::Error + Send +
         }
     }
 
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(target_os = "macos")]
+    {
+        if is_certificate_installed() {
+            tracing::info!("Certificate already installed");
+            return Ok(false);
+        }
+
+        let keychain = login_keychain()?;
+        let output = security_install(cert_path_str, &keychain).run(INTERACTIVE_TRUST_TIMEOUT)?;
+
+        if output.status.success() {
+            tracing::info!("Certificate installed to login keychain");
+            Ok(true)
+        } else {
+            Err(format!(
+                "Failed to install certificate: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if is_certificate_installed() {
+            tracing::info!("Certificate already installed");
+            return Ok(false);
+        }
+
+        install_certificate_linux(&cert_path, cert_path_str)
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
     {
         tracing::warn!("Automatic certificate installation not supported on this OS");
         tracing::info!(
@@ -368,21 +989,170 @@ pub fn install_certificate() -> Result<bool, Box<dyn std::error::Error + Send +
 /// Checks if the certificate is already installed in the Windows certificate store.
 #[cfg(target_os = "windows")]
 pub fn is_certificate_installed() -> bool {
-    let output = std::process::Command::new("certutil")
-        .args(["-store", "-user", "Root", "rai!connect"])
-        .output();
+    match certutil_query().run(TRUST_COMMAND_TIMEOUT) {
+        Ok(o) => o.status.success(),
+        Err(e) => {
+            tracing::warn!("Failed to query certificate store: {}", e);
+            false
+        }
+    }
+}
+
+/// Checks if the current certificate is trusted by the system.
+#[cfg(target_os = "macos")]
+pub fn is_certificate_installed() -> bool {
+    let Ok(cert_path) = get_cert_path() else {
+        return false;
+    };
+    let Some(cert_path) = cert_path.to_str() else {
+        return false;
+    };
 
-    match output {
+    match security_verify(cert_path).run(TRUST_COMMAND_TIMEOUT) {
         Ok(o) => o.status.success(),
-        Err(_) => false,
+        Err(e) => {
+            tracing::warn!("Failed to query keychain: {}", e);
+            false
+        }
     }
 }
 
-#[cfg(not(target_os = "windows"))]
+/// Checks if the current certificate is in the system CA bundle or the NSS database.
+#[cfg(target_os = "linux")]
+pub fn is_certificate_installed() -> bool {
+    let Ok(cert_path) = get_cert_path() else {
+        return false;
+    };
+
+    // Compare contents so a regenerated certificate isn't mistaken for the old one
+    let in_system_store = cert_pem(&cert_path)
+        .ok()
+        .zip(std::fs::read_to_string(LINUX_CA_CERT_PATH).ok())
+        .is_some_and(|(current, installed)| current == installed);
+    if in_system_store {
+        return true;
+    }
+
+    nss_db_dir().is_some_and(|nss_db| {
+        nss_query(&nss_db)
+            .run(TRUST_COMMAND_TIMEOUT)
+            .is_ok_and(|o| o.status.success())
+    })
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
 pub fn is_certificate_installed() -> bool {
     false
 }
 
+/// Fails with the command's output unless it exited successfully.
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn check_output(
+    output: std::process::Output,
+    action: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "Failed to {}: {}{}",
+        action,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+    .into())
+}
+
+/// Removes the certificate from the trust stores [`install_certificate`] uses.
+///
+/// Returns `Ok(false)` if it wasn't found in any of them.
+#[cfg(target_os = "windows")]
+fn remove_from_trust_store() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let output = certutil_uninstall().run(TRUST_COMMAND_TIMEOUT)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() && stdout.contains("CRYPT_E_NOT_FOUND") {
+        return Ok(false);
+    }
+    check_output(output, "remove certificate")?;
+    tracing::info!("Certificate removed from Windows trusted root store");
+    Ok(true)
+}
+
+#[cfg(target_os = "macos")]
+fn remove_from_trust_store() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let keychain = login_keychain()?;
+    let found = security_find(&keychain).run(TRUST_COMMAND_TIMEOUT)?;
+    if !found.status.success() {
+        return Ok(false);
+    }
+
+    let output = security_uninstall(&keychain).run(INTERACTIVE_TRUST_TIMEOUT)?;
+    check_output(output, "remove certificate")?;
+    tracing::info!("Certificate removed from login keychain");
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+fn remove_from_trust_store() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut removed = false;
+
+    if std::path::Path::new(LINUX_CA_CERT_PATH).exists() {
+        std::fs::remove_file(LINUX_CA_CERT_PATH)?;
+        check_output(
+            update_ca_certificates().run(TRUST_COMMAND_TIMEOUT)?,
+            "update system CA store",
+        )?;
+        tracing::info!("Certificate removed from system CA store");
+        removed = true;
+    }
+
+    if let Some(nss_db) = nss_db_dir() {
+        let found = nss_query(&nss_db).run(TRUST_COMMAND_TIMEOUT)?;
+        if found.status.success() {
+            check_output(
+                nss_uninstall(&nss_db).run(TRUST_COMMAND_TIMEOUT)?,
+                "remove certificate from NSS database",
+            )?;
+            tracing::info!("Certificate removed from NSS database {}", nss_db.display());
+            removed = true;
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn remove_from_trust_store() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(false)
+}
+
+/// Removes the certificate from the trust store and deletes it from disk,
+/// together with its private key.
+///
+/// Meant for uninstalling rai!connect, so no self-signed root is left behind.
+///
+/// # Returns
+///
+/// Returns `Ok(true)` if the certificate was removed from the trust store,
+/// `Ok(false)` if it wasn't installed there, or an error if removal failed.
+pub fn uninstall_certificate() -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let removed = remove_from_trust_store()?;
+
+    if let Err(e) = delete_key_from_keyring() {
+        tracing::warn!("Failed to delete private key from keychain: {}", e);
+    }
+
+    for path in [get_cert_path()?, get_legacy_key_path()?] {
+        match std::fs::remove_file(&path) {
+            Ok(()) => tracing::info!("Deleted {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(removed)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,9 +1170,249 @@ mod tests {
         assert_eq!(certs.len(), 1);
     }
 
+    fn cert_with_sans(names: &[&str]) -> Vec<u8> {
+        let mut params = CertificateParams::default();
+        params.subject_alt_names = names
+            .iter()
+            .map(|n| SanType::DnsName((*n).try_into().unwrap()))
+            .collect();
+        let key_pair = KeyPair::generate().unwrap();
+        params.self_signed(&key_pair).unwrap().der().to_vec()
+    }
+
+    #[test]
+    fn test_generated_cert_expiry_within_window() {
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = cert_params(false).unwrap().self_signed(&key_pair).unwrap();
+        let not_after = cert_not_after(cert.der()).unwrap();
+
+        let now = OffsetDateTime::now_utc();
+        let days = time::Duration::days;
+        assert!(not_after > now + days(CERT_VALIDITY_DAYS - 1));
+        assert!(not_after <= now + days(CERT_VALIDITY_DAYS));
+    }
+
+    fn cert_expiring_at(not_after: OffsetDateTime) -> Vec<u8> {
+        let mut params = cert_params(false).unwrap();
+        params.not_before = not_after - time::Duration::days(CERT_VALIDITY_DAYS);
+        params.not_after = not_after;
+        let key_pair = KeyPair::generate().unwrap();
+        params.self_signed(&key_pair).unwrap().der().to_vec()
+    }
+
+    #[test]
+    fn test_expired_cert_is_regenerated() {
+        let now = OffsetDateTime::now_utc();
+        let expired = cert_expiring_at(now - time::Duration::days(1));
+        let expiring = cert_expiring_at(now + time::Duration::days(CERT_RENEWAL_DAYS - 1));
+
+        assert!(regeneration_reason(&expired, now, None)
+            .unwrap()
+            .contains("expired"));
+        assert!(regeneration_reason(&expiring, now, None)
+            .unwrap()
+            .contains("expires soon"));
+    }
+
+    #[test]
+    fn test_far_future_cert_is_kept() {
+        let now = OffsetDateTime::now_utc();
+        let cert = cert_expiring_at(now + time::Duration::days(300));
+
+        assert_eq!(regeneration_reason(&cert, now, None), None);
+    }
+
+    #[test]
+    fn test_acceptor_for_each_key_algorithm() {
+        for algo in [CertAlgo::Ecdsa, CertAlgo::Rsa2048] {
+            let key_pair = generate_key_pair(algo).unwrap();
+            let cert = cert_params(false).unwrap().self_signed(&key_pair).unwrap();
+            let cert_der = cert.der().to_vec();
+            assert_eq!(cert_algorithm(&cert_der), Some(algo));
+
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+            let config = try_create_tls_config(vec![CertificateDer::from(cert_der)], key);
+            assert!(config.is_ok(), "{:?}: {:?}", algo, config.err());
+            let _ = TlsAcceptor::from(Arc::new(config.unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_algorithm_mismatch_triggers_regeneration() {
+        let now = OffsetDateTime::now_utc();
+        let key_pair = generate_key_pair(CertAlgo::Ecdsa).unwrap();
+        let cert = cert_params(false).unwrap().self_signed(&key_pair).unwrap();
+
+        let wanted = |algo| {
+            Some(CertOptions {
+                algo,
+                intercept_real_hosts: false,
+            })
+        };
+
+        assert_eq!(
+            regeneration_reason(cert.der(), now, wanted(CertAlgo::Ecdsa)),
+            None
+        );
+        assert!(regeneration_reason(cert.der(), now, wanted(CertAlgo::Rsa2048)).is_some());
+    }
+
+    #[test]
+    fn test_san_set_per_mode() {
+        let key_pair = KeyPair::generate().unwrap();
+        let localhost_only = cert_params(false).unwrap().self_signed(&key_pair).unwrap();
+        let with_real_hosts = cert_params(true).unwrap().self_signed(&key_pair).unwrap();
+        let real_hosts: Vec<String> = ["ppy.sh", "c.ppy.sh", "osu.ppy.sh", "b.ppy.sh"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+
+        assert!(missing_sans(localhost_only.der(), &required_san_names(false)).is_empty());
+        assert_eq!(missing_sans(localhost_only.der(), &real_hosts), real_hosts);
+
+        assert!(missing_sans(with_real_hosts.der(), &required_san_names(true)).is_empty());
+        assert!(missing_sans(with_real_hosts.der(), &real_hosts).is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint_format() {
+        assert_eq!(
+            fingerprint(b""),
+            "E3:B0:C4:42:98:FC:1C:14:9A:FB:F4:C8:99:6F:B9:24:\
+             27:AE:41:E4:64:9B:93:4C:A4:95:99:1B:78:52:B8:55"
+        );
+    }
+
+    #[test]
+    fn test_regeneration_produces_new_key() {
+        let (old_cert, old_key) = generate_cert(CertOptions::default()).unwrap();
+        let (new_cert, new_key) = generate_cert(CertOptions::default()).unwrap();
+
+        assert_ne!(old_key.secret_pkcs8_der(), new_key.secret_pkcs8_der());
+        assert_ne!(fingerprint(&old_cert), fingerprint(&new_cert));
+    }
+
+    #[test]
+    fn test_fingerprint_stable_for_same_cert() {
+        let first = cert_fingerprint().unwrap();
+        let second = cert_fingerprint().unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            fingerprint(&std::fs::read(get_cert_path().unwrap()).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_missing_sans_reports_uncovered_hosts() {
+        let cert = cert_with_sans(&["localhost", "osu.localhost"]);
+        let required: Vec<String> = ["localhost", "osu.localhost", "c.localhost"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert_eq!(missing_sans(&cert, &required), vec!["c.localhost"]);
+    }
+
+    #[test]
+    fn test_wildcard_san_covers_single_label() {
+        let cert = cert_with_sans(&["localhost", "*.localhost"]);
+
+        assert!(missing_sans(&cert, &required_san_names(false)).is_empty());
+        assert!(!san_matches("*.localhost", "localhost"));
+        assert!(!san_matches("*.localhost", "a.b.localhost"));
+        assert!(san_matches("*.LOCALHOST", "C.localhost"));
+    }
+
+    #[test]
+    fn test_unparseable_cert_covers_nothing() {
+        let required = required_san_names(false);
+        assert_eq!(missing_sans(b"not a certificate", &required), required);
+    }
+
+    #[test]
+    fn test_trust_commands() {
+        assert_eq!(
+            certutil_install(r"C:\rai\localhost.cer").args,
+            ["-addstore", "-user", "Root", r"C:\rai\localhost.cer"]
+        );
+        assert_eq!(
+            security_install("/tmp/localhost.cer", "/Users/me/login.keychain-db"),
+            TrustCommand::new(
+                "security",
+                [
+                    "add-trusted-cert",
+                    "-r",
+                    "trustRoot",
+                    "-k",
+                    "/Users/me/login.keychain-db",
+                    "/tmp/localhost.cer"
+                ]
+            )
+        );
+        assert_eq!(
+            security_verify("/tmp/localhost.cer").args,
+            ["verify-cert", "-c", "/tmp/localhost.cer"]
+        );
+        assert!(update_ca_certificates().args.is_empty());
+
+        let nss_db = std::path::Path::new("/home/me/.pki/nssdb");
+        assert_eq!(
+            nss_install(nss_db, "/tmp/localhost.cer").args,
+            [
+                "-d",
+                "sql:/home/me/.pki/nssdb",
+                "-A",
+                "-t",
+                "C,,",
+                "-n",
+                "rai!connect",
+                "-i",
+                "/tmp/localhost.cer"
+            ]
+        );
+        assert_eq!(
+            nss_query(nss_db).args,
+            ["-d", "sql:/home/me/.pki/nssdb", "-L", "-n", "rai!connect"]
+        );
+        assert_eq!(certutil_query().program, "certutil");
+    }
+
+    #[test]
+    fn test_uninstall_commands() {
+        assert_eq!(
+            certutil_uninstall().args,
+            ["-delstore", "-user", "Root", "rai!connect Local Proxy"]
+        );
+        assert_eq!(
+            security_find("/Users/me/login.keychain-db").args,
+            [
+                "find-certificate",
+                "-c",
+                "rai!connect Local Proxy",
+                "/Users/me/login.keychain-db"
+            ]
+        );
+        assert_eq!(
+            security_uninstall("/Users/me/login.keychain-db").args,
+            [
+                "delete-certificate",
+                "-c",
+                "rai!connect Local Proxy",
+                "-t",
+                "/Users/me/login.keychain-db"
+            ]
+        );
+        assert_eq!(
+            nss_uninstall(std::path::Path::new("/home/me/.pki/nssdb")).args,
+            ["-d", "sql:/home/me/.pki/nssdb", "-D", "-n", "rai!connect"]
+        );
+    }
+
     #[test]
     fn test_create_acceptor() {
-        let result = create_tls_acceptor();
+        let result = create_tls_acceptor(CertOptions::default());
         assert!(
             result.is_ok(),
             "Failed to create TLS acceptor: {:?}",
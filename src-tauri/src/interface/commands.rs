@@ -1,31 +1,151 @@
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use parking_lot::RwLock;
-use tauri::{tray::TrayIconId, AppHandle, Manager, State};
+use parking_lot::{Mutex, RwLock};
+use tauri::{tray::TrayIconId, AppHandle, Emitter, Manager, State};
+use time::format_description::well_known::Rfc3339;
+use tokio::sync::mpsc;
 
 use crate::application::{
-    create_desktop_shortcut, detect_osu_path, get_osu_path, is_osu_running,
-    is_valid_osu_installation, launch_osu, remove_desktop_shortcut, shortcut_exists, ProxyManager,
+    build_diagnostics_report, cleanup_system, create_desktop_shortcut, detect_osu_path,
+    is_osu_running, is_valid_osu_installation, launch_osu, osu_variant, preflight,
+    remove_desktop_shortcut, resolve_osu_path, shortcut_exists, CleanupReport, OsuProcess,
+    OsuVariant, PreflightReport, ProxyError, ProxyManager,
+};
+use crate::domain::{
+    self, normalize_base_url, AppConfig, AppState, ConnectionStatus, LaunchProfile, ProxyConfig,
+    RouteSimulation, SupporterMode,
+};
+use crate::infrastructure::http_proxy::build_upstream_client;
+use crate::infrastructure::logging::{LogBuffer, LogEntry, LogFilter, LogTail};
+use crate::infrastructure::mirror::{self, AvailabilityResult, MirrorStatus};
+use crate::infrastructure::packet_capture::CapturedPacket;
+use crate::infrastructure::port::{self, PortStatus};
+use crate::infrastructure::self_test::{run_self_test, SelfTestReport};
+use crate::infrastructure::storage::{
+    export_config_json, import_config_json, load_config, save_config,
 };
-use crate::domain::{AppConfig, AppState};
-use crate::infrastructure::logging::{LogBuffer, LogEntry};
-use crate::infrastructure::storage::{load_config, save_config};
 use crate::infrastructure::tls;
+use crate::infrastructure::{elevation, event_log};
+use crate::interface::notifications::{notify, ProxyEvent};
 
 pub struct TauriState {
     pub config: RwLock<AppConfig>,
     pub proxy: RwLock<Option<ProxyManager>>,
+    /// Set while `restart_proxy` has the manager out of `proxy`, so nothing
+    /// starts a second one or cleans up underneath it in the meantime.
+    pub restarting: AtomicBool,
+    /// The osu! process started by the last launch, if any.
+    pub osu_process: Mutex<Option<OsuProcess>>,
     pub logs: LogBuffer,
+    pub log_filter: LogFilter,
 }
 
 impl TauriState {
-    pub fn new(logs: LogBuffer) -> Self {
+    pub fn new(logs: LogBuffer, log_filter: LogFilter) -> Self {
         Self {
             config: RwLock::new(AppConfig::default()),
             proxy: RwLock::new(None),
+            restarting: AtomicBool::new(false),
+            osu_process: Mutex::new(None),
             logs,
+            log_filter,
+        }
+    }
+
+    /// Whether a proxy is running or in the middle of restarting.
+    pub fn proxy_active(&self) -> bool {
+        self.proxy.read().is_some() || self.restarting.load(Ordering::SeqCst)
+    }
+
+    fn ensure_not_restarting(&self) -> Result<(), String> {
+        if self.restarting.load(Ordering::SeqCst) {
+            return Err("The proxy is restarting, try again in a moment".to_string());
         }
+        Ok(())
+    }
+}
+
+/// Clears [`TauriState::restarting`] when the restart finishes, however it
+/// ends.
+struct RestartGuard<'a>(&'a AtomicBool);
+
+impl Drop for RestartGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Event emitted whenever the proxy's connection status changes.
+///
+/// The payload is the full [`AppState`] after the transition, the same shape
+/// `get_status` returns, so the UI can apply it directly.
+pub const STATUS_CHANGED_EVENT: &str = "proxy://status-changed";
+
+/// Event emitted for each new log entry, with the [`LogEntry`] as payload.
+///
+/// Entries logged before the frontend subscribed, or dropped because it fell
+/// behind, can be backfilled with `get_logs_since`.
+pub const LOG_ENTRY_EVENT: &str = "logs://new-entry";
+
+/// Re-emits log entries captured by the tracing layer as frontend events.
+pub async fn forward_log_entries(app: AppHandle, mut entries: mpsc::Receiver<LogEntry>) {
+    while let Some(entry) = entries.recv().await {
+        let _ = app.emit(LOG_ENTRY_EVENT, entry);
+    }
+}
+
+/// Creates a proxy manager that reports status changes to the frontend (and
+/// as notifications) and disconnects when osu! exits if the user opted into
+/// that.
+pub fn new_proxy_manager(app: &AppHandle, config: ProxyConfig) -> ProxyManager {
+    let emitter = app.clone();
+    let exit_app = app.clone();
+    // The listener also fires when osu! starts or exits, so notifications
+    // compare against the last status seen
+    let last_status = Mutex::new(ConnectionStatus::Disconnected);
+    let osu_poll_interval = app.state::<TauriState>().config.read().osu_poll_interval();
+    ProxyManager::new(config)
+        .with_osu_poll_interval(osu_poll_interval)
+        .with_status_listener(Arc::new(move |state: &AppState| {
+            if let Err(e) = emitter.emit(STATUS_CHANGED_EVENT, state) {
+                tracing::warn!("Failed to emit status change: {}", e);
+            }
+
+            let previous = std::mem::replace(&mut *last_status.lock(), state.status);
+            if let Some(event) = ProxyEvent::from_status_change(previous, state) {
+                notify(&emitter, &event);
+            }
+        }))
+        .with_osu_exit_handler(Arc::new(move || {
+            let app = exit_app.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = app.state::<TauriState>();
+                // Read at exit time so toggling the setting applies right away
+                if !state.config.read().auto_disconnect_on_osu_exit {
+                    return;
+                }
+
+                tracing::info!("osu! exited, stopping the proxy");
+                match stop_proxy(&state).await {
+                    Ok(()) => notify(&app, &ProxyEvent::OsuExitDisconnected),
+                    Err(e) => tracing::warn!("Failed to stop the proxy after osu! exited: {}", e),
+                }
+            });
+        }))
+}
+
+/// Stops and drops the running proxy, if any.
+async fn stop_proxy(state: &TauriState) -> Result<(), String> {
+    state.ensure_not_restarting()?;
+    let pm = state.proxy.write().take();
+
+    if let Some(mut pm) = pm {
+        pm.stop().await?;
     }
+
+    Ok(())
 }
 
 #[tauri::command]
@@ -37,16 +157,184 @@ pub fn get_config(state: State<'_, TauriState>) -> AppConfig {
 pub fn set_config(
     app: AppHandle,
     state: State<'_, TauriState>,
-    config: AppConfig,
+    mut config: AppConfig,
 ) -> Result<(), String> {
+    config.proxy.api_base_url = normalize_base_url(&config.proxy.api_base_url);
+    config.proxy.direct_base_url = normalize_base_url(&config.proxy.direct_base_url);
+    config.proxy.validate().map_err(|e| e.to_string())?;
+    apply_config(&app, &state, config)
+}
+
+/// Applies a validated config to the running app and persists it.
+fn apply_config(app: &AppHandle, state: &TauriState, config: AppConfig) -> Result<(), String> {
+    event_log::set_enabled(config.windows_event_log);
+    state.logs.set_capacity(config.log_buffer_size);
+    if config.debug_logging != state.config.read().debug_logging {
+        state.log_filter.set_debug(config.debug_logging)?;
+    }
+    // Supporter mode applies to a running proxy straight away; the other
+    // proxy settings need a restart
+    if let Some(pm) = state.proxy.write().as_mut() {
+        pm.set_supporter_mode(config.proxy.supporter_mode());
+    }
     *state.config.write() = config.clone();
-    save_config(&app, &config)?;
+    save_config(app, &config)?;
     Ok(())
 }
 
+/// Changes part of the config, then validates, persists and applies it.
+///
+/// The config lock is held throughout, so a panel changing one setting can't
+/// overwrite another panel's concurrent change with a stale copy.
+fn update_config<F>(app: &AppHandle, state: &TauriState, edit: F) -> Result<AppConfig, String>
+where
+    F: FnOnce(&mut AppConfig) -> Result<(), String>,
+{
+    let mut current = state.config.write();
+    let mut config = current.clone();
+    edit(&mut config)?;
+    config.proxy.validate().map_err(|e| e.to_string())?;
+    save_config(app, &config)?;
+    *current = config.clone();
+    drop(current);
+
+    if let Some(pm) = state.proxy.write().as_mut() {
+        pm.set_supporter_mode(config.proxy.supporter_mode());
+    }
+    Ok(config)
+}
+
+/// Turns supporter injection on or off, taking effect on the next Bancho
+/// poll if the proxy is running.
+#[tauri::command]
+pub fn set_supporter_mode(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    mode: SupporterMode,
+) -> Result<AppConfig, String> {
+    update_config(&app, &state, |config| {
+        config.proxy.set_supporter_mode(mode);
+        Ok(())
+    })
+}
+
+/// Changes the port the proxy listens on, from the next time it starts.
+#[tauri::command]
+pub fn set_https_port(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    port: u16,
+) -> Result<AppConfig, String> {
+    update_config(&app, &state, |config| {
+        config.proxy.https_port = port;
+        Ok(())
+    })
+}
+
+/// Sets the osu! folder, or clears it so the install is detected instead.
+#[tauri::command]
+pub fn set_osu_path(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    path: Option<PathBuf>,
+) -> Result<AppConfig, String> {
+    if let Some(path) = &path {
+        if !is_valid_osu_installation(path) {
+            return Err(format!("No osu! installation found in {}", path.display()));
+        }
+    }
+    update_config(&app, &state, |config| {
+        config.osu_path = path;
+        Ok(())
+    })
+}
+
+/// Adds a launch profile. It isn't selected; use `select_profile` for that.
+#[tauri::command]
+pub fn add_profile(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    mut profile: LaunchProfile,
+) -> Result<AppConfig, String> {
+    profile.name = profile.name.trim().to_string();
+    if profile.name.is_empty() {
+        return Err("Profile name cannot be empty".to_string());
+    }
+    if let Some(path) = &profile.osu_path {
+        if !is_valid_osu_installation(path) {
+            return Err(format!("No osu! installation found in {}", path.display()));
+        }
+    }
+    profile.devserver_host = profile
+        .devserver_host
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty());
+
+    update_config(&app, &state, |config| {
+        config.profiles.push(profile);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn remove_profile(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    index: usize,
+) -> Result<AppConfig, String> {
+    update_config(&app, &state, |config| {
+        config
+            .remove_profile(index)
+            .map(|_| ())
+            .ok_or_else(|| format!("No profile at index {}", index))
+    })
+}
+
+/// Chooses the profile `connect` launches, or the default with `None`.
+#[tauri::command]
+pub fn select_profile(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    index: Option<usize>,
+) -> Result<AppConfig, String> {
+    update_config(&app, &state, |config| {
+        if let Some(index) = index.filter(|&i| i >= config.profiles.len()) {
+            return Err(format!("No profile at index {}", index));
+        }
+        config.active_profile = index;
+        Ok(())
+    })
+}
+
+/// The current config as pretty JSON for moving settings to another machine.
+/// Machine-specific fields such as the osu! path are left out.
+#[tauri::command]
+pub fn export_config(state: State<'_, TauriState>) -> String {
+    export_config_json(&state.config.read())
+}
+
+/// Validate, apply and persist a config produced by `export_config`,
+/// keeping this machine's osu! path.
+#[tauri::command]
+pub fn import_config(
+    app: AppHandle,
+    state: State<'_, TauriState>,
+    json: String,
+) -> Result<AppConfig, String> {
+    let current = state.config.read().clone();
+    let config = import_config_json(&json, &current)?;
+    apply_config(&app, &state, config.clone())?;
+    Ok(config)
+}
+
 #[tauri::command]
 pub fn load_saved_config(app: AppHandle, state: State<'_, TauriState>) -> AppConfig {
     let config = load_config(&app);
+    event_log::set_enabled(config.windows_event_log);
+    state.logs.set_capacity(config.log_buffer_size);
+    if let Err(e) = state.log_filter.set_debug(config.debug_logging) {
+        tracing::warn!("{}", e);
+    }
     *state.config.write() = config.clone();
     config
 }
@@ -61,22 +349,119 @@ pub fn validate_osu_path(path: String) -> bool {
     is_valid_osu_installation(&PathBuf::from(path))
 }
 
+/// Which osu! client is installed at `path`, or `None` if neither is.
 #[tauri::command]
-pub async fn is_osu_running_cmd() -> bool {
-    is_osu_running().await
+pub fn detect_osu_variant(path: String) -> Option<OsuVariant> {
+    osu_variant(&PathBuf::from(path))
+}
+
+#[tauri::command]
+pub async fn is_osu_running_cmd(app: AppHandle) -> bool {
+    let max_age = app.state::<TauriState>().config.read().osu_poll_interval();
+    is_osu_running(max_age).await
 }
 
 #[tauri::command]
 pub fn get_status(state: State<'_, TauriState>) -> AppState {
     let proxy = state.proxy.read();
     match proxy.as_ref() {
-        Some(pm) => pm.state().read().clone(),
+        Some(pm) => {
+            let mut status = pm.state().read().clone();
+            status.update_uptime(domain::unix_millis());
+            status
+        }
         None => AppState::default(),
     }
 }
 
+/// Raw Bancho packets recorded with `capture_packets` on, oldest first, as
+/// hex with their packet type. Empty when the proxy has never run.
+#[tauri::command]
+pub fn get_captured_packets(state: State<'_, TauriState>) -> Vec<CapturedPacket> {
+    state
+        .proxy
+        .read()
+        .as_ref()
+        .map(ProxyManager::captured_packets)
+        .unwrap_or_default()
+}
+
+/// Send a request through the running proxy to check it intercepts and
+/// routes traffic, without needing osu!.
+#[tauri::command]
+pub async fn self_test(state: State<'_, TauriState>) -> Result<SelfTestReport, String> {
+    let (ip, port) = state
+        .proxy
+        .read()
+        .as_ref()
+        .filter(|pm| pm.status() == ConnectionStatus::Connected)
+        .map(|pm| (pm.bind_ip(), pm.https_port()))
+        .ok_or("The proxy is not running")?;
+    let cert = tls::stored_certificate().ok_or("The proxy certificate has not been created yet")?;
+
+    Ok(run_self_test(ip, port, &cert).await)
+}
+
+/// Zero the request and download counters of the running proxy, e.g. to
+/// measure a single play session. The connection status is left as is.
+#[tauri::command]
+pub fn reset_stats(state: State<'_, TauriState>) {
+    if let Some(pm) = state.proxy.read().as_ref() {
+        pm.reset_stats();
+    }
+}
+
+/// Check whether the proxy could bind `port` on the configured `bind_host`,
+/// naming the program holding it so the UI can tell the user exactly what to
+/// close.
+#[tauri::command]
+pub fn check_port_available(state: State<'_, TauriState>, port: u16) -> PortStatus {
+    let ip = state.config.read().proxy.bind_ip();
+    port::check_port_available(ip, port)
+}
+
+/// Whether the app runs with the administrator privileges needed to edit the
+/// hosts file and install the certificate.
+#[tauri::command]
+pub fn is_elevated() -> bool {
+    elevation::is_elevated()
+}
+
+/// Check every prerequisite for connecting, so the UI can show what to fix
+/// before the user hits connect.
+#[tauri::command]
+pub fn preflight_check(state: State<'_, TauriState>) -> PreflightReport {
+    let config = state.config.read().clone();
+    let proxy_running = state.proxy_active();
+    preflight::preflight_check(&config, proxy_running)
+}
+
+/// Build a report for attaching to bug reports: environment, config,
+/// preflight checks and buffered logs, with credentials masked. With
+/// `redact_paths`, the osu! path is masked too.
 #[tauri::command]
-pub async fn start_proxy(state: State<'_, TauriState>) -> Result<(), String> {
+pub fn collect_diagnostics(state: State<'_, TauriState>, redact_paths: bool) -> String {
+    let config = state.config.read().clone();
+    let status = state
+        .proxy
+        .read()
+        .as_ref()
+        .map(|pm| pm.state().read().status)
+        .unwrap_or_default();
+    let preflight = preflight::preflight_check(&config, status != ConnectionStatus::Disconnected);
+
+    build_diagnostics_report(
+        &config,
+        status,
+        &preflight,
+        &state.logs.get_all(),
+        redact_paths,
+    )
+}
+
+#[tauri::command]
+pub async fn start_proxy(app: AppHandle, state: State<'_, TauriState>) -> Result<(), ProxyError> {
+    state.ensure_not_restarting().map_err(ProxyError::Other)?;
     // Check if proxy already exists to prevent orphaned proxies
     if state.proxy.read().is_some() {
         return Ok(());
@@ -84,7 +469,7 @@ pub async fn start_proxy(state: State<'_, TauriState>) -> Result<(), String> {
 
     let config = state.config.read().clone();
 
-    let mut proxy_manager = ProxyManager::new(config.proxy.clone());
+    let mut proxy_manager = new_proxy_manager(&app, config.proxy.clone());
     proxy_manager.start().await?;
     *state.proxy.write() = Some(proxy_manager);
 
@@ -92,33 +477,105 @@ pub async fn start_proxy(state: State<'_, TauriState>) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn connect(state: State<'_, TauriState>) -> Result<(), String> {
+pub async fn connect(app: AppHandle, state: State<'_, TauriState>) -> Result<(), ProxyError> {
     let config = state.config.read().clone();
-    let osu_path = get_osu_path(&config)
-        .ok_or("osu! installation not found. Please configure the path in settings.")?;
+    let profile = config.active_launch_profile();
+    let osu_path = resolve_osu_path(&config)?;
+    state.ensure_not_restarting().map_err(ProxyError::Other)?;
 
     // Check if proxy already exists to prevent orphaned proxies
     if state.proxy.read().is_none() {
-        let mut proxy_manager = ProxyManager::new(config.proxy.clone());
+        let mut proxy_manager = new_proxy_manager(&app, config.proxy.clone());
         proxy_manager.start().await?;
         *state.proxy.write() = Some(proxy_manager);
     }
 
-    launch_osu(&osu_path, "localhost")?;
+    let devserver_host = profile.devserver_host.unwrap_or_else(|| {
+        state
+            .proxy
+            .read()
+            .as_ref()
+            .map_or_else(|| "localhost".to_string(), ProxyManager::devserver_host)
+    });
+    let process = launch_osu(&osu_path, &devserver_host, &profile.launch_args)
+        .await
+        .map_err(ProxyError::LaunchFailed)?;
+    *state.osu_process.lock() = Some(process);
     Ok(())
 }
 
+/// Restart the proxy with the current config, e.g. after changing the port
+/// or mirror URL. Unlike `connect`, this doesn't launch osu!.
 #[tauri::command]
-pub async fn disconnect(state: State<'_, TauriState>) -> Result<(), String> {
+pub async fn restart_proxy(app: AppHandle, state: State<'_, TauriState>) -> Result<(), ProxyError> {
+    if state.restarting.swap(true, Ordering::SeqCst) {
+        return Err(ProxyError::Other(
+            "The proxy is already restarting".to_string(),
+        ));
+    }
+    let _restarting = RestartGuard(&state.restarting);
+
+    let config = state.config.read().proxy.clone();
     let pm = state.proxy.write().take();
 
     if let Some(mut pm) = pm {
-        pm.stop().await?;
+        let result = pm.restart(config).await;
+        // Put it back even on failure: it's stopped but still reports the
+        // error, and disconnecting clears it as usual
+        *state.proxy.write() = Some(pm);
+        return result;
     }
 
+    let mut proxy_manager = new_proxy_manager(&app, config);
+    proxy_manager.start().await?;
+    *state.proxy.write() = Some(proxy_manager);
     Ok(())
 }
 
+#[tauri::command]
+pub async fn disconnect(state: State<'_, TauriState>) -> Result<(), String> {
+    stop_proxy(&state).await
+}
+
+#[tauri::command]
+pub async fn check_beatmap_available(
+    state: State<'_, TauriState>,
+    beatmapset_id: u64,
+) -> Result<AvailabilityResult, String> {
+    let config = state.config.read().proxy.clone();
+    let client = mirror_client(&state, &config);
+    Ok(mirror::check_beatmap_available(&client, &config.direct_base_url, beatmapset_id).await)
+}
+
+/// Check that the configured mirror answers, so a wrong `direct_base_url`
+/// shows up before connecting.
+#[tauri::command]
+pub async fn test_mirror(state: State<'_, TauriState>) -> Result<MirrorStatus, String> {
+    let config = state.config.read().proxy.clone();
+    let client = mirror_client(&state, &config);
+    Ok(mirror::test_mirror(&client, &config.direct_base_url).await)
+}
+
+/// The running proxy's client, so its connection pool is reused, or a new
+/// one if there's no proxy or it was built with other timeouts.
+fn mirror_client(state: &TauriState, config: &ProxyConfig) -> reqwest::Client {
+    state
+        .proxy
+        .read()
+        .as_ref()
+        .and_then(ProxyManager::clients)
+        .filter(|clients| clients.matches(config))
+        .map(|clients| clients.general.clone())
+        .unwrap_or_else(|| build_upstream_client(config))
+}
+
+/// Report how the proxy would route a request for `host` and `path` under
+/// the current config, without sending anything.
+#[tauri::command]
+pub fn simulate_route(state: State<'_, TauriState>, host: String, path: String) -> RouteSimulation {
+    domain::simulate_route(&host, &path, &state.config.read().proxy)
+}
+
 #[tauri::command]
 pub fn hide_window(app: AppHandle) {
     if let Some(window) = app.get_webview_window("main") {
@@ -134,24 +591,49 @@ pub fn show_window(app: AppHandle) {
     }
 }
 
+/// Remove the hosts entries, and the certificate with `remove_certificate`,
+/// without quitting. Refused while the proxy is running, since it needs both.
+#[tauri::command]
+pub fn cleanup_now(
+    state: State<'_, TauriState>,
+    remove_certificate: bool,
+) -> Result<CleanupReport, String> {
+    if state.proxy_active() {
+        return Err("Disconnect before cleaning up".to_string());
+    }
+    cleanup_system(remove_certificate)
+}
+
 #[tauri::command]
 pub fn quit_app(app: AppHandle) {
     app.exit(0);
 }
 
 #[tauri::command]
-pub fn get_logs(state: State<'_, TauriState>, count: Option<usize>) -> Vec<LogEntry> {
-    match count {
+pub fn get_logs(
+    state: State<'_, TauriState>,
+    count: Option<usize>,
+    min_level: Option<String>,
+) -> Result<Vec<LogEntry>, String> {
+    if let Some(level) = min_level {
+        let level = level
+            .parse::<tracing::Level>()
+            .map_err(|_| format!("Invalid log level: {}", level))?;
+        return Ok(state.logs.get_filtered(level, count));
+    }
+
+    Ok(match count {
         Some(n) => state.logs.get_recent(n),
         None => state.logs.get_all(),
-    }
+    })
 }
 
 /// Get only logs newer than the given ID for differential updates.
 /// This is much more efficient than get_logs() when polling frequently,
-/// as it only returns new entries since the last fetch.
+/// as it only returns new entries since the last fetch. `missed` says how
+/// many newer entries were already dropped from the buffer.
 #[tauri::command]
-pub fn get_logs_since(state: State<'_, TauriState>, last_id: u64) -> Vec<LogEntry> {
+pub fn get_logs_since(state: State<'_, TauriState>, last_id: u64) -> LogTail {
     state.logs.get_logs_since(last_id)
 }
 
@@ -168,6 +650,12 @@ pub fn clear_logs(state: State<'_, TauriState>) {
     state.logs.clear();
 }
 
+/// Returns the log filter directives currently in effect.
+#[tauri::command]
+pub fn get_active_log_filter(state: State<'_, TauriState>) -> String {
+    state.log_filter.directives()
+}
+
 #[tauri::command]
 pub fn is_certificate_installed() -> bool {
     tls::is_certificate_installed()
@@ -178,6 +666,36 @@ pub fn install_certificate() -> Result<bool, String> {
     tls::install_certificate().map_err(|e| e.to_string())
 }
 
+/// Remove the certificate from the trust store and delete it from disk.
+#[tauri::command]
+pub fn uninstall_certificate() -> Result<bool, String> {
+    tls::uninstall_certificate().map_err(|e| e.to_string())
+}
+
+/// Replace the certificate with a freshly generated one and install it,
+/// returning the new fingerprint. Refused while the proxy is running, since
+/// it's serving the old one.
+#[tauri::command]
+pub fn regenerate_certificate(state: State<'_, TauriState>) -> Result<String, String> {
+    if state.proxy_active() {
+        return Err("Disconnect before regenerating the certificate".to_string());
+    }
+    let options = tls::CertOptions::from(&state.config.read().proxy);
+    tls::regenerate_certificate(options).map_err(|e| e.to_string())
+}
+
+/// When the certificate expires, as an RFC 3339 timestamp, or `None` if there is no certificate.
+#[tauri::command]
+pub fn get_certificate_expiry() -> Option<String> {
+    tls::cert_expiry().and_then(|expiry| expiry.format(&Rfc3339).ok())
+}
+
+/// SHA-256 fingerprint of the certificate, for checking a manual install.
+#[tauri::command]
+pub fn get_certificate_fingerprint() -> Result<String, String> {
+    tls::cert_fingerprint().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn get_certificate_path() -> Result<String, String> {
     tls::get_cert_path()
@@ -185,6 +703,13 @@ pub fn get_certificate_path() -> Result<String, String> {
         .map_err(|e| e.to_string())
 }
 
+/// Check that the certificate covers every hostname osu! connects to.
+/// Returns the missing hostnames so the UI can explain why a regeneration is needed.
+#[tauri::command]
+pub fn verify_certificate_sans(state: State<'_, TauriState>) -> Result<(), Vec<String>> {
+    tls::cert_covers_required_sans(state.config.read().proxy.intercept_real_hosts)
+}
+
 /// Update the system tray tooltip to reflect the current connection status.
 /// Called by the frontend when the connection status changes.
 #[tauri::command]
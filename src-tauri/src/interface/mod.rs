@@ -1,3 +1,4 @@
 pub mod commands;
+pub mod notifications;
 
 pub use commands::*;
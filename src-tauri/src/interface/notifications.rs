@@ -0,0 +1,135 @@
+//! Desktop notifications for connection changes, which are easy to miss
+//! while the window is hidden in the tray.
+//!
+//! Opt-in through [`AppConfig::notifications_enabled`](crate::domain::AppConfig).
+
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::domain::{AppState, ConnectionStatus};
+use crate::interface::TauriState;
+
+/// Something worth telling the user about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyEvent {
+    /// The proxy started and is ready for osu!.
+    Connected,
+    /// The proxy failed, with the error recorded in `AppState::last_error`.
+    ConnectFailed { error: Option<String> },
+    /// osu! exited and the proxy was stopped because of it.
+    OsuExitDisconnected,
+}
+
+impl ProxyEvent {
+    /// The event for a status change from `previous` to `state.status`, if
+    /// it deserves a notification. Disconnecting on request doesn't.
+    pub fn from_status_change(previous: ConnectionStatus, state: &AppState) -> Option<Self> {
+        if previous == state.status {
+            return None;
+        }
+        match state.status {
+            ConnectionStatus::Connected => Some(Self::Connected),
+            ConnectionStatus::Error => Some(Self::ConnectFailed {
+                error: state.last_error.clone(),
+            }),
+            ConnectionStatus::Disconnected | ConnectionStatus::Connecting => None,
+        }
+    }
+
+    /// Title and body of the notification.
+    pub fn message(&self) -> (&'static str, String) {
+        match self {
+            Self::Connected => (
+                "rai!connect connected",
+                "osu!direct is ready. Downloads now go through the mirror.".to_string(),
+            ),
+            Self::ConnectFailed { error } => (
+                "rai!connect failed to connect",
+                error
+                    .clone()
+                    .unwrap_or_else(|| "The proxy stopped unexpectedly.".to_string()),
+            ),
+            Self::OsuExitDisconnected => (
+                "rai!connect disconnected",
+                "osu! was closed, so the proxy has been stopped.".to_string(),
+            ),
+        }
+    }
+}
+
+/// Shows a notification for `event` if the user turned notifications on.
+pub fn notify(app: &AppHandle, event: &ProxyEvent) {
+    let state = app.state::<TauriState>();
+    if !state.config.read().notifications_enabled {
+        return;
+    }
+
+    let (title, body) = event.message();
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        tracing::warn!("Failed to show notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(status: ConnectionStatus, last_error: Option<&str>) -> AppState {
+        AppState {
+            status,
+            last_error: last_error.map(str::to_string),
+            ..AppState::default()
+        }
+    }
+
+    #[test]
+    fn test_messages_for_each_event() {
+        let (title, body) = ProxyEvent::Connected.message();
+        assert_eq!(title, "rai!connect connected");
+        assert!(body.contains("osu!direct"));
+
+        let failed = ProxyEvent::ConnectFailed {
+            error: Some("Port 443 is already in use by nginx.exe.".to_string()),
+        };
+        let (title, body) = failed.message();
+        assert_eq!(title, "rai!connect failed to connect");
+        assert_eq!(body, "Port 443 is already in use by nginx.exe.");
+
+        let (_, body) = ProxyEvent::ConnectFailed { error: None }.message();
+        assert_eq!(body, "The proxy stopped unexpectedly.");
+
+        let (title, body) = ProxyEvent::OsuExitDisconnected.message();
+        assert_eq!(title, "rai!connect disconnected");
+        assert!(body.contains("osu! was closed"));
+    }
+
+    #[test]
+    fn test_only_new_connected_and_error_states_notify() {
+        use ConnectionStatus::*;
+
+        assert_eq!(
+            ProxyEvent::from_status_change(Connecting, &state(Connected, None)),
+            Some(ProxyEvent::Connected)
+        );
+        assert_eq!(
+            ProxyEvent::from_status_change(Connecting, &state(Error, Some("boom"))),
+            Some(ProxyEvent::ConnectFailed {
+                error: Some("boom".to_string())
+            })
+        );
+
+        // osu! starting re-sends the same status
+        assert_eq!(
+            ProxyEvent::from_status_change(Connected, &state(Connected, None)),
+            None
+        );
+        assert_eq!(
+            ProxyEvent::from_status_change(Connected, &state(Disconnected, None)),
+            None
+        );
+        assert_eq!(
+            ProxyEvent::from_status_change(Disconnected, &state(Connecting, None)),
+            None
+        );
+    }
+}
@@ -8,34 +8,52 @@ use tauri::{
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     Manager, RunEvent, WindowEvent,
 };
+use tokio::sync::mpsc;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use application::{get_osu_path, launch_osu, ProxyManager};
-use infrastructure::logging::{LogBuffer, LogCaptureLayer};
+use application::{cleanup_system, get_osu_path, launch_osu};
+use infrastructure::event_log::{self, EventLogLayer};
+use infrastructure::logging::{
+    LogBuffer, LogCaptureLayer, LogEntry, LogFilter, DEBUG_LOG_DIRECTIVES, LOG_CHANNEL_CAPACITY,
+};
 use interface::{
-    check_shortcut_exists, clear_logs, connect, create_launch_shortcut, detect_osu, disconnect,
+    add_profile, check_beatmap_available, check_port_available, check_shortcut_exists, cleanup_now,
+    clear_logs, collect_diagnostics, connect, create_launch_shortcut, detect_osu,
+    detect_osu_variant, disconnect, export_config, forward_log_entries, get_active_log_filter,
+    get_captured_packets, get_certificate_expiry, get_certificate_fingerprint,
     get_certificate_path, get_config, get_latest_log_id, get_logs, get_logs_since, get_status,
-    hide_window, install_certificate, is_certificate_installed, is_osu_running_cmd,
-    load_saved_config, quit_app, remove_launch_shortcut, set_config, show_window, start_proxy,
-    update_tray_status, validate_osu_path, TauriState,
+    hide_window, import_config, install_certificate, is_certificate_installed, is_elevated,
+    is_osu_running_cmd, load_saved_config, new_proxy_manager, preflight_check, quit_app,
+    regenerate_certificate, remove_launch_shortcut, remove_profile, reset_stats, restart_proxy,
+    select_profile, self_test, set_config, set_https_port, set_osu_path, set_supporter_mode,
+    show_window, simulate_route, start_proxy, test_mirror, uninstall_certificate,
+    update_tray_status, validate_osu_path, verify_certificate_sans, TauriState,
 };
 
-fn init_logging(log_buffer: LogBuffer) {
+fn init_logging(log_buffer: LogBuffer, log_tx: mpsc::Sender<LogEntry>) -> LogFilter {
+    // Start verbose to capture boot logs; the saved setting is applied once
+    // the config has been loaded
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env().ok();
+    let from_env = env_filter.is_some();
+    let filter = env_filter.unwrap_or_else(|| DEBUG_LOG_DIRECTIVES.into());
+    let (log_filter, filter_layer) = LogFilter::new(filter, from_env);
+
     tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "rai_connect=debug,info".into()),
-        )
+        .with(filter_layer)
         .with(tracing_subscriber::fmt::layer())
-        .with(LogCaptureLayer::new(log_buffer))
+        .with(LogCaptureLayer::new(log_buffer).with_sender(log_tx))
+        .with(EventLogLayer)
         .init();
+
+    log_filter
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Create log buffer before initializing tracing so we capture boot logs
     let log_buffer = LogBuffer::new();
-    init_logging(log_buffer.clone());
+    let (log_tx, log_rx) = mpsc::channel(LOG_CHANNEL_CAPACITY);
+    let log_filter = init_logging(log_buffer.clone(), log_tx);
 
     tracing::info!("Starting rai!connect v{}", env!("CARGO_PKG_VERSION"));
 
@@ -62,10 +80,11 @@ pub fn run() {
                         config.proxy.upstream_server = server.clone();
                     }
 
-                    let proxy_running = state.proxy.read().is_some();
+                    let proxy_running = state.proxy_active();
 
                     if !proxy_running {
-                        let mut proxy_manager = ProxyManager::new(config.proxy.clone());
+                        let mut proxy_manager =
+                            new_proxy_manager(&app_handle, config.proxy.clone());
                         if let Err(e) = proxy_manager.start().await {
                             tracing::error!("--launch-osu: Failed to start proxy: {}", e);
                             return;
@@ -77,11 +96,23 @@ pub fn run() {
                     }
 
                     // Launch osu!
+                    let profile = config.active_launch_profile();
+                    let devserver_host = profile.devserver_host.unwrap_or_else(|| {
+                        state
+                            .proxy
+                            .read()
+                            .as_ref()
+                            .map_or_else(|| "localhost".to_string(), |pm| pm.devserver_host())
+                    });
                     if let Some(osu_path) = get_osu_path(&config) {
-                        if let Err(e) = launch_osu(&osu_path, "localhost") {
-                            tracing::error!("--launch-osu: Failed to launch osu!: {}", e);
-                        } else {
-                            tracing::info!("--launch-osu: osu! launched successfully");
+                        match launch_osu(&osu_path, &devserver_host, &profile.launch_args).await {
+                            Ok(process) => {
+                                *state.osu_process.lock() = Some(process);
+                                tracing::info!("--launch-osu: osu! launched successfully");
+                            }
+                            Err(e) => {
+                                tracing::error!("--launch-osu: Failed to launch osu!: {}", e)
+                            }
                         }
                     } else {
                         tracing::error!("--launch-osu: osu! path not configured");
@@ -107,12 +138,19 @@ pub fn run() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_store::Builder::new().build())
+        .plugin(tauri_plugin_notification::init())
         .setup(move |app| {
-            let state = TauriState::new(log_buffer);
+            let state = TauriState::new(log_buffer, log_filter);
             let config = infrastructure::storage::load_config(app.handle());
+            event_log::set_enabled(config.windows_event_log);
+            state.logs.set_capacity(config.log_buffer_size);
+            if let Err(e) = state.log_filter.set_debug(config.debug_logging) {
+                tracing::warn!("{}", e);
+            }
             *state.config.write() = config.clone();
             app.manage(state);
             setup_tray(app)?;
+            tauri::async_runtime::spawn(forward_log_entries(app.handle().clone(), log_rx));
 
             let has_minimized_flag = std::env::args().any(|a| a == "--minimized");
             let has_launch_osu_flag = std::env::args().any(|a| a == "--launch-osu");
@@ -138,7 +176,8 @@ pub fn run() {
                 tauri::async_runtime::spawn(async move {
                     tracing::info!("--launch-osu: Starting proxy and launching osu!");
 
-                    let mut proxy_manager = ProxyManager::new(config_clone.proxy.clone());
+                    let mut proxy_manager =
+                        new_proxy_manager(&app_handle, config_clone.proxy.clone());
                     if let Err(e) = proxy_manager.start().await {
                         tracing::error!("--launch-osu: Failed to start proxy: {}", e);
                         if let Some(window) = app_handle.get_webview_window("main") {
@@ -148,18 +187,26 @@ pub fn run() {
                         return;
                     }
 
+                    let profile = config_clone.active_launch_profile();
+                    let devserver_host = profile
+                        .devserver_host
+                        .unwrap_or_else(|| proxy_manager.devserver_host());
                     let state = app_handle.state::<TauriState>();
                     *state.proxy.write() = Some(proxy_manager);
 
                     if let Some(osu_path) = get_osu_path(&config_clone) {
-                        if let Err(e) = launch_osu(&osu_path, "localhost") {
-                            tracing::error!("--launch-osu: Failed to launch osu!: {}", e);
-                            if let Some(window) = app_handle.get_webview_window("main") {
-                                let _ = window.show();
-                                let _ = window.set_focus();
+                        match launch_osu(&osu_path, &devserver_host, &profile.launch_args).await {
+                            Ok(process) => {
+                                *state.osu_process.lock() = Some(process);
+                                tracing::info!("--launch-osu: osu! launched successfully");
+                            }
+                            Err(e) => {
+                                tracing::error!("--launch-osu: Failed to launch osu!: {}", e);
+                                if let Some(window) = app_handle.get_webview_window("main") {
+                                    let _ = window.show();
+                                    let _ = window.set_focus();
+                                }
                             }
-                        } else {
-                            tracing::info!("--launch-osu: osu! launched successfully");
                         }
                     } else {
                         tracing::error!("--launch-osu: osu! path not configured");
@@ -181,24 +228,51 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             get_config,
             set_config,
+            set_supporter_mode,
+            set_https_port,
+            set_osu_path,
+            add_profile,
+            remove_profile,
+            select_profile,
+            export_config,
+            import_config,
             load_saved_config,
             detect_osu,
             validate_osu_path,
+            detect_osu_variant,
             is_osu_running_cmd,
             get_status,
+            get_captured_packets,
+            reset_stats,
+            self_test,
+            preflight_check,
+            collect_diagnostics,
+            is_elevated,
+            check_port_available,
             start_proxy,
+            restart_proxy,
             connect,
             disconnect,
+            check_beatmap_available,
+            test_mirror,
+            simulate_route,
             hide_window,
             show_window,
             quit_app,
+            cleanup_now,
             get_logs,
             get_logs_since,
             get_latest_log_id,
             clear_logs,
+            get_active_log_filter,
             is_certificate_installed,
             install_certificate,
+            uninstall_certificate,
+            regenerate_certificate,
             get_certificate_path,
+            get_certificate_expiry,
+            get_certificate_fingerprint,
+            verify_certificate_sans,
             update_tray_status,
             create_launch_shortcut,
             check_shortcut_exists,
@@ -216,17 +290,28 @@ pub fn run() {
         })
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
-        .run(|app_handle, event| {
-            if let RunEvent::ExitRequested { api, .. } = event {
+        .run(|app_handle, event| match event {
+            RunEvent::ExitRequested { api, .. } => {
                 let state = app_handle.state::<TauriState>();
-                let proxy = state.proxy.read();
-                if proxy.is_some() {
+                if state.proxy_active() {
                     api.prevent_exit();
                     if let Some(window) = app_handle.get_webview_window("main") {
                         let _ = window.hide();
                     }
                 }
             }
+            // Only reached on a real quit, never when hiding to the tray
+            RunEvent::Exit => {
+                let state = app_handle.state::<TauriState>();
+                let config = state.config.read().clone();
+                if config.cleanup_on_quit && !state.proxy_active() {
+                    match cleanup_system(config.cleanup_certificate_on_quit) {
+                        Ok(report) => tracing::info!("Cleaned up on quit: {:?}", report),
+                        Err(e) => tracing::warn!("Cleanup on quit failed: {}", e),
+                    }
+                }
+            }
+            _ => {}
         });
 }
 
@@ -247,15 +332,20 @@ fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
             "quit" => {
-                let state = app.state::<TauriState>();
-                let proxy = state.proxy.write().take();
-                if let Some(mut pm) = proxy {
-                    // Use block_on to ensure proxy cleanup completes before exiting
-                    tauri::async_runtime::block_on(async move {
-                        let _ = pm.stop().await;
-                    });
+                // Stopping can take a few seconds to drain connections, so
+                // do it off the event loop and exit once it's done
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.hide();
                 }
-                app.exit(0);
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    let state = app.state::<TauriState>();
+                    let proxy = state.proxy.write().take();
+                    if let Some(mut pm) = proxy {
+                        let _ = pm.stop().await;
+                    }
+                    app.exit(0);
+                });
             }
             _ => {}
         })
@@ -1,5 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { AppConfig, AppState, LogEntry } from "$lib/types";
+import type { AppConfig, AppState, LogEntry, LogTail } from "$lib/types";
 import { defaultConfig, defaultState } from "$lib/types";
 
 const loadingOperations = $state(new Set<string>());
@@ -237,8 +237,11 @@ const MAX_LOG_ENTRIES = 1000;
 
 export async function getLogsSince(lastId: number): Promise<LogEntry[]> {
   try {
-    const newLogs = await invoke<LogEntry[]>("get_logs_since", { lastId });
-    if (newLogs.length > 0) {
+    const { entries: newLogs, missed } = await invoke<LogTail>("get_logs_since", { lastId });
+    if (missed > 0) {
+      // Entries were dropped in between, so start over from what's buffered
+      store.logs = newLogs;
+    } else if (newLogs.length > 0) {
       const combined = [...store.logs, ...newLogs];
       // Trim to max size, keeping most recent entries
       store.logs = combined.length > MAX_LOG_ENTRIES
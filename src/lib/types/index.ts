@@ -28,6 +28,12 @@ export interface LogEntry {
   message: string;
 }
 
+export interface LogTail {
+  entries: LogEntry[];
+  /** Newer entries dropped from the buffer before they were fetched. */
+  missed: number;
+}
+
 export interface AppState {
   status: ConnectionStatus;
   osu_running: boolean;